@@ -0,0 +1,160 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::{CodeIndex, Function};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    Class,
+    File,
+    Directory,
+    Package,
+}
+
+impl FromStr for Granularity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "class" => Ok(Granularity::Class),
+            "file" => Ok(Granularity::File),
+            "directory" | "dir" => Ok(Granularity::Directory),
+            "package" => Ok(Granularity::Package),
+            _ => Err(format!("unknown granularity {}", s)),
+        }
+    }
+}
+
+impl Granularity {
+    /// The group a function collapses into. Free functions have no class, so
+    /// in class mode they are grouped by their file, as are functions outside
+    /// any package in package mode.
+    pub(crate) fn group(&self, func: &Function) -> String {
+        match self {
+            Granularity::Class if !func.pkg.is_empty() => func.pkg.clone(),
+            Granularity::Package if !func.package.is_empty() => func.package.clone(),
+            Granularity::Class | Granularity::File | Granularity::Package => func.file.clone(),
+            Granularity::Directory => Path::new(&func.file)
+                .parent()
+                .map(|p| p.display().to_string())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AggregateNode {
+    pub name: String,
+    pub functions: usize,
+    /// Calls between functions of the same group.
+    pub internal_calls: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AggregateEdge {
+    pub source: String,
+    pub target: String,
+    /// Number of underlying call sites.
+    pub weight: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AggregateGraph {
+    pub nodes: Vec<AggregateNode>,
+    pub edges: Vec<AggregateEdge>,
+}
+
+impl CodeIndex {
+    pub fn aggregate(&self, by: Granularity) -> AggregateGraph {
+        let mut nodes: BTreeMap<String, AggregateNode> = BTreeMap::new();
+        for func in self.functions.values() {
+            let name = by.group(func);
+            nodes
+                .entry(name.clone())
+                .or_insert_with(|| AggregateNode {
+                    name,
+                    functions: 0,
+                    internal_calls: 0,
+                })
+                .functions += 1;
+        }
+
+        let mut edges: BTreeMap<(String, String), usize> = BTreeMap::new();
+        for (from, targets) in self.edges.iter() {
+            let source = match self.function_by_id(*from) {
+                Some(func) => by.group(func),
+                None => continue,
+            };
+            for to in targets {
+                let target = match self.function_by_id(*to) {
+                    Some(func) => by.group(func),
+                    None => continue,
+                };
+                if source == target {
+                    nodes.get_mut(&source).unwrap().internal_calls += 1;
+                } else {
+                    *edges.entry((source.clone(), target)).or_default() += 1;
+                }
+            }
+        }
+
+        AggregateGraph {
+            nodes: nodes.into_values().collect(),
+            edges: edges
+                .into_iter()
+                .map(|((source, target), weight)| AggregateEdge {
+                    source,
+                    target,
+                    weight,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aggregate() {
+        let mut index = CodeIndex::new();
+        index.parse_source(
+            "src/ui/view.ts",
+            "class View { render() { this.layout(); Store.get(); Store.get(); } layout() {} }",
+        );
+        index.parse_source(
+            "src/db/store.ts",
+            "class Store { get() { connect(); } }\nfunction connect() {}",
+        );
+
+        let classes = index.aggregate(Granularity::Class);
+        assert_eq!(classes.nodes.len(), 3);
+        assert_eq!(classes.nodes[1].name, "View");
+        assert_eq!(classes.nodes[1].internal_calls, 1);
+        assert_eq!(
+            classes.edges,
+            vec![
+                AggregateEdge {
+                    source: "Store".to_string(),
+                    target: "src/db/store.ts".to_string(),
+                    weight: 1,
+                },
+                AggregateEdge {
+                    source: "View".to_string(),
+                    target: "Store".to_string(),
+                    weight: 2,
+                },
+            ]
+        );
+
+        let dirs = index.aggregate(Granularity::Directory);
+        let names: Vec<_> = dirs.nodes.iter().map(|n| n.name.as_str()).collect();
+        assert_eq!(names, vec!["src/db", "src/ui"]);
+        assert_eq!(dirs.edges.len(), 1);
+        assert_eq!(dirs.edges[0].weight, 2);
+    }
+}
@@ -0,0 +1,396 @@
+//! What a call calls: a project function, something imported from a
+//! package, a JavaScript or Node.js builtin, or a name only known at run
+//! time. Graphs of real projects are dominated by the last three, so trees
+//! and exports can leave them out.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::export::Subgraph;
+use crate::graph::GraphNode;
+use crate::resolve::ImportMap;
+use crate::{CallSite, CodeIndex};
+
+/// Globals of JavaScript, the DOM and Node.js that are called directly or
+/// through their members, e.g. `console.log` or `JSON.parse`.
+const BUILTIN_GLOBALS: [&str; 46] = [
+    "Array",
+    "BigInt",
+    "Boolean",
+    "Buffer",
+    "Date",
+    "Error",
+    "Function",
+    "Intl",
+    "JSON",
+    "Map",
+    "Math",
+    "Number",
+    "Object",
+    "Promise",
+    "Proxy",
+    "Reflect",
+    "RegExp",
+    "Set",
+    "String",
+    "Symbol",
+    "WeakMap",
+    "WeakSet",
+    "atob",
+    "btoa",
+    "clearInterval",
+    "clearTimeout",
+    "console",
+    "decodeURIComponent",
+    "document",
+    "encodeURIComponent",
+    "fetch",
+    "globalThis",
+    "import",
+    "isFinite",
+    "isNaN",
+    "parseFloat",
+    "parseInt",
+    "process",
+    "queueMicrotask",
+    "require",
+    "setImmediate",
+    "setInterval",
+    "setTimeout",
+    "structuredClone",
+    "super",
+    "window",
+];
+
+/// Methods of arrays, strings and promises, called on values whose type the
+/// index does not know, e.g. `items.push` or `this.name.trim`. Names as
+/// common in application code as `get` or `add` are left out.
+const BUILTIN_METHODS: [&str; 41] = [
+    "at",
+    "catch",
+    "charAt",
+    "concat",
+    "endsWith",
+    "entries",
+    "every",
+    "fill",
+    "filter",
+    "finally",
+    "find",
+    "findIndex",
+    "flat",
+    "flatMap",
+    "forEach",
+    "hasOwnProperty",
+    "includes",
+    "indexOf",
+    "join",
+    "keys",
+    "lastIndexOf",
+    "map",
+    "padEnd",
+    "padStart",
+    "pop",
+    "push",
+    "reduce",
+    "replace",
+    "shift",
+    "slice",
+    "some",
+    "sort",
+    "splice",
+    "split",
+    "startsWith",
+    "substring",
+    "then",
+    "toLowerCase",
+    "toString",
+    "toUpperCase",
+    "trim",
+];
+
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum CallKind {
+    /// A function defined in the project.
+    Resolved,
+    /// Bound by an import of a package or Node.js module.
+    External,
+    /// A global such as `console.log`, or a standard method such as `push`.
+    Builtin,
+    /// Anything else, e.g. a method of an object of unknown type.
+    #[default]
+    Dynamic,
+}
+
+impl CallKind {
+    pub const ALL: [CallKind; 4] = [
+        CallKind::Resolved,
+        CallKind::External,
+        CallKind::Builtin,
+        CallKind::Dynamic,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CallKind::Resolved => "resolved",
+            CallKind::External => "external",
+            CallKind::Builtin => "builtin",
+            CallKind::Dynamic => "dynamic",
+        }
+    }
+
+    /// A comma separated list of kinds, e.g. `external,builtin`.
+    pub fn parse_list(list: &str) -> Result<Vec<CallKind>, String> {
+        list.split(',')
+            .map(|k| k.trim())
+            .filter(|k| !k.is_empty())
+            .map(|k| k.parse())
+            .collect()
+    }
+}
+
+impl FromStr for CallKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CallKind::ALL
+            .into_iter()
+            .find(|k| k.name() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown call kind {}, expected resolved, external, builtin or dynamic",
+                    s
+                )
+            })
+    }
+}
+
+impl fmt::Display for CallKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// The kind of a call of `callee`, as written, that a single file can tell:
+/// never `Resolved`, which depends on what the whole project defines.
+pub(crate) fn classify(callee: &str, imports: &ImportMap) -> CallKind {
+    let path = callee.replace("?.", ".");
+    let segments: Vec<&str> = path.split('.').collect();
+    let plain = segments.iter().all(|s| {
+        !s.is_empty()
+            && s.chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+    });
+    if !plain {
+        return CallKind::Dynamic;
+    }
+    if imports.is_external(segments[0]) {
+        return CallKind::External;
+    }
+    if BUILTIN_GLOBALS.contains(&segments[0])
+        || (segments.len() > 1 && BUILTIN_METHODS.contains(segments.last().unwrap()))
+    {
+        return CallKind::Builtin;
+    }
+    CallKind::Dynamic
+}
+
+/// Calls of one callee name.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CalleeCount {
+    pub name: String,
+    pub kind: CallKind,
+    pub calls: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct CalleeReport {
+    /// Call sites of each kind.
+    pub counts: BTreeMap<CallKind, usize>,
+    /// Callees, most called first.
+    pub callees: Vec<CalleeCount>,
+}
+
+impl CodeIndex {
+    /// The kind of a call: an import from a package stays `External` even
+    /// if the project defines a function of that name, otherwise a defined
+    /// callee is `Resolved`.
+    pub(crate) fn call_kind(&self, site: &CallSite) -> CallKind {
+        match site.kind {
+            CallKind::External => CallKind::External,
+            _ if self.function_by_id(site.callee).is_some() => CallKind::Resolved,
+            kind => kind,
+        }
+    }
+
+    /// Kinds of the calls of each callee. A name called in several ways has
+    /// the first of `CallKind::ALL` among them.
+    pub(crate) fn callee_kinds(&self) -> BTreeMap<u64, CallKind> {
+        let mut kinds: BTreeMap<u64, CallKind> = BTreeMap::new();
+        for site in self.call_sites.values().flatten() {
+            let kind = self.call_kind(site);
+            kinds
+                .entry(site.callee)
+                .and_modify(|k| *k = (*k).min(kind))
+                .or_insert(kind);
+        }
+        kinds
+    }
+
+    /// How many calls are of each kind, and the callees of the calls whose
+    /// kind is in `kinds`, all of them when it is empty.
+    pub fn callee_report(&self, kinds: &[CallKind]) -> CalleeReport {
+        let mut report = CalleeReport::default();
+        let mut calls: BTreeMap<(u64, CallKind), usize> = BTreeMap::new();
+        for site in self.call_sites.values().flatten() {
+            let kind = self.call_kind(site);
+            *report.counts.entry(kind).or_default() += 1;
+            if kinds.is_empty() || kinds.contains(&kind) {
+                *calls.entry((site.callee, kind)).or_default() += 1;
+            }
+        }
+        report.callees = calls
+            .into_iter()
+            .map(|((callee, kind), calls)| CalleeCount {
+                name: self.id_gen.name(callee).cloned().unwrap_or_default(),
+                kind,
+                calls,
+            })
+            .collect();
+        report
+            .callees
+            .sort_by(|a, b| b.calls.cmp(&a.calls).then_with(|| a.name.cmp(&b.name)));
+        report
+    }
+
+    /// Takes the callees whose calls are all of one of `kinds` out of
+    /// `graph`, with their edges. Project functions are always kept.
+    pub fn hide_callees(&self, graph: &mut Subgraph, kinds: &[CallKind]) {
+        if kinds.is_empty() {
+            return;
+        }
+        let callee_kinds = self.callee_kinds();
+        let hidden: BTreeSet<String> = graph
+            .nodes
+            .iter()
+            .filter(|n| !n.defined())
+            .filter(|n| {
+                self.id_gen
+                    .get(&n.name)
+                    .and_then(|id| callee_kinds.get(&id))
+                    .is_some_and(|k| kinds.contains(k))
+            })
+            .map(|n| n.name.clone())
+            .collect();
+        graph.nodes.retain(|n| !hidden.contains(&n.name));
+        graph
+            .edges
+            .retain(|e| !hidden.contains(&e.source) && !hidden.contains(&e.target));
+    }
+
+    /// `serde_tree` without the calls of one of `kinds`.
+    pub fn serde_tree_without(
+        &mut self,
+        funcname: &str,
+        depth: i32,
+        kinds: &[CallKind],
+    ) -> Option<GraphNode> {
+        let id = self.id_gen.id(funcname);
+        self.tree_without(id, depth, kinds)
+    }
+
+    fn tree_without(&self, id: u64, depth: i32, kinds: &[CallKind]) -> Option<GraphNode> {
+        if depth == 0 {
+            return None;
+        }
+        let sites: Vec<&CallSite> = self
+            .call_sites
+            .get(&id)
+            .into_iter()
+            .flatten()
+            .filter(|site| !kinds.contains(&self.call_kind(site)))
+            .collect();
+        Some(GraphNode {
+            name: self.id_gen.name(id).cloned().unwrap_or("nil".to_string()),
+            value: sites.len(),
+            children: sites
+                .iter()
+                .filter_map(|site| self.tree_without(site.callee, depth - 1, kinds))
+                .collect(),
+            metric: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::Selection;
+
+    const SOURCE: &str = r#"import { format } from "date-fns";
+import * as fs from "node:fs";
+class Store {
+  save(items) {
+    items.push(1);
+    this.flush();
+    fs.writeFileSync("x", format(new Date()));
+  }
+  flush() { console.log("flushed"); this.queue.drain(); }
+}
+function format() {}
+"#;
+
+    #[test]
+    fn test_classify_callees() {
+        let mut index = CodeIndex::new();
+        index.parse_source("store.ts", SOURCE);
+        let mut kinds: Vec<(String, CallKind)> = index.call_sites
+            [&index.id_gen.get("Store.save").unwrap()]
+            .iter()
+            .map(|site| {
+                let name = index.id_gen.name(site.callee).unwrap().clone();
+                (name, index.call_kind(site))
+            })
+            .collect();
+        kinds.sort();
+        assert_eq!(
+            kinds,
+            vec![
+                ("Store.flush".to_string(), CallKind::Resolved),
+                ("format".to_string(), CallKind::External),
+                ("fs.writeFileSync".to_string(), CallKind::External),
+                ("items.push".to_string(), CallKind::Builtin),
+            ]
+        );
+
+        let report = index.callee_report(&[CallKind::Dynamic]);
+        assert_eq!(report.counts[&CallKind::Builtin], 2);
+        assert_eq!(report.counts[&CallKind::External], 2);
+        assert_eq!(report.callees.len(), 1);
+        assert_eq!(report.callees[0].name, "Store.queue.drain");
+
+        let hide = [CallKind::External, CallKind::Builtin];
+        let tree = index.serde_tree_without("Store.save", 3, &hide).unwrap();
+        assert_eq!(tree.value, 1);
+        assert_eq!(tree.children[0].name, "Store.flush");
+        assert_eq!(tree.children[0].children[0].name, "Store.queue.drain");
+
+        let mut graph = index.subgraph(&Selection::All, None).unwrap();
+        index.hide_callees(&mut graph, &hide);
+        let names: Vec<&str> = graph.nodes.iter().map(|n| n.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["Store.flush", "Store.save", "format", "Store.queue.drain"]
+        );
+        assert_eq!(
+            CallKind::parse_list("external, builtin").unwrap(),
+            hide.to_vec()
+        );
+        assert!(CallKind::parse_list("extern").is_err());
+    }
+}
@@ -0,0 +1,204 @@
+//! `graphgen.toml` at the project root. Every key is optional, and command
+//! line flags override what the file sets.
+//!
+//! ```toml
+//! languages = ["typescript"]
+//! depth = 5
+//! listen_addr = "127.0.0.1:8080"
+//! index = ".graphgen/index.bin"
+//! roots = ["../shared"]
+//!
+//! [files]
+//! exclude = ["src/legacy/**"]
+//! max_file_size = 524288
+//!
+//! [entry_points]
+//! tests = false
+//! patterns = ["handle*"]
+//!
+//! [paths]
+//! "@app/*" = ["src/app/*"]
+//!
+//! [[rule]]
+//! name = "ui must not reach into db"
+//! from = "src/ui/**"
+//! to = "src/db/**"
+//! ```
+
+use glob::Pattern;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::deadcode::EntryPoints;
+use crate::filter::FileFilter;
+use crate::rules::{Rule, RuleSet};
+
+pub const CONFIG_FILE: &str = "graphgen.toml";
+pub const DEFAULT_DEPTH: i32 = 4;
+const LANGUAGES: [&str; 1] = ["typescript"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub languages: Vec<String>,
+    /// Depth of call trees when a request or command does not give one.
+    pub depth: i32,
+    pub listen_addr: Option<String>,
+    /// Where `save` writes the index.
+    pub index: Option<String>,
+    /// Further directories indexed along with the project directory, each
+    /// one a package of its own unless its package.json names it. Relative
+    /// ones are resolved against the directory of the file.
+    pub roots: Vec<String>,
+    pub files: FileFilter,
+    pub entry_points: EntryPoints,
+    /// Import path aliases, as in tsconfig's `compilerOptions.paths`, with
+    /// targets relative to the project directory. They win over tsconfig's.
+    pub paths: BTreeMap<String, Vec<String>>,
+    #[serde(rename = "rule")]
+    pub rules: Vec<Rule>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            languages: LANGUAGES.iter().map(|l| l.to_string()).collect(),
+            depth: DEFAULT_DEPTH,
+            listen_addr: None,
+            index: None,
+            roots: vec![],
+            files: FileFilter::default(),
+            entry_points: EntryPoints::default(),
+            paths: BTreeMap::new(),
+            rules: vec![],
+        }
+    }
+}
+
+impl Config {
+    pub fn from_toml(text: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(text)
+    }
+
+    /// Reads `file`, or returns the defaults when it does not exist.
+    pub fn load(file: &Path) -> Result<Self, String> {
+        match std::fs::read_to_string(file) {
+            Ok(text) => {
+                let mut config =
+                    Config::from_toml(&text).map_err(|e| format!("{}: {}", file.display(), e))?;
+                let dir = file.parent().unwrap_or(Path::new(""));
+                if !dir.as_os_str().is_empty() && dir != Path::new(".") {
+                    for root in config.roots.iter_mut() {
+                        *root = dir.join(&root).display().to_string();
+                    }
+                }
+                Ok(config)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(format!("{}: {}", file.display(), e)),
+        }
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string_pretty(self).unwrap()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for language in self.languages.iter() {
+            if !LANGUAGES.contains(&language.to_lowercase().as_str()) {
+                return Err(format!(
+                    "unsupported language {}, expected one of {}",
+                    language,
+                    LANGUAGES.join(", ")
+                ));
+            }
+        }
+        if self.depth < 1 {
+            return Err(format!("depth must be at least 1, got {}", self.depth));
+        }
+        self.files
+            .validate()
+            .map_err(|e| format!("invalid files pattern: {}", e))?;
+        for p in self.entry_points.patterns.iter() {
+            Pattern::new(p).map_err(|e| format!("invalid entry point {}: {}", p, e))?;
+        }
+        self.rule_set().validate()
+    }
+
+    pub fn rule_set(&self) -> RuleSet {
+        RuleSet {
+            rules: self.rules.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config() {
+        let config = Config::from_toml(
+            r#"
+            depth = 6
+            index = "out/index.bin"
+            roots = ["../shared"]
+
+            [files]
+            exclude = ["src/legacy/**"]
+            gitignore = false
+
+            [entry_points]
+            tests = false
+            patterns = ["handle*"]
+
+            [paths]
+            "@app/*" = ["src/app/*"]
+
+            [[rule]]
+            name = "no db from ui"
+            from = "src/ui/**"
+            to = "src/db/**"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.depth, 6);
+        assert_eq!(config.languages, vec!["typescript"]);
+        assert_eq!(config.roots, vec!["../shared"]);
+        assert_eq!(config.files.exclude, vec!["src/legacy/**"]);
+        assert!(!config.files.gitignore);
+        assert!(config.files.skip_generated);
+        assert!(!config.entry_points.tests && config.entry_points.exported);
+        assert_eq!(config.paths["@app/*"], vec!["src/app/*"]);
+        assert_eq!(
+            config.rule_set().rules[0].from.as_deref(),
+            Some("src/ui/**")
+        );
+        assert!(config.validate().is_ok());
+        assert_eq!(Config::from_toml(&config.to_toml()).unwrap(), config);
+
+        assert!(Config::from_toml("dpeth = 3").is_err());
+        let python = Config::from_toml(r#"languages = ["python"]"#).unwrap();
+        assert!(python.validate().unwrap_err().contains("python"));
+        let rule = Config::from_toml("[[rule]]\nname = \"all\"").unwrap();
+        assert!(rule.validate().is_err());
+    }
+
+    #[test]
+    fn test_load_resolves_roots() {
+        let dir = std::env::temp_dir().join(format!("graphgen-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join(CONFIG_FILE);
+        std::fs::write(&file, "roots = [\"../shared\", \"/opt/lib\"]").unwrap();
+        let config = Config::load(&file).unwrap();
+        assert_eq!(
+            config.roots,
+            vec![
+                dir.join("../shared").display().to_string(),
+                "/opt/lib".to_string()
+            ]
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
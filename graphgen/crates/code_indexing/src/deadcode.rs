@@ -0,0 +1,256 @@
+use glob::{Pattern, PatternError};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use crate::{CodeIndex, Function};
+
+/// Which functions count as roots when computing reachability.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct EntryPoints {
+    pub exported: bool,
+    pub main: bool,
+    pub routes: bool,
+    pub tests: bool,
+    /// Glob patterns matched against qualified names, e.g. `Parser.parse*`.
+    pub patterns: Vec<String>,
+}
+
+impl Default for EntryPoints {
+    fn default() -> Self {
+        EntryPoints {
+            exported: true,
+            main: true,
+            routes: true,
+            tests: true,
+            patterns: vec![],
+        }
+    }
+}
+
+/// How sure we are that an unreachable function is really unused.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Confidence {
+    Low,
+    Medium,
+    High,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeadFunction {
+    pub name: String,
+    pub line: usize,
+    pub confidence: Confidence,
+    pub reason: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct DeadCodeReport {
+    pub entries: usize,
+    pub reachable: usize,
+    /// Unreachable functions grouped by file, in line order.
+    pub files: BTreeMap<String, Vec<DeadFunction>>,
+}
+
+impl DeadCodeReport {
+    pub fn total(&self) -> usize {
+        self.files.values().map(|v| v.len()).sum()
+    }
+}
+
+impl CodeIndex {
+    pub fn dead_code(&self, entries: &EntryPoints) -> Result<DeadCodeReport, PatternError> {
+        let patterns = entries
+            .patterns
+            .iter()
+            .map(|p| Pattern::new(p))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let roots: Vec<u64> = self
+            .functions
+            .iter()
+            .filter(|(key, func)| {
+                (entries.exported && func.exported)
+                    || (entries.main && func.name == "main")
+                    || (entries.tests && func.test)
+                    || (entries.routes && self.is_route(key))
+                    || patterns.iter().any(|p| p.matches(key))
+            })
+            .filter_map(|(key, _)| self.id_gen.get(key))
+            .collect();
+        let reachable = self.reachable_from(&roots);
+
+        // Evidence gathered from live code that static resolution may have missed.
+        let mut dynamic_files = BTreeSet::new();
+        let mut unresolved_members = BTreeSet::new();
+        for caller in reachable.iter() {
+            let targets = self
+                .edges
+                .get(caller)
+                .into_iter()
+                .chain(self.refs.get(caller))
+                .flatten();
+            for target in targets {
+                let name = match self.id_gen.name(*target) {
+                    Some(name) if !self.functions.contains_key(name) => name,
+                    _ => continue,
+                };
+                if name.contains('[') {
+                    if let Some(func) = self.function_by_id(*caller) {
+                        dynamic_files.insert(func.file.clone());
+                    }
+                } else if let Some((_, member)) = name.rsplit_once('.') {
+                    unresolved_members.insert(member.to_string());
+                }
+            }
+        }
+
+        let mut report = DeadCodeReport {
+            entries: roots.len(),
+            reachable: 0,
+            files: BTreeMap::new(),
+        };
+        for (key, func) in self.functions.iter() {
+            let live = self
+                .id_gen
+                .get(key)
+                .is_some_and(|id| reachable.contains(&id));
+            if live {
+                report.reachable += 1;
+                continue;
+            }
+            let (confidence, reason) = if unresolved_members.contains(&func.name) {
+                (
+                    Confidence::Low,
+                    format!("`.{}` is called on an unresolved receiver", func.name),
+                )
+            } else if dynamic_files.contains(&func.file) {
+                (
+                    Confidence::Medium,
+                    "file contains computed calls from live code".to_string(),
+                )
+            } else {
+                (Confidence::High, "no calls or references".to_string())
+            };
+            report
+                .files
+                .entry(func.file.clone())
+                .or_default()
+                .push(DeadFunction {
+                    name: key.clone(),
+                    line: func.line,
+                    confidence,
+                    reason,
+                });
+        }
+        for funcs in report.files.values_mut() {
+            funcs.sort_by_key(|f| f.line);
+        }
+        Ok(report)
+    }
+
+    fn is_route(&self, key: &str) -> bool {
+        self.id_gen
+            .get(key)
+            .is_some_and(|id| self.routes.contains(&id))
+    }
+
+    pub(crate) fn function_by_id(&self, id: u64) -> Option<&Function> {
+        self.id_gen
+            .name(id)
+            .and_then(|name| self.functions.get(name))
+    }
+
+    /// Ids reachable from `roots` through call and reference edges.
+    pub(crate) fn reachable_from(&self, roots: &[u64]) -> BTreeSet<u64> {
+        let mut visited: BTreeSet<u64> = roots.iter().copied().collect();
+        let mut queue: VecDeque<u64> = roots.iter().copied().collect();
+        while let Some(id) = queue.pop_front() {
+            let targets = self
+                .edges
+                .get(&id)
+                .into_iter()
+                .chain(self.refs.get(&id))
+                .flatten();
+            for target in targets {
+                if visited.insert(*target) {
+                    queue.push_back(*target);
+                }
+            }
+        }
+        visited
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"
+export function main() {
+    helper();
+    run(callback);
+}
+function helper() {}
+function callback() {}
+function unused() { orphan(); }
+function orphan() {}
+class Widget {
+    draw() { this.paint(); }
+    paint() {}
+    resize() {}
+}
+function serve(app) {
+    app.get("/widgets", listWidgets);
+}
+function listWidgets() { obj.resize(); }
+"#;
+
+    fn index() -> CodeIndex {
+        let mut index = CodeIndex::new();
+        index.parse_source("src/app.ts", SOURCE);
+        index
+    }
+
+    fn dead_names(report: &DeadCodeReport) -> Vec<String> {
+        report.files["src/app.ts"]
+            .iter()
+            .map(|f| f.name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_dead_code_from_exports() {
+        let report = index().dead_code(&EntryPoints::default()).unwrap();
+        assert_eq!(
+            dead_names(&report),
+            vec![
+                "unused",
+                "orphan",
+                "Widget.draw",
+                "Widget.paint",
+                "Widget.resize",
+                "serve"
+            ]
+        );
+        let funcs = &report.files["src/app.ts"];
+        assert_eq!(funcs[0].confidence, Confidence::High);
+        // listWidgets is live as a route handler and calls `obj.resize()`.
+        assert_eq!(funcs[4].confidence, Confidence::Low);
+    }
+
+    #[test]
+    fn test_dead_code_patterns_and_routes() {
+        let entries = EntryPoints {
+            patterns: vec!["serve".to_string(), "Widget.d*".to_string()],
+            ..EntryPoints::default()
+        };
+        let report = index().dead_code(&entries).unwrap();
+        assert_eq!(
+            dead_names(&report),
+            vec!["unused", "orphan", "Widget.resize"]
+        );
+        assert_eq!(report.entries, 4);
+    }
+}
@@ -0,0 +1,317 @@
+//! Problems met while indexing, per file: files that could not be read,
+//! source tree-sitter only parsed by skipping over it, declarations left
+//! out of the index, and calls the index cannot tell anything about.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use tree_sitter::Node;
+
+use crate::callees::CallKind;
+use crate::misc::{node_str, span};
+use crate::{CodeIndex, Span};
+
+/// Longest excerpt of the offending source quoted in a message.
+const EXCERPT_CHARS: usize = 40;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticKind {
+    /// The file could not be read and is not indexed.
+    Io,
+    /// The file is not UTF-8 and is not indexed.
+    InvalidUtf8,
+    /// Source the parser skipped; the rest of the file is indexed.
+    Syntax,
+    /// A declaration left out of the index, e.g. one without a name.
+    Unsupported,
+}
+
+impl DiagnosticKind {
+    /// Whether the whole file is missing from the index.
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, DiagnosticKind::Io | DiagnosticKind::InvalidUtf8)
+    }
+}
+
+impl fmt::Display for DiagnosticKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DiagnosticKind::Io => write!(f, "I/O error"),
+            DiagnosticKind::InvalidUtf8 => write!(f, "invalid UTF-8"),
+            DiagnosticKind::Syntax => write!(f, "syntax error"),
+            DiagnosticKind::Unsupported => write!(f, "unsupported"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    /// Where it starts, line 0 when it concerns the whole file.
+    pub span: Span,
+    pub end_line: usize,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn at(kind: DiagnosticKind, node: Node, content: &str, message: String) -> Self {
+        Diagnostic {
+            kind,
+            span: span(node, content),
+            end_line: node.end_position().row + 1,
+            message,
+        }
+    }
+}
+
+/// The diagnostics of one file, by position.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FileDiagnostics {
+    /// Relative to the root the file is in.
+    pub file: String,
+    pub diagnostics: Vec<Diagnostic>,
+    /// Call sites in the functions of this file of `CallKind::Dynamic`:
+    /// neither project functions, imports from packages nor builtins.
+    pub unresolved_callees: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct HealthReport {
+    pub indexed_files: usize,
+    /// Files with an I/O or UTF-8 error, left out of the index.
+    pub unreadable_files: usize,
+    /// Indexed files with syntax errors or unsupported declarations.
+    pub files_with_errors: usize,
+    pub call_sites: usize,
+    pub unresolved_callees: usize,
+    /// Files with diagnostics or unresolved callees, by path.
+    pub files: Vec<FileDiagnostics>,
+}
+
+/// Reads a source file, `Ok(None)` when it does not exist.
+pub(crate) fn read_source(path: &str) -> Result<Option<String>, Diagnostic> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(Diagnostic {
+                kind: DiagnosticKind::Io,
+                span: Span::default(),
+                end_line: 0,
+                message: e.to_string(),
+            })
+        }
+    };
+    String::from_utf8(bytes).map(Some).map_err(|e| {
+        let offset = e.utf8_error().valid_up_to();
+        let line = e.as_bytes()[..offset]
+            .iter()
+            .filter(|b| **b == b'\n')
+            .count()
+            + 1;
+        Diagnostic {
+            kind: DiagnosticKind::InvalidUtf8,
+            span: Span {
+                line,
+                ..Span::default()
+            },
+            end_line: line,
+            message: format!("first invalid byte at offset {}", offset),
+        }
+    })
+}
+
+/// The outermost `ERROR` nodes of a tree, and the tokens the parser assumed
+/// to recover, e.g. a missing `}`. A file that did not parse as a program
+/// at all is one error if nothing inside it is.
+pub(crate) fn syntax_errors(root: Node, content: &str) -> Vec<Diagnostic> {
+    let mut result = vec![];
+    let mut queue = vec![root];
+    let mut cursor = root.walk();
+    while let Some(node) = queue.pop() {
+        if !node.has_error() {
+            continue;
+        }
+        for child in node.children(&mut cursor) {
+            if child.is_error() {
+                let text = node_str(child, content).unwrap_or_default();
+                let message = format!("unexpected `{}`", excerpt(&text));
+                result.push(Diagnostic::at(
+                    DiagnosticKind::Syntax,
+                    child,
+                    content,
+                    message,
+                ));
+            } else if child.is_missing() {
+                let message = format!("missing `{}`", child.kind());
+                result.push(Diagnostic::at(
+                    DiagnosticKind::Syntax,
+                    child,
+                    content,
+                    message,
+                ));
+            } else {
+                queue.push(child);
+            }
+        }
+    }
+    if result.is_empty() && root.is_error() {
+        let text = node_str(root, content).unwrap_or_default();
+        let message = format!("unexpected `{}`", excerpt(&text));
+        result.push(Diagnostic::at(
+            DiagnosticKind::Syntax,
+            root,
+            content,
+            message,
+        ));
+    }
+    result.sort_by_key(|d| (d.span.line, d.span.column));
+    result
+}
+
+/// The first line of `text`, shortened to `EXCERPT_CHARS`.
+fn excerpt(text: &str) -> String {
+    let line = text.lines().next().unwrap_or_default().trim();
+    match line.char_indices().nth(EXCERPT_CHARS) {
+        Some((end, _)) => format!("{}...", &line[..end]),
+        None if line.len() < text.trim().len() => format!("{}...", line),
+        None => line.to_string(),
+    }
+}
+
+impl CodeIndex {
+    /// A declaration of `filename` the parser leaves out.
+    pub(crate) fn unsupported(&mut self, filename: &str, node: Node, content: &str, what: &str) {
+        let diagnostic = Diagnostic::at(
+            DiagnosticKind::Unsupported,
+            node,
+            content,
+            format!("{} without a name or body, not indexed", what),
+        );
+        self.diagnostics
+            .entry(filename.to_string())
+            .or_default()
+            .push(diagnostic);
+    }
+
+    /// Diagnostics of every file, and how many calls are dynamic.
+    pub fn diagnostics(&self) -> HealthReport {
+        let mut report = HealthReport {
+            indexed_files: self.files.len(),
+            ..HealthReport::default()
+        };
+        let mut unresolved: BTreeMap<&str, usize> = BTreeMap::new();
+        for (from, sites) in self.call_sites.iter() {
+            let caller = match self.function_by_id(*from) {
+                Some(caller) => caller,
+                None => continue,
+            };
+            for site in sites {
+                report.call_sites += 1;
+                if self.call_kind(site) == CallKind::Dynamic {
+                    report.unresolved_callees += 1;
+                    *unresolved.entry(&caller.file).or_default() += 1;
+                }
+            }
+        }
+        let files: BTreeSet<&str> = self
+            .diagnostics
+            .keys()
+            .map(|f| f.as_str())
+            .chain(unresolved.keys().copied())
+            .collect();
+        for file in files {
+            let diagnostics = self.diagnostics.get(file).cloned().unwrap_or_default();
+            if diagnostics.iter().any(|d| d.kind.is_fatal()) {
+                report.unreadable_files += 1;
+            } else if !diagnostics.is_empty() {
+                report.files_with_errors += 1;
+            }
+            report.files.push(FileDiagnostics {
+                file: self.relative_path(file).to_string(),
+                diagnostics,
+                unresolved_callees: unresolved.get(file).copied().unwrap_or_default(),
+            });
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diagnostics() {
+        let dir = std::env::temp_dir().join(format!("graphgen-diagnostics-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, content: &[u8]| std::fs::write(dir.join(name), content).unwrap();
+        write(
+            "a.ts",
+            b"function a() { b(); console.log(1); obj.run(); }\nfunction b() {}",
+        );
+        write(
+            "broken.ts",
+            b"function ok() { a(; }\nfunction after() { ok(); }",
+        );
+        write("latin1.ts", b"function c() {}\n// caf\xe9");
+        let root = dir.display().to_string();
+
+        let mut index = CodeIndex::new();
+        let stats = index.parse_project(&root).unwrap();
+        assert_eq!((stats.added, stats.failed), (2, 1));
+        // the rest of a file with syntax errors is still indexed.
+        assert!(index.functions.contains_key("ok"));
+        assert!(index.functions.contains_key("after"));
+        assert!(!index.functions.contains_key("c"));
+
+        let report = index.diagnostics();
+        assert_eq!(report.indexed_files, 2);
+        assert_eq!(report.unreadable_files, 1);
+        assert_eq!(report.files_with_errors, 1);
+        assert_eq!((report.call_sites, report.unresolved_callees), (5, 1));
+        let files: Vec<&str> = report.files.iter().map(|f| f.file.as_str()).collect();
+        assert_eq!(files, vec!["a.ts", "broken.ts", "latin1.ts"]);
+        // `console.log` is a builtin, `obj.run` is not known.
+        assert_eq!(report.files[0].unresolved_callees, 1);
+        let broken = &report.files[1].diagnostics;
+        assert!(!broken.is_empty());
+        assert!(broken
+            .iter()
+            .all(|d| d.kind == DiagnosticKind::Syntax && d.span.line == 1));
+        let latin1 = &report.files[2].diagnostics[0];
+        assert_eq!(latin1.kind, DiagnosticKind::InvalidUtf8);
+        assert_eq!(latin1.span.line, 2);
+
+        // fixed files lose their diagnostics, unchanged ones keep them.
+        write("latin1.ts", b"function c() {}");
+        let stats = index.reindex();
+        assert_eq!((stats.added, stats.failed), (1, 0));
+        let report = index.diagnostics();
+        assert_eq!((report.unreadable_files, report.files_with_errors), (0, 1));
+        std::fs::remove_file(dir.join("broken.ts")).unwrap();
+        index.reindex();
+        assert_eq!(index.diagnostics().files_with_errors, 0);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_unsupported_declarations() {
+        let mut index = CodeIndex::new();
+        // error recovery leaves a method without a name.
+        index.parse_source("a.ts", "class A { (a) { x(); } }\nfunction b() {}");
+        assert_eq!(index.function_list(), vec!["b"]);
+        let kinds: Vec<DiagnosticKind> = index.diagnostics["a.ts"].iter().map(|d| d.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![DiagnosticKind::Syntax, DiagnosticKind::Unsupported]
+        );
+        // nothing of this file parsed as a statement.
+        index.parse_source("b.ts", "function 1 {");
+        assert_eq!(index.diagnostics["b.ts"][0].kind, DiagnosticKind::Syntax);
+        assert_eq!(excerpt("abc\ndef"), "abc...");
+        assert_eq!(excerpt(&"x".repeat(50)), format!("{}...", "x".repeat(40)));
+    }
+}
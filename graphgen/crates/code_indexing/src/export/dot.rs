@@ -0,0 +1,141 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::str::FromStr;
+
+use super::{EdgeKind, ExportNode, Subgraph};
+
+/// How nodes are grouped into `subgraph cluster_*` blocks.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Cluster {
+    None,
+    Class,
+    File,
+    Package,
+}
+
+impl FromStr for Cluster {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Cluster::None),
+            "class" => Ok(Cluster::Class),
+            "file" => Ok(Cluster::File),
+            "package" => Ok(Cluster::Package),
+            _ => Err(format!("unknown cluster mode {}", s)),
+        }
+    }
+}
+
+impl Cluster {
+    fn key(&self, node: &ExportNode) -> Option<String> {
+        if !node.defined() {
+            return None;
+        }
+        match self {
+            Cluster::None => None,
+            Cluster::Class if !node.class.is_empty() => Some(node.class.clone()),
+            Cluster::Package if !node.package.is_empty() => Some(node.package.clone()),
+            Cluster::Class | Cluster::File | Cluster::Package => Some(node.file.clone()),
+        }
+    }
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn node_line(node: &ExportNode) -> String {
+    let mut attrs = vec![format!("label={}", quote(&node.name))];
+    if node.defined() {
+        attrs.push(format!("file={}", quote(&node.file)));
+        attrs.push(format!("line={}", node.line));
+        attrs.push(format!(
+            "tooltip={}",
+            quote(&format!("{}:{}", node.file, node.line))
+        ));
+        if !node.class.is_empty() {
+            attrs.push(format!("class={}", quote(&node.class)));
+        }
+    } else {
+        attrs.push("style=dashed".to_string());
+    }
+    if let Some(m) = &node.metrics {
+        attrs.push(format!("fan_in={}", m.fan_in));
+        attrs.push(format!("fan_out={}", m.fan_out));
+        attrs.push(format!("calls={}", m.calls));
+        attrs.push(format!("reach={}", m.reach));
+        attrs.push(format!("betweenness={:.3}", m.betweenness));
+        attrs.push(format!("pagerank={:.6}", m.pagerank));
+    }
+    format!("{} [{}];", quote(&node.name), attrs.join(", "))
+}
+
+/// Renders `graph` in Graphviz DOT, ready for `dot -Tsvg`.
+pub fn to_dot(graph: &Subgraph, cluster: Cluster) -> String {
+    let mut out = String::new();
+    out += "digraph callgraph {\n";
+    out += "    rankdir=LR;\n";
+    out += "    node [shape=box, fontname=\"Helvetica\"];\n";
+    out += "    edge [fontname=\"Helvetica\", fontsize=10];\n";
+
+    let mut clusters: BTreeMap<String, Vec<&ExportNode>> = BTreeMap::new();
+    for node in graph.nodes.iter() {
+        match cluster.key(node) {
+            Some(key) => clusters.entry(key).or_default().push(node),
+            None => {
+                let _ = writeln!(out, "    {}", node_line(node));
+            }
+        }
+    }
+    for (i, (key, nodes)) in clusters.iter().enumerate() {
+        let _ = writeln!(out, "    subgraph cluster_{} {{", i);
+        let _ = writeln!(out, "        label={};", quote(key));
+        for node in nodes {
+            let _ = writeln!(out, "        {}", node_line(node));
+        }
+        out += "    }\n";
+    }
+    for edge in graph.edges.iter() {
+        let style = match edge.kind {
+            EdgeKind::Call => "",
+            EdgeKind::Reference => ", style=dotted",
+        };
+        let _ = writeln!(
+            out,
+            "    {} -> {} [label=\"{}\"{}];",
+            quote(&edge.source),
+            quote(&edge.target),
+            edge.count,
+            style
+        );
+    }
+    out += "}\n";
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::Selection;
+    use crate::CodeIndex;
+
+    #[test]
+    fn test_to_dot() {
+        let mut index = CodeIndex::new();
+        index.parse_source(
+            "a.ts",
+            "class A { run() { this.step(); this.step(); help(\"x\"); } step() {} }\nfunction help(s) {}",
+        );
+        let metrics = index.metrics();
+        let graph = index.subgraph(&Selection::All, Some(&metrics)).unwrap();
+        let dot = to_dot(&graph, Cluster::Class);
+        assert!(dot.starts_with("digraph callgraph {\n"));
+        assert!(dot.contains("    subgraph cluster_0 {\n        label=\"A\";\n"));
+        assert!(dot.contains("\"A.run\" -> \"A.step\" [label=\"2\"];"));
+        assert!(dot.contains("label=\"a.ts\""));
+        assert!(dot.contains("fan_in=1"));
+    }
+}
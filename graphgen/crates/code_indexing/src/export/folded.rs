@@ -0,0 +1,277 @@
+//! Brendan Gregg's folded stack format (`main;parse;lex 3`) built from static
+//! call paths, for flamegraph.pl, inferno and speedscope.
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use crate::callees::CallKind;
+use crate::metrics::{Metric, Metrics};
+use crate::CodeIndex;
+
+/// Stops enumerating paths past this many, stacks of no weight included, as
+/// the number of paths grows exponentially with depth.
+pub const MAX_STACKS: usize = 100_000;
+
+/// Fractional metrics are multiplied by this before rounding, since folded
+/// stack weights are integers.
+const METRIC_SCALE: f64 = 1000.0;
+
+/// What each stack, i.e. each function on each path, weighs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FoldedWeight {
+    /// 1, so a frame is as wide as the number of paths through it.
+    Paths,
+    /// The number of call sites along the path, multiplied out.
+    Calls,
+    /// The metric of the last function on the path.
+    Metric(Metric),
+}
+
+impl FromStr for FoldedWeight {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "paths" => Ok(FoldedWeight::Paths),
+            "calls" => Ok(FoldedWeight::Calls),
+            _ => s
+                .parse::<Metric>()
+                .map(FoldedWeight::Metric)
+                .map_err(|_| format!("unknown weight {}, expected paths, calls or a metric", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FoldedStacks {
+    pub stacks: Vec<(String, u64)>,
+    /// Set when `MAX_STACKS` paths were visited.
+    pub truncated: bool,
+}
+
+impl FoldedStacks {
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for (stack, weight) in self.stacks.iter() {
+            out += &format!("{} {}\n", stack, weight);
+        }
+        out
+    }
+}
+
+/// Frame names may not contain the stack separator or a line break.
+fn frame(name: &str) -> String {
+    name.replace(';', ":").replace(['\n', '\r'], " ")
+}
+
+fn metric_weight(metrics: &Metrics, metric: Metric, name: &str) -> u64 {
+    let value = metrics
+        .functions
+        .get(name)
+        .map(|m| m.get(metric))
+        .unwrap_or(0.0);
+    match metric {
+        Metric::Betweenness | Metric::PageRank => (value * METRIC_SCALE).round() as u64,
+        _ => value.round() as u64,
+    }
+}
+
+struct Walk<'a> {
+    index: &'a CodeIndex,
+    weight: FoldedWeight,
+    metrics: Option<&'a Metrics>,
+    hidden: &'a [CallKind],
+    path: Vec<u64>,
+    visited: usize,
+    out: FoldedStacks,
+}
+
+impl Walk<'_> {
+    fn visit(&mut self, id: u64, multiplicity: u64, depth: i32) {
+        if self.visited >= MAX_STACKS {
+            self.out.truncated = true;
+            return;
+        }
+        self.visited += 1;
+        let name = self.index.id_gen.name(id).cloned().unwrap_or_default();
+        self.path.push(id);
+        let weight = match (self.weight, self.metrics) {
+            (FoldedWeight::Paths, _) => 1,
+            (FoldedWeight::Calls, _) => multiplicity,
+            (FoldedWeight::Metric(metric), Some(metrics)) => metric_weight(metrics, metric, &name),
+            (FoldedWeight::Metric(_), None) => 0,
+        };
+        if weight > 0 {
+            let stack: Vec<String> = self
+                .path
+                .iter()
+                .map(|id| {
+                    frame(
+                        self.index
+                            .id_gen
+                            .name(*id)
+                            .map(|s| s.as_str())
+                            .unwrap_or(""),
+                    )
+                })
+                .collect();
+            self.out.stacks.push((stack.join(";"), weight));
+        }
+
+        if depth > 1 {
+            let mut children: BTreeMap<&String, (u64, u64)> = BTreeMap::new();
+            let sites = self.index.call_sites.get(&id).into_iter().flatten();
+            for site in sites.filter(|site| !self.hidden.contains(&self.index.call_kind(site))) {
+                if let Some(callee) = self.index.id_gen.name(site.callee) {
+                    children.entry(callee).or_insert((site.callee, 0)).1 += 1;
+                }
+            }
+            for (to, count) in children.into_values() {
+                // Recursion would repeat the same frames up to the depth limit.
+                if !self.path.contains(&to) {
+                    self.visit(to, multiplicity.saturating_mul(count), depth - 1);
+                }
+            }
+        }
+        self.path.pop();
+    }
+}
+
+impl CodeIndex {
+    /// Every static call path from `root` of at most `depth` functions, each
+    /// prefix being its own stack, leaving out calls of one of `hidden`.
+    /// Returns None if `root` is unknown.
+    pub fn folded_stacks(
+        &self,
+        root: &str,
+        depth: i32,
+        weight: FoldedWeight,
+        metrics: Option<&Metrics>,
+        hidden: &[CallKind],
+    ) -> Option<FoldedStacks> {
+        let id = self.id_gen.get(root)?;
+        let computed;
+        let metrics = match (weight, metrics) {
+            (FoldedWeight::Metric(_), None) => {
+                computed = self.metrics();
+                Some(&computed)
+            }
+            (_, metrics) => metrics,
+        };
+        let mut walk = Walk {
+            index: self,
+            weight,
+            metrics,
+            hidden,
+            path: vec![],
+            visited: 0,
+            out: FoldedStacks::default(),
+        };
+        walk.visit(id, 1, depth);
+        Some(walk.out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_folded_stacks() {
+        let mut index = CodeIndex::new();
+        index.parse_source(
+            "a.ts",
+            "function main() { parse(); parse(); log(); }\nfunction parse() { lex(); parse(); }\nfunction lex() {}\nfunction log() {}",
+        );
+        let paths = index
+            .folded_stacks("main", 3, FoldedWeight::Paths, None, &[])
+            .unwrap();
+        assert_eq!(
+            paths.to_text(),
+            "main 1\nmain;log 1\nmain;parse 1\nmain;parse;lex 1\n"
+        );
+        let calls = index
+            .folded_stacks("main", 3, FoldedWeight::Calls, None, &[])
+            .unwrap();
+        assert_eq!(calls.stacks[3], ("main;parse;lex".to_string(), 2));
+        let fan_in = index
+            .folded_stacks("main", 3, "fan_in".parse().unwrap(), None, &[])
+            .unwrap();
+        // main has no callers, so only its callees carry weight.
+        assert_eq!(fan_in.stacks[0], ("main;log".to_string(), 1));
+        assert!(index
+            .folded_stacks("missing", 3, FoldedWeight::Paths, None, &[])
+            .is_none());
+    }
+
+    #[test]
+    fn test_folded_stacks_hide() {
+        let mut index = CodeIndex::new();
+        index.parse_source(
+            "a.ts",
+            "import { get } from \"axios\";\nfunction main() { get(); console.log(); parse(); }\nfunction parse() { items.push(); }",
+        );
+        let all = index
+            .folded_stacks("main", 3, FoldedWeight::Paths, None, &[])
+            .unwrap();
+        assert_eq!(all.stacks.len(), 5);
+        let hidden = index
+            .folded_stacks(
+                "main",
+                3,
+                FoldedWeight::Paths,
+                None,
+                &[CallKind::External, CallKind::Builtin],
+            )
+            .unwrap();
+        assert_eq!(hidden.to_text(), "main 1\nmain;parse 1\n");
+    }
+
+    #[test]
+    fn test_folded_stacks_limit() {
+        // every function calls every other one, which makes 9! paths.
+        let names: Vec<String> = (0..10).map(|i| format!("f{}", i)).collect();
+        let calls = names
+            .iter()
+            .map(|n| format!("{}();", n))
+            .collect::<String>();
+        let source: String = names
+            .iter()
+            .map(|n| format!("function {}() {{ {} }}\n", n, calls))
+            .collect();
+        let mut index = CodeIndex::new();
+        index.parse_source("a.ts", &source);
+        let paths = index
+            .folded_stacks("f0", 10, FoldedWeight::Paths, None, &[])
+            .unwrap();
+        assert!(paths.truncated);
+        assert_eq!(paths.stacks.len(), MAX_STACKS);
+        // stacks of no weight count towards the limit too.
+        let none = index
+            .folded_stacks(
+                "f0",
+                10,
+                "fan_in".parse().unwrap(),
+                Some(&Metrics::default()),
+                &[],
+            )
+            .unwrap();
+        assert!(none.truncated && none.stacks.is_empty());
+
+        // 1000 calls of each next function overflow along a chain of 8.
+        let source: String = (0..8)
+            .map(|i| {
+                format!(
+                    "function g{}() {{ {} }}\n",
+                    i,
+                    format!("g{}();", i + 1).repeat(1000)
+                )
+            })
+            .collect();
+        index.parse_source("b.ts", &source);
+        let calls = index
+            .folded_stacks("g0", 8, FoldedWeight::Calls, None, &[])
+            .unwrap();
+        assert_eq!(calls.stacks.last().unwrap().1, u64::MAX);
+    }
+}
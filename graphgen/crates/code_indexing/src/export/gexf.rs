@@ -0,0 +1,111 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use super::{xml_escape, AttrType, Subgraph, NODE_ATTRIBUTES};
+
+fn type_name(t: AttrType) -> &'static str {
+    match t {
+        AttrType::String => "string",
+        AttrType::Int => "integer",
+        AttrType::Double => "double",
+        AttrType::Boolean => "boolean",
+    }
+}
+
+/// Renders `graph` as GEXF 1.3 for Gephi. The qualified name is the node
+/// label, and edge weights are call counts.
+pub fn to_gexf(graph: &Subgraph) -> String {
+    let mut out = String::new();
+    out += "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n";
+    out += "<gexf xmlns=\"http://gexf.net/1.3\" version=\"1.3\">\n";
+    out += "  <graph mode=\"static\" defaultedgetype=\"directed\">\n";
+    out += "    <attributes class=\"node\">\n";
+    for (key, t) in NODE_ATTRIBUTES.iter() {
+        let _ = writeln!(
+            out,
+            "      <attribute id=\"{0}\" title=\"{0}\" type=\"{1}\"/>",
+            key,
+            type_name(*t)
+        );
+    }
+    out += "    </attributes>\n";
+    out += "    <attributes class=\"edge\">\n";
+    out += "      <attribute id=\"kind\" title=\"kind\" type=\"string\"/>\n";
+    out += "      <attribute id=\"count\" title=\"count\" type=\"integer\"/>\n";
+    out += "    </attributes>\n";
+
+    let ids: BTreeMap<&String, usize> = graph
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(i, n)| (&n.name, i))
+        .collect();
+    out += "    <nodes>\n";
+    for (i, node) in graph.nodes.iter().enumerate() {
+        let _ = writeln!(
+            out,
+            "      <node id=\"n{}\" label=\"{}\">",
+            i,
+            xml_escape(&node.name)
+        );
+        out += "        <attvalues>\n";
+        for (key, _) in NODE_ATTRIBUTES.iter() {
+            if let Some(value) = node.attribute(key) {
+                let _ = writeln!(
+                    out,
+                    "          <attvalue for=\"{}\" value=\"{}\"/>",
+                    key,
+                    xml_escape(&value)
+                );
+            }
+        }
+        out += "        </attvalues>\n      </node>\n";
+    }
+    out += "    </nodes>\n    <edges>\n";
+    for (i, edge) in graph.edges.iter().enumerate() {
+        let _ = writeln!(
+            out,
+            "      <edge id=\"e{}\" source=\"n{}\" target=\"n{}\" weight=\"{}\">",
+            i, ids[&edge.source], ids[&edge.target], edge.count
+        );
+        out += "        <attvalues>\n";
+        let _ = writeln!(
+            out,
+            "          <attvalue for=\"kind\" value=\"{}\"/>",
+            edge.kind.name()
+        );
+        let _ = writeln!(
+            out,
+            "          <attvalue for=\"count\" value=\"{}\"/>",
+            edge.count
+        );
+        out += "        </attvalues>\n      </edge>\n";
+    }
+    out += "    </edges>\n  </graph>\n</gexf>\n";
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::Selection;
+    use crate::CodeIndex;
+
+    fn graph() -> Subgraph {
+        let mut index = CodeIndex::new();
+        index.parse_source(
+            "a.ts",
+            "class A { run() { this.step(); this.step(); later(this.step); } step() {} }",
+        );
+        let metrics = index.metrics();
+        index.subgraph(&Selection::All, Some(&metrics)).unwrap()
+    }
+
+    #[test]
+    fn test_to_gexf() {
+        let gexf = to_gexf(&graph());
+        assert!(gexf.contains("<node id=\"n0\" label=\"A.run\">"));
+        assert!(gexf.contains("weight=\"2\""));
+        assert!(gexf.contains("<attvalue for=\"kind\" value=\"reference\"/>"));
+    }
+}
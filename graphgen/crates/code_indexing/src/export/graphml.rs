@@ -0,0 +1,91 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use super::{xml_escape, AttrType, Subgraph, NODE_ATTRIBUTES};
+
+fn type_name(t: AttrType) -> &'static str {
+    match t {
+        AttrType::String => "string",
+        AttrType::Int => "int",
+        AttrType::Double => "double",
+        AttrType::Boolean => "boolean",
+    }
+}
+
+/// Renders `graph` as GraphML, readable by yEd, Gephi and networkx.
+pub fn to_graphml(graph: &Subgraph) -> String {
+    let mut out = String::new();
+    out += "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n";
+    out += "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n";
+    for (key, t) in NODE_ATTRIBUTES.iter() {
+        let _ = writeln!(
+            out,
+            "  <key id=\"{0}\" for=\"node\" attr.name=\"{0}\" attr.type=\"{1}\"/>",
+            key,
+            type_name(*t)
+        );
+    }
+    out += "  <key id=\"kind\" for=\"edge\" attr.name=\"kind\" attr.type=\"string\"/>\n";
+    out += "  <key id=\"count\" for=\"edge\" attr.name=\"count\" attr.type=\"int\"/>\n";
+    out += "  <graph id=\"callgraph\" edgedefault=\"directed\">\n";
+
+    let ids: BTreeMap<&String, usize> = graph
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(i, n)| (&n.name, i))
+        .collect();
+    for (i, node) in graph.nodes.iter().enumerate() {
+        let _ = writeln!(out, "    <node id=\"n{}\">", i);
+        for (key, _) in NODE_ATTRIBUTES.iter() {
+            if let Some(value) = node.attribute(key) {
+                let _ = writeln!(
+                    out,
+                    "      <data key=\"{}\">{}</data>",
+                    key,
+                    xml_escape(&value)
+                );
+            }
+        }
+        out += "    </node>\n";
+    }
+    for (i, edge) in graph.edges.iter().enumerate() {
+        let _ = writeln!(
+            out,
+            "    <edge id=\"e{}\" source=\"n{}\" target=\"n{}\">",
+            i, ids[&edge.source], ids[&edge.target]
+        );
+        let _ = writeln!(out, "      <data key=\"kind\">{}</data>", edge.kind.name());
+        let _ = writeln!(out, "      <data key=\"count\">{}</data>", edge.count);
+        out += "    </edge>\n";
+    }
+    out += "  </graph>\n</graphml>\n";
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::Selection;
+    use crate::CodeIndex;
+
+    fn graph() -> Subgraph {
+        let mut index = CodeIndex::new();
+        index.parse_source(
+            "a.ts",
+            "class A { run() { this.step(); this.step(); later(this.step); } step() {} }",
+        );
+        let metrics = index.metrics();
+        index.subgraph(&Selection::All, Some(&metrics)).unwrap()
+    }
+
+    #[test]
+    fn test_to_graphml() {
+        let graphml = to_graphml(&graph());
+        assert!(graphml.contains(
+            "<key id=\"pagerank\" for=\"node\" attr.name=\"pagerank\" attr.type=\"double\"/>"
+        ));
+        assert!(graphml.contains("<data key=\"class\">A</data>"));
+        assert!(graphml.contains("<data key=\"kind\">reference</data>"));
+    }
+}
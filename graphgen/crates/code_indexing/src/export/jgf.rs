@@ -0,0 +1,92 @@
+use serde_json::{json, Map, Value};
+
+use super::{AttrType, Subgraph, NODE_ATTRIBUTES};
+
+/// Renders `graph` in JSON Graph Format v2, with node attributes and edge
+/// counts under `metadata`. Node ids are qualified names.
+pub fn to_jgf(graph: &Subgraph) -> Value {
+    let mut nodes = Map::new();
+    for node in graph.nodes.iter() {
+        let mut metadata = Map::new();
+        for (key, t) in NODE_ATTRIBUTES.iter().skip(1) {
+            if let Some(value) = node.attribute(key) {
+                let value = match t {
+                    AttrType::String => json!(value),
+                    AttrType::Int => json!(value.parse::<u64>().unwrap_or_default()),
+                    AttrType::Double => json!(value.parse::<f64>().unwrap_or_default()),
+                    AttrType::Boolean => json!(value == "true"),
+                };
+                metadata.insert(key.to_string(), value);
+            }
+        }
+        nodes.insert(
+            node.name.clone(),
+            json!({ "label": node.name, "metadata": metadata }),
+        );
+    }
+    let edges: Vec<Value> = graph
+        .edges
+        .iter()
+        .map(|edge| {
+            json!({
+                "source": edge.source,
+                "target": edge.target,
+                "relation": edge.kind.name(),
+                "directed": true,
+                "metadata": { "count": edge.count },
+            })
+        })
+        .collect();
+    let mut metadata = Map::new();
+    if let Some(note) = graph.truncation_note() {
+        metadata.insert("truncated".to_string(), json!(note));
+    }
+    json!({
+        "graph": {
+            "directed": true,
+            "type": "callgraph",
+            "metadata": metadata,
+            "nodes": nodes,
+            "edges": edges,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::Selection;
+    use crate::CodeIndex;
+
+    fn graph() -> Subgraph {
+        let mut index = CodeIndex::new();
+        index.parse_source(
+            "a.ts",
+            "class A { run() { this.step(); this.step(); later(this.step); } step() {} }",
+        );
+        let metrics = index.metrics();
+        index.subgraph(&Selection::All, Some(&metrics)).unwrap()
+    }
+
+    #[test]
+    fn test_to_jgf() {
+        let jgf = to_jgf(&graph());
+        let step = &jgf["graph"]["nodes"]["A.step"]["metadata"];
+        assert_eq!(step["file"], "a.ts");
+        assert_eq!(step["class"], "A");
+        assert_eq!(step["line"], 1);
+        assert_eq!(step["fan_in"], 1);
+        let edges = jgf["graph"]["edges"].as_array().unwrap();
+        let kinds: Vec<_> = edges
+            .iter()
+            .filter(|e| e["target"] == "A.step")
+            .map(|e| {
+                (
+                    e["relation"].as_str().unwrap(),
+                    e["metadata"]["count"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(kinds, vec![("call", 2), ("reference", 1)]);
+    }
+}
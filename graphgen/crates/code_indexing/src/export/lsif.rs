@@ -0,0 +1,153 @@
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+use super::symbols::Document;
+use crate::{CodeIndex, Span};
+
+const LSIF_VERSION: &str = "0.4.3";
+
+struct Emitter {
+    next_id: u64,
+    lines: Vec<String>,
+}
+
+impl Emitter {
+    fn emit(&mut self, kind: &str, label: &str, mut fields: Value) -> u64 {
+        self.next_id += 1;
+        fields["id"] = json!(self.next_id);
+        fields["type"] = json!(kind);
+        fields["label"] = json!(label);
+        self.lines.push(fields.to_string());
+        self.next_id
+    }
+
+    fn vertex(&mut self, label: &str, fields: Value) -> u64 {
+        self.emit("vertex", label, fields)
+    }
+
+    fn edge(&mut self, label: &str, out_v: u64, in_v: u64) -> u64 {
+        self.emit("edge", label, json!({"outV": out_v, "inV": in_v}))
+    }
+
+    fn range(&mut self, span: Span) -> u64 {
+        let line = span.line.saturating_sub(1);
+        self.vertex(
+            "range",
+            json!({
+                "start": {"line": line, "character": span.column},
+                "end": {"line": line, "character": span.end_column},
+            }),
+        )
+    }
+}
+
+/// Ranges of one symbol in one document.
+#[derive(Default)]
+struct Ranges {
+    definitions: Vec<u64>,
+    references: Vec<u64>,
+}
+
+/// Renders the index as an LSIF dump, one JSON vertex or edge per line, with
+/// hover, definition and reference results for every project function.
+pub fn to_lsif(index: &CodeIndex) -> String {
+    let documents = index.documents();
+    let root = index.root_uri();
+    let mut out = Emitter {
+        next_id: 0,
+        lines: vec![],
+    };
+    out.vertex(
+        "metaData",
+        json!({
+            "version": LSIF_VERSION,
+            "projectRoot": root,
+            "positionEncoding": "utf-16",
+            "toolInfo": {"name": "graphgen", "version": env!("CARGO_PKG_VERSION")},
+        }),
+    );
+    let project = out.vertex("project", json!({"kind": "typescript"}));
+
+    let mut result_sets: BTreeMap<&str, u64> = BTreeMap::new();
+    for def in documents.iter().flat_map(|d| d.definitions.iter()) {
+        let result_set = out.vertex("resultSet", json!({}));
+        let hover = out.vertex(
+            "hoverResult",
+            json!({"result": {"contents": {"kind": "markdown", "value": def.documentation}}}),
+        );
+        out.edge("textDocument/hover", result_set, hover);
+        result_sets.insert(&def.name, result_set);
+    }
+
+    // symbol -> document id -> ranges, for the definition and reference results.
+    let mut symbol_ranges: BTreeMap<&str, BTreeMap<u64, Ranges>> = BTreeMap::new();
+    let mut document_ids = vec![];
+    for doc in documents.iter() {
+        let id = emit_document(&mut out, &root, doc, &result_sets, &mut symbol_ranges);
+        document_ids.push(id);
+    }
+    if !document_ids.is_empty() {
+        out.emit(
+            "edge",
+            "contains",
+            json!({"outV": project, "inVs": document_ids}),
+        );
+    }
+
+    for (name, docs) in symbol_ranges.iter() {
+        let result_set = result_sets[name];
+        let definition = out.vertex("definitionResult", json!({}));
+        out.edge("textDocument/definition", result_set, definition);
+        let references = out.vertex("referenceResult", json!({}));
+        out.edge("textDocument/references", result_set, references);
+        for (document, ranges) in docs.iter() {
+            if !ranges.definitions.is_empty() {
+                let item =
+                    json!({"outV": definition, "inVs": ranges.definitions, "document": document});
+                out.emit("edge", "item", item);
+                let item = json!({"outV": references, "inVs": ranges.definitions, "document": document, "property": "definitions"});
+                out.emit("edge", "item", item);
+            }
+            if !ranges.references.is_empty() {
+                let item = json!({"outV": references, "inVs": ranges.references, "document": document, "property": "references"});
+                out.emit("edge", "item", item);
+            }
+        }
+    }
+
+    let mut text = out.lines.join("\n");
+    text.push('\n');
+    text
+}
+
+fn emit_document<'a>(
+    out: &mut Emitter,
+    root: &str,
+    doc: &'a Document,
+    result_sets: &BTreeMap<&str, u64>,
+    symbol_ranges: &mut BTreeMap<&'a str, BTreeMap<u64, Ranges>>,
+) -> u64 {
+    let id = out.vertex(
+        "document",
+        json!({"uri": format!("{}/{}", root, doc.path), "languageId": "typescript"}),
+    );
+    let mut ranges = vec![];
+    for def in doc.definitions.iter() {
+        let range = out.range(def.span);
+        out.edge("next", range, result_sets[def.name.as_str()]);
+        ranges.push(range);
+        let entry = symbol_ranges.entry(&def.name).or_default();
+        entry.entry(id).or_default().definitions.push(range);
+    }
+    for reference in doc.references.iter() {
+        let range = out.range(reference.span);
+        out.edge("next", range, result_sets[reference.name.as_str()]);
+        ranges.push(range);
+        let entry = symbol_ranges.entry(&reference.name).or_default();
+        entry.entry(id).or_default().references.push(range);
+    }
+    if !ranges.is_empty() {
+        out.emit("edge", "contains", json!({"outV": id, "inVs": ranges}));
+    }
+    id
+}
@@ -0,0 +1,102 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use super::{one_line, EdgeKind, Subgraph};
+
+/// A quoted node label: `#` starts an entity code, and `<`, `>` and a
+/// backtick would be read as HTML or Markdown.
+fn label(s: &str) -> String {
+    one_line(s)
+        .replace('#', "#35;")
+        .replace('"', "#quot;")
+        .replace('<', "#lt;")
+        .replace('>', "#gt;")
+        .replace('`', "#96;")
+}
+
+/// Renders `graph` as a Mermaid `flowchart`, for Markdown renderers that
+/// support Mermaid code blocks. Incomplete graphs get a visible note node.
+pub fn to_mermaid(graph: &Subgraph) -> String {
+    let mut out = String::from("flowchart LR\n");
+    let ids: BTreeMap<&String, usize> = graph
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(i, n)| (&n.name, i))
+        .collect();
+    for (i, node) in graph.nodes.iter().enumerate() {
+        let class = if node.defined() { "" } else { ":::external" };
+        let _ = writeln!(out, "    n{}[\"{}\"]{}", i, label(&node.name), class);
+    }
+    for edge in graph.edges.iter() {
+        let arrow = match edge.kind {
+            EdgeKind::Call => "-->",
+            EdgeKind::Reference => "-.->",
+        };
+        let _ = writeln!(
+            out,
+            "    n{} {}|{}| n{}",
+            ids[&edge.source], arrow, edge.count, ids[&edge.target]
+        );
+    }
+    if let Some(note) = graph.truncation_note() {
+        let _ = writeln!(out, "    truncated[\"{}\"]:::note", label(&note));
+    }
+    out += "    classDef external stroke-dasharray: 5 5\n";
+    out += "    classDef note fill:#fff5ad,stroke:#e0c000\n";
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::Selection;
+    use crate::CodeIndex;
+
+    #[test]
+    fn test_to_mermaid() {
+        let mut index = CodeIndex::new();
+        index.parse_source("a.ts", "function a() { b(); }\nfunction b() { c(); }");
+        let tree = Selection::Tree {
+            root: "a".to_string(),
+            depth: 2,
+        };
+        let graph = index.subgraph(&tree, None).unwrap();
+        assert_eq!(
+            to_mermaid(&graph),
+            "flowchart LR
+    n0[\"a\"]
+    n1[\"b\"]
+    n0 -->|1| n1
+    truncated[\"depth limit reached, deeper calls not shown\"]:::note
+    classDef external stroke-dasharray: 5 5
+    classDef note fill:#fff5ad,stroke:#e0c000
+"
+        );
+    }
+
+    #[test]
+    fn test_mermaid_labels() {
+        let mut index = CodeIndex::new();
+        index.parse_source(
+            "a.ts",
+            "function a() {\n  fetch(url)\n    .then((r) => r.json());\n  (x < y ? f : g)();\n  obj[\"#k\"]();\n}",
+        );
+        let graph = index.subgraph(&Selection::All, None).unwrap();
+        let out = to_mermaid(&graph);
+        assert!(out.contains("[\"fetch(url) .then\"]:::external"), "{}", out);
+        assert!(
+            out.contains("[\"(x #lt; y ? f : g)\"]:::external"),
+            "{}",
+            out
+        );
+        assert!(
+            out.contains("[\"obj[#quot;#35;k#quot;]\"]:::external"),
+            "{}",
+            out
+        );
+        assert!(out
+            .lines()
+            .all(|l| l == "flowchart LR" || l.starts_with("    ")));
+    }
+}
@@ -0,0 +1,399 @@
+//! Exporters rendering a selected part of the call graph to text formats.
+
+pub mod dot;
+pub mod folded;
+pub mod gexf;
+pub mod graphml;
+pub mod jgf;
+pub mod lsif;
+pub mod mermaid;
+pub mod neo4j;
+pub mod plantuml;
+pub mod scip;
+pub mod svg;
+pub mod symbols;
+
+use glob::{Pattern, PatternError};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use crate::metrics::{FunctionMetrics, Metrics};
+use crate::CodeIndex;
+
+/// Which part of the index to export.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Selection {
+    All,
+    /// Everything reachable from `root`, with the same depth semantics as
+    /// `CodeIndex::serde_tree`: a depth of 1 is the root alone.
+    Tree {
+        root: String,
+        depth: i32,
+    },
+    /// Functions whose qualified name matches a glob, and calls between them.
+    Matching(String),
+    /// Functions of one workspace package or project root, and calls
+    /// between them.
+    Package(String),
+    /// Functions on call paths from `from` to `to` of at most `depth` nodes.
+    Path {
+        from: String,
+        to: String,
+        depth: i32,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExportNode {
+    pub name: String,
+    /// Empty for callees not defined in the project.
+    pub file: String,
+    pub line: usize,
+    pub class: String,
+    /// Empty for callees not defined in the project.
+    pub package: String,
+    pub metrics: Option<FunctionMetrics>,
+}
+
+impl ExportNode {
+    pub fn defined(&self) -> bool {
+        !self.file.is_empty()
+    }
+
+    /// Value of one of `NODE_ATTRIBUTES`, or None when it does not apply.
+    pub(crate) fn attribute(&self, key: &str) -> Option<String> {
+        let metrics = self.metrics.as_ref();
+        match key {
+            "name" => Some(self.name.clone()),
+            "file" => Some(self.file.clone()).filter(|f| !f.is_empty()),
+            "line" => Some(self.line.to_string()).filter(|_| self.defined()),
+            "class" => Some(self.class.clone()).filter(|c| !c.is_empty()),
+            "package" => Some(self.package.clone()).filter(|p| !p.is_empty()),
+            "defined" => Some(self.defined().to_string()),
+            "fan_in" => metrics.map(|m| m.fan_in.to_string()),
+            "fan_out" => metrics.map(|m| m.fan_out.to_string()),
+            "calls" => metrics.map(|m| m.calls.to_string()),
+            "reach" => metrics.map(|m| m.reach.to_string()),
+            "betweenness" => metrics.map(|m| m.betweenness.to_string()),
+            "pagerank" => metrics.map(|m| m.pagerank.to_string()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum AttrType {
+    String,
+    Int,
+    Double,
+    Boolean,
+}
+
+/// Node attributes shared by the GraphML, GEXF and JSON Graph exporters.
+pub(crate) const NODE_ATTRIBUTES: [(&str, AttrType); 12] = [
+    ("name", AttrType::String),
+    ("file", AttrType::String),
+    ("line", AttrType::Int),
+    ("class", AttrType::String),
+    ("package", AttrType::String),
+    ("defined", AttrType::Boolean),
+    ("fan_in", AttrType::Int),
+    ("fan_out", AttrType::Int),
+    ("calls", AttrType::Int),
+    ("reach", AttrType::Int),
+    ("betweenness", AttrType::Double),
+    ("pagerank", AttrType::Double),
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EdgeKind {
+    Call,
+    /// The target is passed or stored as a value, e.g. a callback.
+    Reference,
+}
+
+impl EdgeKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EdgeKind::Call => "call",
+            EdgeKind::Reference => "reference",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExportEdge {
+    pub source: String,
+    pub target: String,
+    pub kind: EdgeKind,
+    /// Call sites, or references, from source to target.
+    pub count: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Subgraph {
+    pub nodes: Vec<ExportNode>,
+    pub edges: Vec<ExportEdge>,
+    /// Set when a tree or path selection stopped at its depth with calls left
+    /// to follow.
+    pub truncated: bool,
+    /// Nodes dropped by `Subgraph::truncate`.
+    pub omitted: usize,
+}
+
+impl Subgraph {
+    /// Keeps the first `max_nodes` nodes, closest to the root for trees and
+    /// paths, and the edges between them.
+    pub fn truncate(&mut self, max_nodes: usize) {
+        if self.nodes.len() <= max_nodes {
+            return;
+        }
+        self.omitted += self.nodes.len() - max_nodes;
+        self.nodes.truncate(max_nodes);
+        let kept: BTreeSet<&String> = self.nodes.iter().map(|n| &n.name).collect();
+        self.edges
+            .retain(|e| kept.contains(&e.source) && kept.contains(&e.target));
+    }
+
+    /// Human readable reason the graph is incomplete, if it is.
+    pub fn truncation_note(&self) -> Option<String> {
+        match (self.truncated, self.omitted) {
+            (false, 0) => None,
+            (true, 0) => Some("depth limit reached, deeper calls not shown".to_string()),
+            (false, n) => Some(format!("{} more functions not shown", n)),
+            (true, n) => Some(format!(
+                "depth limit reached and {} more functions not shown",
+                n
+            )),
+        }
+    }
+
+    pub fn node(&self, name: &str) -> Option<&ExportNode> {
+        self.nodes.iter().find(|n| n.name == name)
+    }
+}
+
+impl CodeIndex {
+    pub fn subgraph(
+        &self,
+        selection: &Selection,
+        metrics: Option<&Metrics>,
+    ) -> Result<Subgraph, PatternError> {
+        let mut graph = Subgraph::default();
+        let mut order: Vec<u64> = vec![];
+        match selection {
+            Selection::All => {
+                order.extend(self.functions.keys().filter_map(|k| self.id_gen.get(k)));
+                for targets in self.edges.values() {
+                    order.extend(targets.iter().copied());
+                }
+            }
+            Selection::Matching(pattern) => {
+                let pattern = Pattern::new(pattern)?;
+                order.extend(
+                    self.functions
+                        .keys()
+                        .filter(|k| pattern.matches(k))
+                        .filter_map(|k| self.id_gen.get(k)),
+                );
+            }
+            Selection::Package(package) => {
+                order.extend(
+                    self.functions
+                        .iter()
+                        .filter(|(_, f)| &f.package == package)
+                        .filter_map(|(k, _)| self.id_gen.get(k)),
+                );
+            }
+            Selection::Tree { root, depth } => {
+                let root = match self.id_gen.get(root) {
+                    Some(root) if *depth > 0 => root,
+                    _ => return Ok(graph),
+                };
+                let mut levels = BTreeMap::from([(root, 1)]);
+                let mut queue = VecDeque::from([root]);
+                while let Some(id) = queue.pop_front() {
+                    order.push(id);
+                    let level = levels[&id];
+                    for target in self.edges.get(&id).into_iter().flatten() {
+                        if levels.contains_key(target) {
+                            continue;
+                        }
+                        if level >= *depth {
+                            graph.truncated = true;
+                            continue;
+                        }
+                        levels.insert(*target, level + 1);
+                        queue.push_back(*target);
+                    }
+                }
+            }
+            Selection::Path { from, to, depth } => {
+                let (from, to) = match (self.id_gen.get(from), self.id_gen.get(to)) {
+                    (Some(from), Some(to)) if *depth > 0 => (from, to),
+                    _ => return Ok(graph),
+                };
+                let mut reverse: BTreeMap<u64, Vec<u64>> = BTreeMap::new();
+                for (caller, targets) in self.edges.iter() {
+                    for target in targets {
+                        reverse.entry(*target).or_default().push(*caller);
+                    }
+                }
+                let forward = distances(from, &self.edges);
+                let backward = distances(to, &reverse);
+                let mut on_path: Vec<(usize, u64)> = vec![];
+                for (id, d) in forward.iter() {
+                    if let Some(b) = backward.get(id) {
+                        if (d + b + 1) as i32 <= *depth {
+                            on_path.push((*d, *id));
+                        } else {
+                            graph.truncated = true;
+                        }
+                    }
+                }
+                on_path.sort();
+                order.extend(on_path.into_iter().map(|(_, id)| id));
+            }
+        }
+
+        let mut seen = BTreeSet::new();
+        order.retain(|id| seen.insert(*id));
+        for id in order.iter() {
+            let name = self.id_gen.name(*id).cloned().unwrap_or_default();
+            let node = match self.functions.get(&name) {
+                Some(func) => ExportNode {
+                    file: func.file.clone(),
+                    line: func.line,
+                    class: func.pkg.clone(),
+                    package: func.package.clone(),
+                    metrics: metrics.and_then(|m| m.functions.get(&name)).cloned(),
+                    name,
+                },
+                None => ExportNode {
+                    name,
+                    file: String::new(),
+                    line: 0,
+                    class: String::new(),
+                    package: String::new(),
+                    metrics: None,
+                },
+            };
+            graph.nodes.push(node);
+        }
+        for from in order.iter() {
+            for (kind, adjacency) in [
+                (EdgeKind::Call, &self.edges),
+                (EdgeKind::Reference, &self.refs),
+            ] {
+                let mut counts: BTreeMap<u64, usize> = BTreeMap::new();
+                for to in adjacency.get(from).into_iter().flatten() {
+                    if seen.contains(to) {
+                        *counts.entry(*to).or_default() += 1;
+                    }
+                }
+                for (to, count) in counts {
+                    graph.edges.push(ExportEdge {
+                        source: self.id_gen.name(*from).cloned().unwrap_or_default(),
+                        target: self.id_gen.name(to).cloned().unwrap_or_default(),
+                        kind,
+                        count,
+                    });
+                }
+            }
+        }
+        Ok(graph)
+    }
+}
+
+/// `s` on one line, with each run of whitespace, e.g. the line breaks of a
+/// chained call written over several lines, made a single space.
+pub(crate) fn one_line(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+pub(crate) fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Unweighted shortest distances from `start` along `adjacency`.
+fn distances(start: u64, adjacency: &BTreeMap<u64, Vec<u64>>) -> BTreeMap<u64, usize> {
+    let mut result = BTreeMap::from([(start, 0)]);
+    let mut queue = VecDeque::from([start]);
+    while let Some(id) = queue.pop_front() {
+        let d = result[&id];
+        for next in adjacency.get(&id).into_iter().flatten() {
+            if !result.contains_key(next) {
+                result.insert(*next, d + 1);
+                queue.push_back(*next);
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subgraph_tree() {
+        let mut index = CodeIndex::new();
+        index.parse_source(
+            "a.ts",
+            "function a() { b(); b(); log(); }\nfunction b() { c(); }\nfunction c() {}",
+        );
+        let tree = Selection::Tree {
+            root: "a".to_string(),
+            depth: 2,
+        };
+        let graph = index.subgraph(&tree, None).unwrap();
+        let names: Vec<_> = graph.nodes.iter().map(|n| n.name.as_str()).collect();
+        assert_eq!(names, vec!["a", "log", "b"]);
+        assert!(graph.truncated);
+        assert!(!graph.node("log").unwrap().defined());
+        assert_eq!(graph.edges.len(), 2);
+        assert_eq!(graph.edges.iter().map(|e| e.count).sum::<usize>(), 3);
+
+        let mut graph = graph;
+        graph.truncate(1);
+        assert_eq!(graph.omitted, 2);
+        assert!(graph.edges.is_empty());
+        assert_eq!(
+            graph.truncation_note().unwrap(),
+            "depth limit reached and 2 more functions not shown"
+        );
+
+        let matching = Selection::Matching("[bc]".to_string());
+        let graph = index.subgraph(&matching, None).unwrap();
+        assert_eq!(graph.nodes.len(), 2);
+        assert_eq!(graph.edges.len(), 1);
+        assert!(!graph.truncated);
+    }
+
+    #[test]
+    fn test_subgraph_path() {
+        let mut index = CodeIndex::new();
+        index.parse_source(
+            "a.ts",
+            "function a() { b(); x(); }\nfunction b() { c(); }\nfunction c() { d(); }\nfunction x() { d(); }\nfunction d() {}\nfunction e() { d(); }",
+        );
+        let path = |depth| Selection::Path {
+            from: "a".to_string(),
+            to: "d".to_string(),
+            depth,
+        };
+        let graph = index.subgraph(&path(3), None).unwrap();
+        let names: Vec<_> = graph.nodes.iter().map(|n| n.name.as_str()).collect();
+        assert_eq!(names, vec!["a", "x", "d"]);
+        assert!(graph.truncated);
+
+        let graph = index.subgraph(&path(4), None).unwrap();
+        assert_eq!(graph.nodes.len(), 5);
+        assert!(!graph.truncated);
+    }
+}
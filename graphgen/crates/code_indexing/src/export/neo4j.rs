@@ -0,0 +1,307 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::CodeIndex;
+
+const CYPHER_BATCH: usize = 500;
+
+/// One `neo4j-admin database import` input file.
+#[derive(Debug, Clone)]
+pub struct CsvFile {
+    pub name: String,
+    /// `--nodes` or `--relationships`.
+    pub kind: &'static str,
+    pub content: String,
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn cypher_string(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+struct Table {
+    header: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    fn new(header: &[&'static str]) -> Self {
+        Table {
+            header: header.to_vec(),
+            rows: vec![],
+        }
+    }
+
+    fn csv(&self) -> String {
+        let mut out = self.header.join(",") + "\n";
+        for row in self.rows.iter() {
+            let fields: Vec<String> = row.iter().map(|f| csv_field(f)).collect();
+            out += &fields.join(",");
+            out += "\n";
+        }
+        out
+    }
+}
+
+/// Nodes and relationships of the index as flat tables, keyed by the CSV
+/// file each one is written to.
+struct Neo4jGraph {
+    tables: Vec<(&'static str, &'static str, Table)>,
+}
+
+impl CodeIndex {
+    fn neo4j_graph(&self) -> Neo4jGraph {
+        let mut functions = Table::new(&[
+            "id:ID(Function)",
+            "name",
+            "class",
+            "file",
+            "line:int",
+            "end_line:int",
+            "exported:boolean",
+            "test:boolean",
+            ":LABEL",
+        ]);
+        let mut classes = Table::new(&["id:ID(Class)", "name", "file", ":LABEL"]);
+        let mut files = Table::new(&["id:ID(File)", "path", ":LABEL"]);
+        let mut calls = Table::new(&[
+            ":START_ID(Function)",
+            ":END_ID(Function)",
+            "count:int",
+            ":TYPE",
+        ]);
+        let mut defined_in = Table::new(&[":START_ID(Function)", ":END_ID(File)", ":TYPE"]);
+        let mut class_defined_in = Table::new(&[":START_ID(Class)", ":END_ID(File)", ":TYPE"]);
+        let mut member_of = Table::new(&[":START_ID(Function)", ":END_ID(Class)", ":TYPE"]);
+        let mut extends = Table::new(&[":START_ID(Class)", ":END_ID(Class)", ":TYPE"]);
+
+        let mut paths = BTreeSet::new();
+        for (key, func) in self.functions.iter() {
+            let file = self.relative_path(&func.file).to_string();
+            functions.rows.push(vec![
+                key.clone(),
+                func.name.clone(),
+                func.pkg.clone(),
+                file.clone(),
+                func.line.to_string(),
+                func.end_line.to_string(),
+                func.exported.to_string(),
+                func.test.to_string(),
+                "Function".to_string(),
+            ]);
+            defined_in
+                .rows
+                .push(vec![key.clone(), file.clone(), "DEFINED_IN".to_string()]);
+            if self.classes.contains_key(&func.pkg) {
+                member_of
+                    .rows
+                    .push(vec![key.clone(), func.pkg.clone(), "MEMBER_OF".to_string()]);
+            }
+            paths.insert(file);
+        }
+        for (name, cls) in self.classes.iter() {
+            let file = self.relative_path(&cls.file).to_string();
+            classes.rows.push(vec![
+                name.clone(),
+                name.clone(),
+                file.clone(),
+                "Class".to_string(),
+            ]);
+            class_defined_in
+                .rows
+                .push(vec![name.clone(), file.clone(), "DEFINED_IN".to_string()]);
+            if let Some(parent) = cls
+                .extends
+                .as_ref()
+                .filter(|p| self.classes.contains_key(*p))
+            {
+                extends
+                    .rows
+                    .push(vec![name.clone(), parent.clone(), "EXTENDS".to_string()]);
+            }
+            paths.insert(file);
+        }
+        for path in paths {
+            files
+                .rows
+                .push(vec![path.clone(), path, "File".to_string()]);
+        }
+        for (from, targets) in self.edges.iter() {
+            let caller = match self.id_gen.name(*from) {
+                Some(name) if self.functions.contains_key(name) => name,
+                _ => continue,
+            };
+            let mut counts: BTreeMap<&String, usize> = BTreeMap::new();
+            for to in targets {
+                if let Some(callee) = self.id_gen.name(*to) {
+                    if self.functions.contains_key(callee) {
+                        *counts.entry(callee).or_default() += 1;
+                    }
+                }
+            }
+            for (callee, count) in counts {
+                calls.rows.push(vec![
+                    caller.clone(),
+                    callee.clone(),
+                    count.to_string(),
+                    "CALLS".to_string(),
+                ]);
+            }
+        }
+
+        Neo4jGraph {
+            tables: vec![
+                ("functions.csv", "--nodes", functions),
+                ("classes.csv", "--nodes", classes),
+                ("files.csv", "--nodes", files),
+                ("calls.csv", "--relationships", calls),
+                ("defined_in.csv", "--relationships", defined_in),
+                ("class_defined_in.csv", "--relationships", class_defined_in),
+                ("member_of.csv", "--relationships", member_of),
+                ("extends.csv", "--relationships", extends),
+            ],
+        }
+    }
+
+    /// CSV files for `neo4j-admin database import full`.
+    pub fn neo4j_csv(&self) -> Vec<CsvFile> {
+        self.neo4j_graph()
+            .tables
+            .into_iter()
+            .map(|(name, kind, table)| CsvFile {
+                name: name.to_string(),
+                kind,
+                content: table.csv(),
+            })
+            .collect()
+    }
+
+    /// A Cypher script creating the same graph as `neo4j_csv` in a running
+    /// database. Nodes are merged on `id`, so it can be re-run after changes.
+    pub fn neo4j_cypher(&self) -> String {
+        let graph = self.neo4j_graph();
+        let mut out = String::new();
+        for label in ["Function", "Class", "File"] {
+            let _ = writeln!(
+                out,
+                "CREATE CONSTRAINT {0}_id IF NOT EXISTS FOR (n:{1}) REQUIRE n.id IS UNIQUE;",
+                label.to_lowercase(),
+                label
+            );
+        }
+        for (_, kind, table) in graph.tables.iter() {
+            let is_node = *kind == "--nodes";
+            for batch in table.rows.chunks(CYPHER_BATCH) {
+                let rows: Vec<String> = batch.iter().map(|row| cypher_row(table, row)).collect();
+                let _ = writeln!(out, "UNWIND [\n  {}\n] AS row", rows.join(",\n  "));
+                if is_node {
+                    let label = batch[0].last().unwrap();
+                    let _ = writeln!(
+                        out,
+                        "MERGE (n:{} {{id: row.id}}) SET n += row.props;",
+                        label
+                    );
+                } else {
+                    let (start, end) = endpoint_labels(table);
+                    let rel = batch[0].last().unwrap();
+                    let _ = writeln!(
+                        out,
+                        "MATCH (a:{} {{id: row.start}}), (b:{} {{id: row.end}})\nMERGE (a)-[r:{}]->(b) SET r += row.props;",
+                        start, end, rel
+                    );
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Labels of the `:START_ID(Label)` and `:END_ID(Label)` columns.
+fn endpoint_labels(table: &Table) -> (&str, &str) {
+    let label = |h: &'static str| {
+        h.split_once('(')
+            .map(|(_, rest)| rest.trim_end_matches(')'))
+            .unwrap_or("")
+    };
+    (label(table.header[0]), label(table.header[1]))
+}
+
+/// A row as a Cypher map literal: ids, plus typed properties under `props`.
+fn cypher_row(table: &Table, row: &[String]) -> String {
+    let mut ids = vec![];
+    let mut props = vec![];
+    for (header, value) in table.header.iter().zip(row.iter()) {
+        let (name, ty) = header.split_once(':').unwrap_or((header, ""));
+        let literal = match ty {
+            "int" | "boolean" => value.clone(),
+            _ => cypher_string(value),
+        };
+        if ty.starts_with("ID") {
+            ids.push(format!("id: {}", literal));
+        } else if ty.starts_with("START_ID") {
+            ids.push(format!("start: {}", literal));
+        } else if ty.starts_with("END_ID") {
+            ids.push(format!("end: {}", literal));
+        } else if !name.is_empty() {
+            props.push(format!("{}: {}", name, literal));
+        }
+    }
+    format!("{{{}, props: {{{}}}}}", ids.join(", "), props.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index() -> CodeIndex {
+        let mut index = CodeIndex::new();
+        index.parse_source(
+            "src/shapes.ts",
+            "class Shape { area() { return 0; } }\nclass Square extends Shape { area() { return sq(this.side); } }\nfunction sq(x) { return x * x; }",
+        );
+        index
+    }
+
+    #[test]
+    fn test_neo4j_csv() {
+        let files = index().neo4j_csv();
+        let get = |name: &str| {
+            files
+                .iter()
+                .find(|f| f.name == name)
+                .unwrap()
+                .content
+                .clone()
+        };
+        assert_eq!(
+            get("calls.csv"),
+            ":START_ID(Function),:END_ID(Function),count:int,:TYPE\nSquare.area,sq,1,CALLS\n"
+        );
+        assert_eq!(
+            get("extends.csv"),
+            ":START_ID(Class),:END_ID(Class),:TYPE\nSquare,Shape,EXTENDS\n"
+        );
+        assert!(get("member_of.csv").contains("Shape.area,Shape,MEMBER_OF\n"));
+        assert_eq!(
+            get("files.csv"),
+            "id:ID(File),path,:LABEL\nsrc/shapes.ts,src/shapes.ts,File\n"
+        );
+    }
+
+    #[test]
+    fn test_neo4j_cypher() {
+        let cypher = index().neo4j_cypher();
+        assert!(cypher.contains(
+            "CREATE CONSTRAINT function_id IF NOT EXISTS FOR (n:Function) REQUIRE n.id IS UNIQUE;"
+        ));
+        assert!(cypher.contains("{id: 'sq', props: {name: 'sq', class: '', file: 'src/shapes.ts', line: 3, end_line: 3, exported: false, test: false}}"));
+        assert!(cypher.contains("{start: 'Square', end: 'Shape', props: {}}\n] AS row\nMATCH (a:Class {id: row.start}), (b:Class {id: row.end})\nMERGE (a)-[r:EXTENDS]->(b) SET r += row.props;"));
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use super::{one_line, EdgeKind, Subgraph};
+
+/// A quoted label: it cannot hold `"`, `\` starts an escape such as `\n`,
+/// and `~` escapes and `<` opens Creole markup.
+fn label(s: &str) -> String {
+    one_line(s)
+        .replace('\\', "\\\\")
+        .replace('~', "~~")
+        .replace('<', "~<")
+        .replace('"', "'")
+}
+
+/// Renders `graph` as a PlantUML diagram of rectangles and call arrows.
+/// Incomplete graphs get a floating note.
+pub fn to_plantuml(graph: &Subgraph) -> String {
+    let mut out = String::from("@startuml\nleft to right direction\n");
+    let ids: BTreeMap<&String, usize> = graph
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(i, n)| (&n.name, i))
+        .collect();
+    for (i, node) in graph.nodes.iter().enumerate() {
+        let style = if node.defined() { "" } else { " #line.dashed" };
+        let _ = writeln!(
+            out,
+            "rectangle \"{}\" as n{}{}",
+            label(&node.name),
+            i,
+            style
+        );
+    }
+    for edge in graph.edges.iter() {
+        let arrow = match edge.kind {
+            EdgeKind::Call => "-->",
+            EdgeKind::Reference => "..>",
+        };
+        let _ = writeln!(
+            out,
+            "n{} {} n{} : {}",
+            ids[&edge.source], arrow, ids[&edge.target], edge.count
+        );
+    }
+    if let Some(note) = graph.truncation_note() {
+        let _ = writeln!(out, "note \"{}\" as truncated", label(&note));
+    }
+    out += "@enduml\n";
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::Selection;
+    use crate::CodeIndex;
+
+    #[test]
+    fn test_to_plantuml() {
+        let mut index = CodeIndex::new();
+        index.parse_source("a.ts", "function a() { b(); log(); }\nfunction b() {}");
+        let mut graph = index.subgraph(&Selection::All, None).unwrap();
+        graph.truncate(3);
+        assert_eq!(
+            to_plantuml(&graph),
+            "@startuml
+left to right direction
+rectangle \"a\" as n0
+rectangle \"b\" as n1
+rectangle \"log\" as n2 #line.dashed
+n0 --> n2 : 1
+n0 --> n1 : 1
+@enduml
+"
+        );
+    }
+
+    #[test]
+    fn test_plantuml_labels() {
+        let mut index = CodeIndex::new();
+        index.parse_source(
+            "a.ts",
+            "function a() {\n  fetch(url)\n    .then((r) => r.json());\n  (x < y ? f : g)();\n  obj[\"#k\"]();\n}",
+        );
+        let graph = index.subgraph(&Selection::All, None).unwrap();
+        let out = to_plantuml(&graph);
+        assert!(out.contains("rectangle \"fetch(url) .then\" as"), "{}", out);
+        assert!(out.contains("rectangle \"(x ~< y ? f : g)\" as"), "{}", out);
+        assert!(out.contains("rectangle \"obj['#k']\" as"), "{}", out);
+        assert!(out.lines().all(|l| ["@", "left", "rectangle", "n"]
+            .iter()
+            .any(|p| l.starts_with(p))));
+    }
+}
@@ -0,0 +1,220 @@
+//! SCIP index output. The message layout follows `scip.proto`; the few
+//! messages needed are encoded by hand rather than pulling in a protobuf
+//! code generator.
+
+use super::symbols::Document;
+use crate::{CodeIndex, Span};
+
+const SYMBOL_ROLE_DEFINITION: u64 = 1;
+const TEXT_ENCODING_UTF8: u64 = 1;
+const POSITION_ENCODING_UTF16: u64 = 2;
+
+#[derive(Default)]
+struct Message(Vec<u8>);
+
+impl Message {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    fn uint(&mut self, field: u64, value: u64) {
+        if value != 0 {
+            self.varint(field << 3);
+            self.varint(value);
+        }
+    }
+
+    fn bytes(&mut self, field: u64, value: &[u8]) {
+        self.varint((field << 3) | 2);
+        self.varint(value.len() as u64);
+        self.0.extend_from_slice(value);
+    }
+
+    fn string(&mut self, field: u64, value: &str) {
+        if !value.is_empty() {
+            self.bytes(field, value.as_bytes());
+        }
+    }
+
+    fn message(&mut self, field: u64, value: Message) {
+        self.bytes(field, &value.0);
+    }
+
+    fn packed(&mut self, field: u64, values: &[u64]) {
+        let mut packed = Message::default();
+        for value in values {
+            packed.varint(*value);
+        }
+        self.bytes(field, &packed.0);
+    }
+}
+
+/// A descriptor name, backquoted unless it is a plain identifier.
+fn escape(name: &str) -> String {
+    if !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '+' | '-' | '$'))
+    {
+        name.to_string()
+    } else {
+        format!("`{}`", name.replace('`', "``"))
+    }
+}
+
+/// A global SCIP symbol: the file as namespace, then the class as type and
+/// the function as method, e.g. `graphgen . . . src/`a.ts`/Parser#parse().`.
+pub fn scip_symbol(path: &str, name: &str) -> String {
+    let mut symbol = String::from("graphgen . . . ");
+    for dir in path.split('/') {
+        symbol += &escape(dir);
+        symbol.push('/');
+    }
+    match name.split_once('.') {
+        Some((class, method)) if !name.contains(' ') => {
+            symbol += &format!("{}#{}().", escape(class), escape(method));
+        }
+        _ => symbol += &format!("{}().", escape(name)),
+    }
+    symbol
+}
+
+fn range(span: Span) -> Vec<u64> {
+    let line = span.line.saturating_sub(1) as u64;
+    vec![line, span.column as u64, span.end_column as u64]
+}
+
+fn document(doc: &Document, symbols: &std::collections::BTreeMap<&str, String>) -> Message {
+    let mut msg = Message::default();
+    msg.string(1, &doc.path);
+    for def in doc.definitions.iter() {
+        let mut occurrence = Message::default();
+        occurrence.packed(1, &range(def.span));
+        occurrence.string(2, &symbols[def.name.as_str()]);
+        occurrence.uint(3, SYMBOL_ROLE_DEFINITION);
+        msg.message(2, occurrence);
+    }
+    for reference in doc.references.iter() {
+        let mut occurrence = Message::default();
+        occurrence.packed(1, &range(reference.span));
+        occurrence.string(2, &symbols[reference.name.as_str()]);
+        msg.message(2, occurrence);
+    }
+    for def in doc.definitions.iter() {
+        let mut info = Message::default();
+        info.string(1, &symbols[def.name.as_str()]);
+        info.string(3, &def.documentation);
+        info.string(6, &def.name);
+        msg.message(3, info);
+    }
+    msg.string(4, "typescript");
+    msg.uint(6, POSITION_ENCODING_UTF16);
+    msg
+}
+
+/// Encodes the index as a binary SCIP `Index` message.
+pub fn to_scip(index: &CodeIndex) -> Vec<u8> {
+    let documents = index.documents();
+    let symbols = documents
+        .iter()
+        .flat_map(|doc| {
+            doc.definitions
+                .iter()
+                .map(move |def| (def.name.as_str(), scip_symbol(&doc.path, &def.name)))
+        })
+        .collect();
+
+    let mut tool = Message::default();
+    tool.string(1, "graphgen");
+    tool.string(2, env!("CARGO_PKG_VERSION"));
+    let mut metadata = Message::default();
+    metadata.message(2, tool);
+    metadata.string(3, &index.root_uri());
+    metadata.uint(4, TEXT_ENCODING_UTF8);
+
+    let mut msg = Message::default();
+    msg.message(1, metadata);
+    for doc in documents.iter() {
+        msg.message(2, document(doc, &symbols));
+    }
+    msg.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::lsif::to_lsif;
+    use serde_json::Value;
+
+    const SOURCE: &str =
+        "class Parser {\n  parse() { return tokenize(); }\n}\nfunction tokenize() {}\n";
+
+    fn index() -> CodeIndex {
+        let mut index = CodeIndex::new();
+        index.parse_source("src/parser.ts", SOURCE);
+        index
+    }
+
+    #[test]
+    fn test_documents() {
+        let docs = index().documents();
+        assert_eq!(docs.len(), 1);
+        let spans: Vec<_> = docs[0]
+            .definitions
+            .iter()
+            .map(|d| {
+                (
+                    d.name.as_str(),
+                    d.span.line,
+                    d.span.column,
+                    d.span.end_column,
+                )
+            })
+            .collect();
+        assert_eq!(
+            spans,
+            vec![("Parser.parse", 2, 2, 7), ("tokenize", 4, 9, 17)]
+        );
+        assert_eq!(docs[0].references[0].name, "tokenize");
+        assert_eq!(docs[0].references[0].span.column, 19);
+    }
+
+    #[test]
+    fn test_scip_symbol() {
+        assert_eq!(
+            scip_symbol("src/parser.ts", "Parser.parse"),
+            "graphgen . . . src/`parser.ts`/Parser#parse()."
+        );
+        assert_eq!(
+            scip_symbol("a.test.ts", "suite > works"),
+            "graphgen . . . `a.test.ts`/`suite > works`()."
+        );
+        let bytes = to_scip(&index());
+        // Index.metadata is field 1, length delimited.
+        assert_eq!(bytes[0], 0x0a);
+        let needle = b"graphgen . . . src/`parser.ts`/tokenize().";
+        assert!(bytes.windows(needle.len()).any(|w| w == needle));
+    }
+
+    #[test]
+    fn test_lsif() {
+        let lsif = to_lsif(&index());
+        let lines: Vec<Value> = lsif
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines[0]["label"], "metaData");
+        let ranges: Vec<_> = lines.iter().filter(|v| v["label"] == "range").collect();
+        assert_eq!(ranges.len(), 3);
+        assert_eq!(ranges[2]["start"]["character"], 19);
+        let items = lines
+            .iter()
+            .filter(|v| v["label"] == "item" && v["property"] == "references")
+            .count();
+        assert_eq!(items, 1);
+    }
+}
@@ -0,0 +1,257 @@
+//! A small layered layout and SVG renderer, so call trees can be drawn
+//! without a browser or Graphviz.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+
+use super::{xml_escape, EdgeKind, Subgraph};
+
+const FONT_SIZE: f64 = 12.0;
+const CHAR_WIDTH: f64 = 7.2;
+const NODE_HEIGHT: f64 = 26.0;
+const PADDING: f64 = 10.0;
+const COLUMN_GAP: f64 = 60.0;
+const ROW_GAP: f64 = 12.0;
+const MARGIN: f64 = 20.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// Node boxes in `graph.nodes` order, and the size of the whole drawing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layout {
+    pub boxes: Vec<Rect>,
+    pub width: f64,
+    pub height: f64,
+}
+
+fn text_width(s: &str) -> f64 {
+    s.chars().count() as f64 * CHAR_WIDTH + 2.0 * PADDING
+}
+
+/// Assigns each node to a column by its call distance from the roots, nodes
+/// nobody in the graph calls, and keeps the order nodes are reached in within
+/// a column so callees stay next to their callers.
+fn columns(graph: &Subgraph) -> Vec<Vec<usize>> {
+    let index: BTreeMap<&String, usize> = graph
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(i, n)| (&n.name, i))
+        .collect();
+    let mut out: Vec<Vec<usize>> = vec![vec![]; graph.nodes.len()];
+    let mut called = vec![false; graph.nodes.len()];
+    for edge in graph.edges.iter() {
+        let (from, to) = (index[&edge.source], index[&edge.target]);
+        out[from].push(to);
+        called[to] |= from != to;
+    }
+
+    let mut column: Vec<Option<usize>> = vec![None; graph.nodes.len()];
+    let mut columns: Vec<Vec<usize>> = vec![];
+    let roots = (0..graph.nodes.len()).filter(|i| !called[*i]);
+    // Nodes only reachable through cycles start a new tree of their own.
+    for start in roots.chain(0..graph.nodes.len()) {
+        if column[start].is_some() {
+            continue;
+        }
+        column[start] = Some(0);
+        let mut queue = VecDeque::from([start]);
+        while let Some(v) = queue.pop_front() {
+            let c = column[v].unwrap();
+            if columns.len() <= c {
+                columns.push(vec![]);
+            }
+            columns[c].push(v);
+            for w in out[v].iter().copied() {
+                if column[w].is_none() {
+                    column[w] = Some(c + 1);
+                    queue.push_back(w);
+                }
+            }
+        }
+    }
+    columns
+}
+
+pub fn layout(graph: &Subgraph) -> Layout {
+    let columns = columns(graph);
+    let mut boxes = vec![
+        Rect {
+            x: 0.0,
+            y: 0.0,
+            width: 0.0,
+            height: NODE_HEIGHT,
+        };
+        graph.nodes.len()
+    ];
+    let column_height = |c: &Vec<usize>| c.len() as f64 * (NODE_HEIGHT + ROW_GAP) - ROW_GAP;
+    let tallest = columns.iter().map(column_height).fold(0.0, f64::max);
+    let mut x = MARGIN;
+    for column in columns.iter() {
+        let width = column
+            .iter()
+            .map(|i| text_width(&graph.nodes[*i].name))
+            .fold(0.0, f64::max);
+        let mut y = MARGIN + (tallest - column_height(column)) / 2.0;
+        for i in column.iter() {
+            boxes[*i] = Rect {
+                x,
+                y,
+                width,
+                height: NODE_HEIGHT,
+            };
+            y += NODE_HEIGHT + ROW_GAP;
+        }
+        x += width + COLUMN_GAP;
+    }
+    Layout {
+        boxes,
+        width: (x - COLUMN_GAP + MARGIN).max(2.0 * MARGIN),
+        height: tallest.max(0.0) + 2.0 * MARGIN,
+    }
+}
+
+/// Renders `graph` as a standalone SVG document, callers on the left.
+/// Functions outside the project are dashed and reference edges dotted.
+pub fn to_svg(graph: &Subgraph) -> String {
+    let layout = layout(graph);
+    let note = graph.truncation_note();
+    let height = layout.height + if note.is_some() { 20.0 } else { 0.0 };
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\" font-family=\"sans-serif\" font-size=\"{2}\">",
+        layout.width, height, FONT_SIZE
+    );
+    out += "<defs><marker id=\"arrow\" viewBox=\"0 0 10 10\" refX=\"10\" refY=\"5\" markerWidth=\"8\" markerHeight=\"8\" orient=\"auto-start-reverse\"><path d=\"M0,0 L10,5 L0,10 z\" fill=\"#666\"/></marker></defs>\n";
+    let _ = writeln!(
+        out,
+        "<rect width=\"{}\" height=\"{}\" fill=\"#fff\"/>",
+        layout.width, height
+    );
+
+    let index: BTreeMap<&String, usize> = graph
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(i, n)| (&n.name, i))
+        .collect();
+    for edge in graph.edges.iter() {
+        let from = layout.boxes[index[&edge.source]];
+        let to = layout.boxes[index[&edge.target]];
+        let (x1, y1) = (from.x + from.width, from.y + from.height / 2.0);
+        let (x2, y2) = (to.x, to.y + to.height / 2.0);
+        let bend = ((x2 - x1).abs() / 2.0).max(COLUMN_GAP / 2.0);
+        let dash = match edge.kind {
+            EdgeKind::Call => "",
+            EdgeKind::Reference => " stroke-dasharray=\"2 3\"",
+        };
+        let _ = writeln!(
+            out,
+            "<path d=\"M{},{} C{},{} {},{} {},{}\" fill=\"none\" stroke=\"#666\"{} marker-end=\"url(#arrow)\"><title>{} calls</title></path>",
+            x1, y1, x1 + bend, y1, x2 - bend, y2, x2, y2, dash, edge.count
+        );
+    }
+    for (node, rect) in graph.nodes.iter().zip(layout.boxes.iter()) {
+        let style = if node.defined() {
+            "fill=\"#e8f0fe\" stroke=\"#4a6fa5\""
+        } else {
+            "fill=\"#f5f5f5\" stroke=\"#999\" stroke-dasharray=\"4 3\""
+        };
+        let title = if node.defined() {
+            format!("{}:{}", node.file, node.line)
+        } else {
+            node.name.clone()
+        };
+        let _ = writeln!(
+            out,
+            "<g><title>{}</title><rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"4\" {}/><text x=\"{}\" y=\"{}\" dominant-baseline=\"central\">{}</text></g>",
+            xml_escape(&title),
+            rect.x,
+            rect.y,
+            rect.width,
+            rect.height,
+            style,
+            rect.x + PADDING,
+            rect.y + rect.height / 2.0,
+            xml_escape(&node.name)
+        );
+    }
+    if let Some(note) = note {
+        let _ = writeln!(
+            out,
+            "<text x=\"{}\" y=\"{}\" fill=\"#a08000\">{}</text>",
+            MARGIN,
+            height - MARGIN / 2.0,
+            xml_escape(&note)
+        );
+    }
+    out += "</svg>\n";
+    out
+}
+
+/// Rasterises an SVG document produced by `to_svg`, using system fonts for
+/// the labels.
+#[cfg(feature = "png")]
+pub fn svg_to_png(svg: &str) -> Result<Vec<u8>, String> {
+    use resvg::{tiny_skia, usvg};
+
+    let mut options = usvg::Options::default();
+    options.fontdb_mut().load_system_fonts();
+    let tree = usvg::Tree::from_str(svg, &options).map_err(|e| e.to_string())?;
+    let size = tree.size().to_int_size();
+    let mut pixmap = tiny_skia::Pixmap::new(size.width(), size.height())
+        .ok_or_else(|| "empty image".to_string())?;
+    resvg::render(&tree, tiny_skia::Transform::default(), &mut pixmap.as_mut());
+    pixmap.encode_png().map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::Selection;
+    use crate::CodeIndex;
+
+    fn graph() -> Subgraph {
+        let mut index = CodeIndex::new();
+        index.parse_source(
+            "a.ts",
+            "function main() { parse(); render(); }\nfunction parse() { log(); }\nfunction render() { log(); }\nfunction log() {}",
+        );
+        let tree = Selection::Tree {
+            root: "main".to_string(),
+            depth: 4,
+        };
+        index.subgraph(&tree, None).unwrap()
+    }
+
+    #[test]
+    fn test_layout() {
+        let graph = graph();
+        let layout = layout(&graph);
+        let rect =
+            |name: &str| layout.boxes[graph.nodes.iter().position(|n| n.name == name).unwrap()];
+        assert!(rect("main").x < rect("parse").x);
+        assert_eq!(rect("parse").x, rect("render").x);
+        assert!(rect("parse").y != rect("render").y);
+        assert!(rect("render").x < rect("log").x);
+        // The single root is centred against the two callees.
+        assert_eq!(rect("main").y, rect("log").y);
+    }
+
+    #[test]
+    fn test_to_svg() {
+        let svg = to_svg(&graph());
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert_eq!(svg.matches("<rect x=").count(), 4);
+        assert_eq!(svg.matches("marker-end=").count(), 4);
+        assert!(svg.contains(">render</text>"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+}
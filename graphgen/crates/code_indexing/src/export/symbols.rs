@@ -0,0 +1,103 @@
+//! Definition and reference occurrences per file, shared by the LSIF and
+//! SCIP exporters.
+
+use std::collections::BTreeMap;
+
+use crate::{CodeIndex, Function, Span};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    /// Qualified function name, the key in the index.
+    pub name: String,
+    pub span: Span,
+    /// Markdown shown on hover.
+    pub documentation: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Document {
+    /// Path relative to the project directory.
+    pub path: String,
+    pub definitions: Vec<Definition>,
+    /// Call sites resolved to a project function.
+    pub references: Vec<Reference>,
+}
+
+fn documentation(func: &Function) -> String {
+    let kind = if func.test {
+        "test"
+    } else if func.pkg.is_empty() {
+        "function"
+    } else {
+        "method"
+    };
+    format!("```typescript\n({}) {}\n```", kind, func.str())
+}
+
+impl CodeIndex {
+    /// The project root as a `file://` URI, resolved against the working
+    /// directory when the index was built from a relative path.
+    pub fn root_uri(&self) -> String {
+        let root = std::fs::canonicalize(&self.root)
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|_| self.root.clone());
+        format!("file://{}", root.trim_end_matches('/'))
+    }
+
+    pub fn documents(&self) -> Vec<Document> {
+        let mut documents: BTreeMap<&str, Document> = BTreeMap::new();
+        for (key, func) in self.functions.iter() {
+            self.document(&mut documents, &func.file)
+                .definitions
+                .push(Definition {
+                    name: key.clone(),
+                    span: func.name_span,
+                    documentation: documentation(func),
+                });
+        }
+        for (from, sites) in self.call_sites.iter() {
+            let caller = match self.function_by_id(*from) {
+                Some(func) => func,
+                None => continue,
+            };
+            for site in sites {
+                if let Some(callee) = self.id_gen.name(site.callee) {
+                    if self.functions.contains_key(callee) {
+                        self.document(&mut documents, &caller.file)
+                            .references
+                            .push(Reference {
+                                name: callee.clone(),
+                                span: site.span,
+                            });
+                    }
+                }
+            }
+        }
+        let mut documents: Vec<Document> = documents.into_values().collect();
+        for doc in documents.iter_mut() {
+            doc.definitions
+                .sort_by_key(|d| (d.span.line, d.span.column));
+            doc.references.sort_by_key(|r| (r.span.line, r.span.column));
+            doc.references.dedup();
+        }
+        documents
+    }
+
+    fn document<'a, 'b>(
+        &self,
+        documents: &'b mut BTreeMap<&'a str, Document>,
+        file: &'a str,
+    ) -> &'b mut Document {
+        let path = self.relative_path(file);
+        documents.entry(path).or_insert_with(|| Document {
+            path: path.to_string(),
+            ..Document::default()
+        })
+    }
+}
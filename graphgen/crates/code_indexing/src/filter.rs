@@ -0,0 +1,438 @@
+//! Which files under the project directory are indexed, and why the others
+//! are not.
+
+use glob::{Pattern, PatternError};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::rules::PATH_MATCH;
+
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 1024 * 1024;
+
+/// Globs are relative to the project directory, e.g. `src/**/*.ts`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct FileFilter {
+    /// When set, only files matching one of these are indexed.
+    pub include: Vec<String>,
+    /// Files and directories never indexed.
+    pub exclude: Vec<String>,
+    /// Honour `.gitignore` and `.ignore` files.
+    pub gitignore: bool,
+    /// Larger files, usually bundles, are skipped; 0 for no limit.
+    pub max_file_size: u64,
+    /// Skip `*.d.ts`, `*.min.js` and files whose header says they are
+    /// generated.
+    pub skip_generated: bool,
+}
+
+impl Default for FileFilter {
+    fn default() -> Self {
+        FileFilter {
+            include: vec![],
+            exclude: vec![],
+            gitignore: true,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            skip_generated: true,
+        }
+    }
+}
+
+impl FileFilter {
+    pub fn validate(&self) -> Result<(), PatternError> {
+        for p in self.include.iter().chain(self.exclude.iter()) {
+            Pattern::new(p)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(tag = "kind", content = "detail", rename_all = "snake_case")]
+pub enum SkipReason {
+    /// A directory named in `skip_dirs`, e.g. `node_modules`.
+    SkipDir,
+    /// Matched by a `.gitignore` or `.ignore` file.
+    Ignored,
+    /// Matched by this exclude pattern.
+    Excluded(String),
+    NotIncluded,
+    /// Size in bytes.
+    TooLarge(u64),
+    Generated,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SkipReason::SkipDir => write!(f, "skipped directory"),
+            SkipReason::Ignored => write!(f, "ignored by .gitignore"),
+            SkipReason::Excluded(p) => write!(f, "excluded by {}", p),
+            SkipReason::NotIncluded => write!(f, "not included"),
+            SkipReason::TooLarge(size) => write!(f, "too large ({} bytes)", size),
+            SkipReason::Generated => write!(f, "generated"),
+        }
+    }
+}
+
+/// A file or directory left out of the index. Skipped directories are not
+/// descended into, so their files are not listed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Skipped {
+    pub path: String,
+    pub reason: SkipReason,
+}
+
+/// Number of skipped entries per kind of reason.
+pub fn summarize(skipped: &[Skipped]) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for s in skipped {
+        let kind = match &s.reason {
+            SkipReason::TooLarge(_) => "too large".to_string(),
+            reason => reason.to_string(),
+        };
+        *counts.entry(kind).or_insert(0) += 1;
+    }
+    counts
+}
+
+pub(crate) struct Walker<'a> {
+    root: PathBuf,
+    skip_dirs: &'a [String],
+    filter: &'a FileFilter,
+    include: Vec<Pattern>,
+    exclude: Vec<(String, Pattern)>,
+}
+
+impl<'a> Walker<'a> {
+    /// `root` as `CodeIndex::root_path` formats it, so paths match the keys.
+    pub(crate) fn new(root: PathBuf, skip_dirs: &'a [String], filter: &'a FileFilter) -> Self {
+        Walker {
+            root,
+            skip_dirs,
+            filter,
+            include: filter
+                .include
+                .iter()
+                .filter_map(|p| Pattern::new(p).ok())
+                .collect(),
+            exclude: filter
+                .exclude
+                .iter()
+                .filter_map(|p| Pattern::new(p).ok().map(|pattern| (p.clone(), pattern)))
+                .collect(),
+        }
+    }
+
+    /// The TypeScript files to index, in path order.
+    pub(crate) fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub(crate) fn walk(&self, skipped: &mut Vec<Skipped>) -> Vec<String> {
+        let mut files = vec![];
+        let mut ignores = vec![];
+        self.walk_dir(&self.root, &mut ignores, &mut files, skipped);
+        files
+    }
+
+    fn walk_dir(
+        &self,
+        dir: &Path,
+        ignores: &mut Vec<Gitignore>,
+        files: &mut Vec<String>,
+        skipped: &mut Vec<Skipped>,
+    ) {
+        let pushed = self.push_ignores(dir, ignores);
+        let read_dir = if dir.as_os_str().is_empty() {
+            std::fs::read_dir(".")
+        } else {
+            std::fs::read_dir(dir)
+        };
+        let mut entries: Vec<_> = match read_dir {
+            Ok(entries) => entries.filter_map(|e| e.ok()).collect(),
+            Err(e) => {
+                error!("read_dir {} error {:?}", dir.display(), e);
+                vec![]
+            }
+        };
+        entries.sort_by_key(|e| e.file_name());
+        for entry in entries {
+            let path = dir.join(entry.file_name());
+            // symlinked directories are not followed, they may form cycles.
+            let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+            if is_dir {
+                match self.dir_reason(&path, ignores) {
+                    Some(reason) => skipped.push(Skipped {
+                        path: path.display().to_string(),
+                        reason,
+                    }),
+                    None => self.walk_dir(&path, ignores, files, skipped),
+                }
+            } else if is_source(&path) && path.is_file() {
+                match self.file_reason(&path, ignores) {
+                    Some(reason) => skipped.push(Skipped {
+                        path: path.display().to_string(),
+                        reason,
+                    }),
+                    None => files.push(path.display().to_string()),
+                }
+            }
+        }
+        if pushed {
+            ignores.pop();
+        }
+    }
+
+    /// Why the source file `path` would not be indexed, checking each of its
+    /// directories as `walk` does. Paths outside the project are not checked.
+    pub(crate) fn skip_reason(&self, path: &Path) -> Option<SkipReason> {
+        let relative = path.strip_prefix(&self.root).ok()?;
+        let mut ignores = vec![];
+        let mut dir = self.root.clone();
+        self.push_ignores(&dir, &mut ignores);
+        if let Some(parent) = relative.parent() {
+            for component in parent.components() {
+                dir.push(component);
+                if let Some(reason) = self.dir_reason(&dir, &ignores) {
+                    return Some(reason);
+                }
+                self.push_ignores(&dir, &mut ignores);
+            }
+        }
+        self.file_reason(path, &ignores)
+    }
+
+    fn relative<'p>(&self, path: &'p Path) -> &'p Path {
+        path.strip_prefix(&self.root).unwrap_or(path)
+    }
+
+    fn excluded(&self, path: &Path) -> Option<SkipReason> {
+        let relative = self.relative(path).to_str()?;
+        self.exclude
+            .iter()
+            .find(|(_, p)| p.matches_with(relative, PATH_MATCH))
+            .map(|(name, _)| SkipReason::Excluded(name.clone()))
+    }
+
+    fn dir_reason(&self, dir: &Path, ignores: &[Gitignore]) -> Option<SkipReason> {
+        let name = dir.file_name()?.to_str()?;
+        if self.skip_dirs.iter().any(|s| s == name) {
+            return Some(SkipReason::SkipDir);
+        }
+        if is_ignored(ignores, dir, true) {
+            return Some(SkipReason::Ignored);
+        }
+        self.excluded(dir)
+    }
+
+    fn file_reason(&self, path: &Path, ignores: &[Gitignore]) -> Option<SkipReason> {
+        if is_ignored(ignores, path, false) {
+            return Some(SkipReason::Ignored);
+        }
+        if let Some(reason) = self.excluded(path) {
+            return Some(reason);
+        }
+        if !self.include.is_empty() {
+            let relative = self.relative(path).to_str()?;
+            if !self
+                .include
+                .iter()
+                .any(|p| p.matches_with(relative, PATH_MATCH))
+            {
+                return Some(SkipReason::NotIncluded);
+            }
+        }
+        let name = path.file_name()?.to_str()?;
+        if self.filter.skip_generated && (name.ends_with(".d.ts") || name.ends_with(".min.js")) {
+            return Some(SkipReason::Generated);
+        }
+        if self.filter.max_file_size > 0 {
+            let size = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+            if size > self.filter.max_file_size {
+                return Some(SkipReason::TooLarge(size));
+            }
+        }
+        if self.filter.skip_generated && has_generated_header(path) {
+            return Some(SkipReason::Generated);
+        }
+        None
+    }
+
+    fn push_ignores(&self, dir: &Path, ignores: &mut Vec<Gitignore>) -> bool {
+        if !self.filter.gitignore {
+            return false;
+        }
+        let mut builder = GitignoreBuilder::new(dir);
+        let mut found = false;
+        // `.ignore` is added last so it overrides `.gitignore`.
+        for name in [".gitignore", ".ignore"] {
+            let file = dir.join(name);
+            if file.is_file() {
+                if let Some(e) = builder.add(&file) {
+                    error!("{} error {:?}", file.display(), e);
+                }
+                found = true;
+            }
+        }
+        match builder.build() {
+            Ok(gitignore) if found => {
+                ignores.push(gitignore);
+                true
+            }
+            Ok(_) => false,
+            Err(e) => {
+                error!("ignore files in {} error {:?}", dir.display(), e);
+                false
+            }
+        }
+    }
+}
+
+pub(crate) fn is_source(path: &Path) -> bool {
+    // TODO: supports more languages.
+    path.extension().and_then(|e| e.to_str()) == Some("ts")
+}
+
+/// The deepest ignore file with an opinion on `path` decides.
+fn is_ignored(ignores: &[Gitignore], path: &Path, is_dir: bool) -> bool {
+    for gitignore in ignores.iter().rev() {
+        match gitignore.matched(path, is_dir) {
+            Match::Ignore(_) => return true,
+            Match::Whitelist(_) => return false,
+            Match::None => {}
+        }
+    }
+    false
+}
+
+/// Phrases, lowercased, that mark a file as generated in its leading
+/// comments. A bare "generated" is too common in ordinary doc comments.
+const GENERATED_MARKERS: [&str; 4] = [
+    "@generated",
+    "do not edit",
+    "auto-generated",
+    "generated by",
+];
+
+/// Whether the comments opening the file mark it as generated, as in
+/// `// Code generated by protoc-gen-ts. DO NOT EDIT.`
+fn has_generated_header(path: &Path) -> bool {
+    let mut head = vec![0u8; 1024];
+    let read = match std::fs::File::open(path).and_then(|mut f| f.read(&mut head)) {
+        Ok(read) => read,
+        Err(_) => return false,
+    };
+    let head = String::from_utf8_lossy(&head[..read]);
+    head.lines()
+        .map(|l| l.trim())
+        .take_while(|l| {
+            l.is_empty()
+                || l.starts_with("//")
+                || l.starts_with("/*")
+                || l.starts_with('*')
+                || l.starts_with("#!")
+        })
+        .any(|l| {
+            let l = l.to_lowercase();
+            GENERATED_MARKERS.iter().any(|m| l.contains(m))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CodeIndex;
+
+    #[test]
+    fn test_filter_skips_with_reasons() {
+        let dir = std::env::temp_dir().join(format!("graphgen-filter-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let write = |name: &str, content: &str| {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        };
+        write(".gitignore", "dist/\n*.local.ts\n");
+        write("src/main.ts", "function main() {}");
+        write("src/node_modules_helpers/help.ts", "function help() {}");
+        write("src/config.local.ts", "function local() {}");
+        write("src/api.d.ts", "declare function api(): void;");
+        write(
+            "src/proto.ts",
+            "// Code generated by protoc. DO NOT EDIT.\nfunction proto() {}",
+        );
+        write(
+            "src/user.ts",
+            "/**\n * Handles user-generated content.\n * @returns a generated id\n */\nfunction user() {}",
+        );
+        write("src/schema.ts", "/* @generated */\nfunction schema() {}");
+        write(
+            "src/big.ts",
+            &format!("function big() {{}}\n{}", " ".repeat(2048)),
+        );
+        write("src/legacy/old.ts", "function old() {}");
+        write("dist/out.ts", "function out() {}");
+        write("node_modules/lib/index.ts", "function lib() {}");
+
+        let mut index = CodeIndex::new();
+        index
+            .set_filter(FileFilter {
+                exclude: vec!["src/legacy".to_string()],
+                max_file_size: 1024,
+                ..FileFilter::default()
+            })
+            .unwrap();
+        index.parse_project(&dir.display().to_string()).unwrap();
+        assert_eq!(index.function_list(), vec!["help", "main", "user"]);
+
+        let reasons: BTreeMap<String, SkipReason> = index
+            .skipped()
+            .iter()
+            .map(|s| {
+                let path = Path::new(&s.path).strip_prefix(&dir).unwrap();
+                (path.display().to_string(), s.reason.clone())
+            })
+            .collect();
+        assert_eq!(reasons["dist"], SkipReason::Ignored);
+        assert_eq!(reasons["node_modules"], SkipReason::SkipDir);
+        assert_eq!(reasons["src/config.local.ts"], SkipReason::Ignored);
+        assert_eq!(reasons["src/api.d.ts"], SkipReason::Generated);
+        assert_eq!(reasons["src/proto.ts"], SkipReason::Generated);
+        assert_eq!(reasons["src/schema.ts"], SkipReason::Generated);
+        assert!(matches!(reasons["src/big.ts"], SkipReason::TooLarge(_)));
+        assert_eq!(
+            reasons["src/legacy"],
+            SkipReason::Excluded("src/legacy".to_string())
+        );
+        assert_eq!(summarize(index.skipped())["generated"], 3);
+
+        // a file that becomes ignored is dropped on update.
+        write(".gitignore", "dist/\n*.local.ts\nsrc/main.ts\n");
+        let stats = index.update_files(vec![dir.join("src/main.ts").display().to_string()]);
+        assert_eq!(stats.removed, 1);
+        assert_eq!(index.function_list(), vec!["help", "user"]);
+
+        index
+            .set_filter(FileFilter {
+                include: vec!["src/**/*.ts".to_string()],
+                gitignore: false,
+                skip_generated: false,
+                max_file_size: 0,
+                ..FileFilter::default()
+            })
+            .unwrap();
+        index.reindex();
+        assert_eq!(
+            index.function_list(),
+            vec!["big", "help", "local", "main", "old", "proto", "schema", "user"]
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
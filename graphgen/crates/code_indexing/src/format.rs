@@ -0,0 +1,330 @@
+//! The on-disk index file: magic bytes, a bincode `IndexHeader`, then the
+//! bincode `CodeIndex`. Files from before the header existed are format 0;
+//! older formats are migrated on load.
+
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::callees::CallKind;
+use crate::{CallSite, Class, CodeIndex, Function, IDGenerator, Span};
+
+pub const MAGIC: &[u8; 8] = b"GRAPHGEN";
+/// Bump whenever a serialized struct changes, and add a migration.
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IndexHeader {
+    pub format_version: u32,
+    /// Version of the graphgen build that wrote the file.
+    pub tool_version: String,
+    pub root: String,
+    /// Seconds since the Unix epoch.
+    pub created: u64,
+}
+
+#[derive(Debug)]
+pub enum IndexError {
+    Io(std::io::Error),
+    /// Neither a graphgen index nor a headerless legacy one.
+    NotAnIndex,
+    /// Written by a newer graphgen.
+    UnsupportedVersion(u32),
+    Corrupt(bincode::Error),
+    /// A section of a memory-mapped index lies outside the file.
+    Malformed(String),
+}
+
+impl fmt::Display for IndexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IndexError::Io(e) => write!(f, "{}", e),
+            IndexError::NotAnIndex => write!(f, "not a graphgen index file"),
+            IndexError::UnsupportedVersion(v) => write!(
+                f,
+                "index format {} is newer than the supported format {}",
+                v, FORMAT_VERSION
+            ),
+            IndexError::Corrupt(e) => write!(f, "corrupt index file: {}", e),
+            IndexError::Malformed(e) => write!(f, "malformed index file: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for IndexError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            IndexError::Io(e) => Some(e),
+            IndexError::Corrupt(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for IndexError {
+    fn from(e: std::io::Error) -> Self {
+        IndexError::Io(e)
+    }
+}
+
+impl From<bincode::Error> for IndexError {
+    fn from(e: bincode::Error) -> Self {
+        match *e {
+            bincode::ErrorKind::Io(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                IndexError::Corrupt(Box::new(bincode::ErrorKind::Io(e)))
+            }
+            bincode::ErrorKind::Io(e) => IndexError::Io(e),
+            _ => IndexError::Corrupt(e),
+        }
+    }
+}
+
+/// The index as written before the header, when functions and classes had
+/// no locations and calls no positions.
+#[derive(Deserialize)]
+struct FunctionV0 {
+    name: String,
+    pkg: String,
+    body: String,
+}
+
+#[derive(Deserialize)]
+struct ClassV0 {
+    name: String,
+    declaration: String,
+}
+
+#[derive(Deserialize)]
+struct CodeIndexV0 {
+    edges: BTreeMap<u64, Vec<u64>>,
+    functions: BTreeMap<String, FunctionV0>,
+    classes: BTreeMap<String, ClassV0>,
+    skip_dirs: Vec<String>,
+    id_gen: IDGenerator,
+}
+
+impl From<CodeIndexV0> for CodeIndex {
+    fn from(old: CodeIndexV0) -> Self {
+        let mut index = CodeIndex::new();
+        index.skip_dirs = old.skip_dirs;
+        index.id_gen = old.id_gen;
+        index.call_sites = old
+            .edges
+            .iter()
+            .map(|(from, targets)| {
+                let sites = targets
+                    .iter()
+                    .map(|to| CallSite {
+                        callee: *to,
+                        span: Span::default(),
+                        kind: CallKind::Dynamic,
+                    })
+                    .collect();
+                (*from, sites)
+            })
+            .collect();
+        index.edges = old.edges;
+        for (key, func) in old.functions {
+            index.id_gen.id(&key);
+            index.functions.insert(
+                key,
+                Function {
+                    test: false,
+                    name: func.name,
+                    pkg: func.pkg,
+                    file: String::new(),
+                    line: 0,
+                    end_line: 0,
+                    name_span: Span::default(),
+                    exported: false,
+                    body: func.body,
+                    package: String::new(),
+                },
+            );
+        }
+        for (key, class) in old.classes {
+            index.classes.insert(
+                key,
+                Class {
+                    name: class.name,
+                    file: String::new(),
+                    extends: None,
+                    declaration: class.declaration,
+                    package: String::new(),
+                },
+            );
+        }
+        index
+    }
+}
+
+/// The encoding `bincode::serialize_into` writes, refusing any length that
+/// would read past the end of a file of `len` bytes instead of allocating it.
+fn limited(len: u64) -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(len)
+}
+
+impl CodeIndex {
+    pub fn load(filename: &str) -> Result<Self, IndexError> {
+        let file = std::fs::File::open(filename)?;
+        let len = file.metadata()?.len();
+        let mut file = BufReader::new(file);
+        let mut magic = [0u8; 8];
+        let has_magic = match file.read_exact(&mut magic) {
+            Ok(_) => &magic == MAGIC,
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => false,
+            Err(e) => return Err(e.into()),
+        };
+        if !has_magic {
+            return load_v0(filename);
+        }
+        let header: IndexHeader = limited(len).deserialize_from(&mut file)?;
+        match header.format_version {
+            FORMAT_VERSION => Ok(limited(len).deserialize_from(&mut file)?),
+            v => Err(IndexError::UnsupportedVersion(v)),
+        }
+    }
+
+    /// Reads only the header, e.g. to check an index is current without
+    /// loading it. Legacy files have no header.
+    pub fn read_header(filename: &str) -> Result<IndexHeader, IndexError> {
+        let file = std::fs::File::open(filename)?;
+        let len = file.metadata()?.len();
+        let mut file = BufReader::new(file);
+        let mut magic = [0u8; 8];
+        file.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(IndexError::NotAnIndex);
+        }
+        Ok(limited(len).deserialize_from(&mut file)?)
+    }
+
+    /// Writes the index next to `filename` and renames it into place, so a
+    /// failed write never leaves a truncated index behind.
+    pub fn into_file(&self, filename: &str) -> Result<(), IndexError> {
+        let header = IndexHeader {
+            format_version: FORMAT_VERSION,
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            root: self.root.clone(),
+            created: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        };
+        let tmp = format!("{}.tmp", filename);
+        let mut file = BufWriter::new(std::fs::File::create(&tmp)?);
+        file.write_all(MAGIC)?;
+        bincode::serialize_into(&mut file, &header)?;
+        bincode::serialize_into(&mut file, self)?;
+        file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        std::fs::rename(&tmp, filename)?;
+        Ok(())
+    }
+}
+
+fn load_v0(filename: &str) -> Result<CodeIndex, IndexError> {
+    let buffer = std::fs::read(filename)?;
+    match limited(buffer.len() as u64).deserialize::<CodeIndexV0>(&buffer) {
+        Ok(old) => Ok(old.into()),
+        Err(_) => Err(IndexError::NotAnIndex),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_file(name: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("graphgen-{}-{}.bin", name, std::process::id()));
+        path.display().to_string()
+    }
+
+    #[test]
+    fn test_header_and_errors() {
+        let mut index = CodeIndex::new();
+        index.parse_source("a.ts", "function a() { b(); }\nfunction b() {}");
+        let path = temp_file("header");
+        index.into_file(&path).unwrap();
+        let header = CodeIndex::read_header(&path).unwrap();
+        assert_eq!(header.format_version, FORMAT_VERSION);
+        assert_eq!(
+            CodeIndex::load(&path).unwrap().function_list(),
+            vec!["a", "b"]
+        );
+
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - 4]).unwrap();
+        assert!(matches!(
+            CodeIndex::load(&path),
+            Err(IndexError::Corrupt(_))
+        ));
+
+        let mut newer = MAGIC.to_vec();
+        newer.extend(
+            bincode::serialize(&IndexHeader {
+                format_version: 99,
+                ..header.clone()
+            })
+            .unwrap(),
+        );
+        std::fs::write(&path, newer).unwrap();
+        assert!(matches!(
+            CodeIndex::load(&path),
+            Err(IndexError::UnsupportedVersion(99))
+        ));
+
+        // a length far past the end of the file is refused, not allocated.
+        let mut huge = MAGIC.to_vec();
+        huge.extend(bincode::serialize(&header).unwrap());
+        huge.extend(u64::MAX.to_le_bytes());
+        std::fs::write(&path, huge).unwrap();
+        assert!(matches!(
+            CodeIndex::load(&path),
+            Err(IndexError::Corrupt(_))
+        ));
+
+        std::fs::write(&path, "not an index").unwrap();
+        assert!(matches!(
+            CodeIndex::load(&path),
+            Err(IndexError::NotAnIndex)
+        ));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_migrate_v0() {
+        #[derive(Serialize)]
+        struct Legacy {
+            edges: BTreeMap<u64, Vec<u64>>,
+            functions: BTreeMap<String, (String, String, String)>,
+            classes: BTreeMap<String, (String, String)>,
+            skip_dirs: Vec<String>,
+            id_gen: IDGenerator,
+        }
+        let mut id_gen = IDGenerator::new();
+        let (a, b) = (id_gen.id("a"), id_gen.id("b"));
+        let legacy = Legacy {
+            edges: BTreeMap::from([(a, vec![b])]),
+            functions: BTreeMap::from([(
+                "a".to_string(),
+                ("a".to_string(), String::new(), "{ b(); }".to_string()),
+            )]),
+            classes: BTreeMap::new(),
+            skip_dirs: vec![],
+            id_gen,
+        };
+        let path = temp_file("legacy");
+        std::fs::write(&path, bincode::serialize(&legacy).unwrap()).unwrap();
+        let index = CodeIndex::load(&path).unwrap();
+        assert_eq!(index.function_list(), vec!["a"]);
+        assert_eq!(index.call_sites[&a][0].callee, b);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -5,4 +5,6 @@ pub struct GraphNode {
     pub name: String,
     pub children: Vec<GraphNode>,
     pub value: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metric: Option<f64>,
 }
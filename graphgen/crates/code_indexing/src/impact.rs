@@ -0,0 +1,393 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::path::Path;
+use std::process::Command;
+
+use crate::CodeIndex;
+
+/// Lines touched in one file, numbered as in the new side of the diff.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FileChange {
+    pub path: String,
+    /// Inclusive, sorted, non-overlapping line ranges.
+    pub ranges: Vec<(usize, usize)>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ImpactedFunction {
+    pub name: String,
+    pub file: String,
+    pub line: usize,
+    /// 0 for changed functions, otherwise the call distance to the nearest one.
+    pub depth: usize,
+    /// The function this one calls on its way to a change.
+    pub via: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ImpactReport {
+    pub changed: Vec<ImpactedFunction>,
+    pub affected: Vec<ImpactedFunction>,
+}
+
+impl ImpactReport {
+    pub fn to_markdown(&self) -> String {
+        let mut out = format!(
+            "**{} changed functions, {} affected callers**\n\n",
+            self.changed.len(),
+            self.affected.len()
+        );
+        out += "| function | file | depth | via |\n|---|---|---|---|\n";
+        for f in self.changed.iter().chain(self.affected.iter()) {
+            out += &format!(
+                "| `{}` | {}:{} | {} | {} |\n",
+                f.name,
+                f.file,
+                f.line,
+                f.depth,
+                f.via
+                    .as_ref()
+                    .map(|v| format!("`{}`", v))
+                    .unwrap_or_default()
+            );
+        }
+        out
+    }
+}
+
+/// Parses `git diff` style unified diff text into per-file changed lines.
+/// Deleted lines are attributed to the new-side line where they used to be.
+pub fn parse_unified_diff(diff: &str) -> Vec<FileChange> {
+    let mut changes: Vec<FileChange> = vec![];
+    let mut lines: BTreeSet<usize> = BTreeSet::new();
+    let mut path: Option<String> = None;
+    let mut new_line = 0;
+    // lines of the current hunk still to come, on the old and new side;
+    // within a hunk `--- ` and `+++ ` are changed lines, not headers.
+    let (mut old_left, mut new_left) = (0, 0);
+
+    let mut flush = |path: &Option<String>, lines: &mut BTreeSet<usize>| {
+        if let Some(path) = path {
+            if !lines.is_empty() {
+                changes.push(FileChange {
+                    path: path.clone(),
+                    ranges: to_ranges(lines),
+                });
+            }
+        }
+        lines.clear();
+    };
+
+    for line in diff.lines() {
+        let hunk_line = match line.chars().next() {
+            // "\ No newline at end of file"
+            Some('\\') => true,
+            Some('+') => new_left > 0,
+            Some('-') => old_left > 0,
+            _ => old_left > 0 && new_left > 0,
+        };
+        if !hunk_line {
+            // a hunk ends after the lines its header counts; lines it did
+            // not count make it malformed, and are read as headers.
+            (old_left, new_left) = (0, 0);
+            if let Some(target) = line.strip_prefix("+++ ") {
+                flush(&path, &mut lines);
+                let target = target.split('\t').next().unwrap_or(target);
+                path = match target {
+                    "/dev/null" => None,
+                    _ => Some(target.strip_prefix("b/").unwrap_or(target).to_string()),
+                };
+            } else if let Some(header) = line.strip_prefix("@@ ") {
+                (new_line, old_left, new_left) = hunk_range(header).unwrap_or((1, 0, 0));
+            }
+            continue;
+        }
+        match line.chars().next() {
+            Some('\\') => {}
+            Some('+') => {
+                lines.insert(new_line);
+                new_line += 1;
+                new_left -= 1;
+            }
+            Some('-') => {
+                lines.insert(new_line.max(1));
+                old_left -= 1;
+            }
+            _ => {
+                new_line += 1;
+                old_left -= 1;
+                new_left -= 1;
+            }
+        }
+    }
+    flush(&path, &mut lines);
+    changes
+}
+
+/// The first new-side line of a hunk, and how many old and new lines it has.
+fn hunk_range(header: &str) -> Option<(usize, usize, usize)> {
+    let range = |prefix: char| -> Option<(usize, usize)> {
+        let range = header.split_whitespace().find(|s| s.starts_with(prefix))?;
+        let mut parts = range[1..].split(',');
+        let start = parts.next()?.parse().ok()?;
+        let len = match parts.next() {
+            Some(len) => len.parse().ok()?,
+            None => 1,
+        };
+        Some((start, len))
+    };
+    let (_, old_len) = range('-')?;
+    let (new_start, new_len) = range('+')?;
+    Some((new_start, old_len, new_len))
+}
+
+fn to_ranges(lines: &BTreeSet<usize>) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = vec![];
+    for line in lines.iter().copied() {
+        match ranges.last_mut() {
+            Some(last) if last.1 + 1 >= line => last.1 = line,
+            _ => ranges.push((line, line)),
+        }
+    }
+    ranges
+}
+
+/// Runs `git diff` between two revisions in `repo`. An empty `head` diffs
+/// against the working tree. Revisions that are no commit, or could be
+/// taken for an option, are `InvalidInput` errors.
+pub fn git_diff(repo: &str, base: &str, head: &str) -> std::io::Result<String> {
+    for rev in [base, head].into_iter().filter(|r| !r.is_empty()) {
+        verify_revision(repo, rev)?;
+    }
+    let mut cmd = Command::new("git");
+    cmd.arg("-C")
+        .arg(repo)
+        .arg("diff")
+        .arg("--unified=0")
+        .arg("--end-of-options")
+        .arg(base);
+    if !head.is_empty() {
+        cmd.arg(head);
+    }
+    git_output(cmd)
+}
+
+fn verify_revision(repo: &str, rev: &str) -> std::io::Result<()> {
+    let invalid = || {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("invalid revision {}", rev),
+        )
+    };
+    if rev.starts_with('-') {
+        return Err(invalid());
+    }
+    let mut cmd = Command::new("git");
+    cmd.arg("-C")
+        .arg(repo)
+        .arg("rev-parse")
+        .arg("--quiet")
+        .arg("--verify")
+        .arg("--end-of-options")
+        .arg(format!("{}^{{commit}}", rev));
+    git_output(cmd).map(|_| ()).map_err(|_| invalid())
+}
+
+fn git_output(mut cmd: Command) -> std::io::Result<String> {
+    let output = cmd.output()?;
+    if !output.status.success() {
+        return Err(std::io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Diff paths are repository relative while indexed files carry the project
+/// directory prefix, so compare whole trailing path components.
+fn same_file(indexed: &str, diff_path: &str) -> bool {
+    Path::new(indexed).ends_with(Path::new(diff_path))
+}
+
+impl CodeIndex {
+    pub fn impact(&self, changes: &[FileChange], depth: usize) -> ImpactReport {
+        let mut report = ImpactReport::default();
+        let mut seen: BTreeMap<&String, usize> = BTreeMap::new();
+        let mut queue = VecDeque::new();
+        for (key, func) in self.functions.iter() {
+            let touched = changes.iter().any(|change| {
+                same_file(&func.file, &change.path)
+                    && change
+                        .ranges
+                        .iter()
+                        .any(|(start, end)| *start <= func.end_line && *end >= func.line)
+            });
+            if touched {
+                seen.insert(key, 0);
+                queue.push_back(key);
+                report.changed.push(self.impacted(key, 0, None));
+            }
+        }
+
+        let callers = self.callers();
+        while let Some(key) = queue.pop_front() {
+            let distance = seen[key];
+            if distance >= depth {
+                continue;
+            }
+            for caller in callers.get(key).into_iter().flatten() {
+                if seen.contains_key(caller) {
+                    continue;
+                }
+                seen.insert(caller, distance + 1);
+                queue.push_back(caller);
+                report
+                    .affected
+                    .push(self.impacted(caller, distance + 1, Some(key.clone())));
+            }
+        }
+        report
+    }
+
+    /// Reverse call edges between project functions, keyed by callee.
+    pub(crate) fn callers(&self) -> BTreeMap<&String, BTreeSet<&String>> {
+        let mut callers: BTreeMap<&String, BTreeSet<&String>> = BTreeMap::new();
+        for (from, targets) in self.edges.iter() {
+            let from = match self.id_gen.name(*from) {
+                Some(from) if self.functions.contains_key(from) => from,
+                _ => continue,
+            };
+            for to in targets {
+                if let Some(to) = self.id_gen.name(*to) {
+                    if self.functions.contains_key(to) {
+                        callers.entry(to).or_default().insert(from);
+                    }
+                }
+            }
+        }
+        callers
+    }
+
+    fn impacted(&self, key: &str, depth: usize, via: Option<String>) -> ImpactedFunction {
+        let func = &self.functions[key];
+        ImpactedFunction {
+            name: key.to_string(),
+            file: func.file.clone(),
+            line: func.line,
+            depth,
+            via,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "function leaf() {
+    return 1;
+}
+function middle() {
+    return leaf();
+}
+function top() {
+    middle();
+}
+function other() {}
+";
+
+    const DIFF: &str = "diff --git a/src/lib.ts b/src/lib.ts
+index 1111111..2222222 100644
+--- a/src/lib.ts
++++ b/src/lib.ts
+@@ -1,3 +1,3 @@
+ function leaf() {
+-    return 0;
++    return 1;
+ }
+";
+
+    #[test]
+    fn test_parse_unified_diff() {
+        let changes = parse_unified_diff(DIFF);
+        assert_eq!(
+            changes,
+            vec![FileChange {
+                path: "src/lib.ts".to_string(),
+                ranges: vec![(2, 2)],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_hunk_lines_like_headers() {
+        let diff = "--- a/a.sql
++++ b/a.sql
+@@ -2 +2 @@
+--- old comment
++++ new counter
+@@ -10 +10,0 @@
+-x
+";
+        assert_eq!(
+            parse_unified_diff(diff),
+            vec![FileChange {
+                path: "a.sql".to_string(),
+                ranges: vec![(2, 2), (10, 10)],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_malformed_hunk() {
+        // the first hunk counts no new line, so `+x` ends it and the lines
+        // up to the next file are not read as changes.
+        let diff = "--- a/a.ts
++++ b/a.ts
+@@ -1 +1,0 @@
++x
+-y
+-z
+--- a/b.ts
++++ b/b.ts
+@@ -3 +3 @@
+-old
++new
+";
+        assert_eq!(
+            parse_unified_diff(diff),
+            vec![FileChange {
+                path: "b.ts".to_string(),
+                ranges: vec![(3, 3)],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_git_diff_rejects_options() {
+        let err = git_diff(".", "--output=/tmp/graphgen-impact", "").unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        let err = git_diff(".", "HEAD", "-p").unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(!Path::new("/tmp/graphgen-impact").exists());
+    }
+
+    #[test]
+    fn test_impact() {
+        let mut index = CodeIndex::new();
+        index.parse_source("/work/project/src/lib.ts", SOURCE);
+        let changes = parse_unified_diff(DIFF);
+
+        let report = index.impact(&changes, 10);
+        assert_eq!(report.changed.len(), 1);
+        assert_eq!(report.changed[0].name, "leaf");
+        let affected: Vec<_> = report
+            .affected
+            .iter()
+            .map(|f| (f.name.as_str(), f.depth))
+            .collect();
+        assert_eq!(affected, vec![("middle", 1), ("top", 2)]);
+
+        assert_eq!(index.impact(&changes, 1).affected.len(), 1);
+    }
+}
@@ -0,0 +1,773 @@
+use glob::PatternError;
+use log::{info, warn};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Component, Path, PathBuf};
+
+use crate::diagnostics::read_source;
+use crate::filter::{is_source, summarize, FileFilter, SkipReason, Skipped, Walker};
+use crate::resolve::{Resolver, PACKAGE_TAG};
+use crate::{CallSite, CodeIndex};
+
+/// What one file contributed to the index, so it can be taken out again.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct FileRecord {
+    hash: u64,
+    // the workspace package or project root of the file.
+    package: String,
+    // names of the functions defined here, including ones another file
+    // defined first, see `Definers::key`; keys of the classes.
+    functions: Vec<String>,
+    classes: Vec<String>,
+    calls: BTreeMap<u64, Vec<CallSite>>,
+    refs: BTreeMap<u64, Vec<u64>>,
+    routes: Vec<u64>,
+    // the file each import specifier resolved to, `None` if to none.
+    imports: BTreeMap<String, Option<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReindexStats {
+    pub added: usize,
+    pub changed: usize,
+    pub removed: usize,
+    pub unchanged: usize,
+    /// Files that could not be read, see `CodeIndex::diagnostics`.
+    pub failed: usize,
+}
+
+impl FileRecord {
+    /// The same record with every id passed through `id`, for an index
+    /// whose ids were handed out again.
+    pub(crate) fn renumbered(&self, id: impl Fn(u64) -> u64) -> FileRecord {
+        FileRecord {
+            hash: self.hash,
+            package: self.package.clone(),
+            functions: self.functions.clone(),
+            classes: self.classes.clone(),
+            calls: self
+                .calls
+                .iter()
+                .map(|(from, sites)| {
+                    let sites = sites
+                        .iter()
+                        .map(|site| CallSite {
+                            callee: id(site.callee),
+                            ..site.clone()
+                        })
+                        .collect();
+                    (id(*from), sites)
+                })
+                .collect(),
+            refs: self
+                .refs
+                .iter()
+                .map(|(from, targets)| (id(*from), targets.iter().map(|t| id(*t)).collect()))
+                .collect(),
+            routes: self.routes.iter().map(|r| id(*r)).collect(),
+            imports: self.imports.clone(),
+        }
+    }
+
+    /// Whether the file calls, references or routes to any of `ids`.
+    fn uses(&self, ids: &BTreeSet<u64>) -> bool {
+        let calls = self.calls.iter().flat_map(|(from, sites)| {
+            std::iter::once(*from).chain(sites.iter().map(|site| site.callee))
+        });
+        let refs = self
+            .refs
+            .iter()
+            .flat_map(|(from, targets)| std::iter::once(*from).chain(targets.iter().copied()));
+        calls
+            .chain(refs)
+            .chain(self.routes.iter().copied())
+            .any(|id| ids.contains(&id))
+    }
+}
+
+/// The files, and their packages, defining each function name. A name
+/// defined in several packages keeps its plain key for the package of the
+/// first file by path, and is keyed `name (package)` in the others, so
+/// calls within each package and through imports stay apart.
+#[derive(Debug, Clone, Default)]
+struct Definers(BTreeMap<String, BTreeMap<String, String>>);
+
+impl Definers {
+    fn add_file<'a>(&mut self, path: &str, package: &str, names: impl Iterator<Item = &'a String>) {
+        for name in names {
+            self.0
+                .entry(name.clone())
+                .or_default()
+                .insert(path.to_string(), package.to_string());
+        }
+    }
+
+    fn remove_files(&mut self, paths: &BTreeSet<String>) {
+        for files in self.0.values_mut() {
+            files.retain(|path, _| !paths.contains(path));
+        }
+        self.0.retain(|_, files| !files.is_empty());
+    }
+
+    /// The key of `name` as defined, or else called, in `package`.
+    fn key(&self, name: &str, package: &str) -> String {
+        let packages = match self.0.get(name) {
+            Some(files) => files.values(),
+            None => return name.to_string(),
+        };
+        let owner = packages.clone().next();
+        if owner.is_some_and(|owner| owner != package) && packages.clone().any(|p| p == package) {
+            return format!("{} ({})", name, package);
+        }
+        name.to_string()
+    }
+
+    /// Every key `name` has.
+    fn keys(&self, name: &str) -> BTreeSet<String> {
+        let packages = self
+            .0
+            .get(name)
+            .into_iter()
+            .flat_map(|files| files.values());
+        packages.map(|package| self.key(name, package)).collect()
+    }
+
+    /// Names whose keys differ between `self` and `other`.
+    fn changed_names(&self, other: &Definers) -> BTreeSet<String> {
+        let names = self.0.keys().chain(other.0.keys());
+        names
+            .filter(|name| self.keys(name) != other.keys(name))
+            .cloned()
+            .collect()
+    }
+}
+
+/// A file parsed on its own, to be merged into the index.
+struct ParsedFile {
+    path: String,
+    hash: u64,
+    package: String,
+    index: CodeIndex,
+    imports: BTreeMap<String, Option<String>>,
+}
+
+/// FNV-1a, which unlike `DefaultHasher` is stable across Rust releases.
+pub(crate) fn content_hash(content: &str) -> u64 {
+    content.bytes().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+impl CodeIndex {
+    /// Which files `reindex` looks at; invalid patterns are rejected.
+    pub fn set_filter(&mut self, filter: FileFilter) -> Result<(), PatternError> {
+        filter.validate()?;
+        self.filter = filter;
+        Ok(())
+    }
+
+    /// Import path aliases, as in tsconfig's `compilerOptions.paths`, that
+    /// take precedence over the project's tsconfig.json.
+    pub fn set_path_aliases(&mut self, aliases: BTreeMap<String, Vec<String>>) {
+        self.path_aliases = aliases;
+    }
+
+    /// More project directories to index along with the one passed to
+    /// `parse_project`, e.g. the libraries of a multi-root workspace.
+    pub fn set_extra_roots(&mut self, roots: Vec<String>) {
+        self.extra_roots = roots;
+    }
+
+    /// The project directory and the extra roots.
+    pub fn roots(&self) -> Vec<&str> {
+        std::iter::once(self.root.as_str())
+            .chain(self.extra_roots.iter().map(|r| r.as_str()))
+            .collect()
+    }
+
+    /// Project files in the index, in path order.
+    pub fn indexed_files(&self) -> Vec<&str> {
+        self.files.keys().map(|f| f.as_str()).collect()
+    }
+
+    /// What the last `reindex` left out, and why.
+    pub fn skipped(&self) -> &[Skipped] {
+        &self.skipped
+    }
+
+    /// The project directories as the keys of `files` start: glob, which
+    /// found them originally, drops `.` components and repeated separators.
+    fn root_paths(&self) -> Vec<PathBuf> {
+        self.roots()
+            .into_iter()
+            .map(|root| {
+                Path::new(root)
+                    .components()
+                    .filter(|c| *c != Component::CurDir)
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn walkers(&self) -> Vec<Walker<'_>> {
+        self.root_paths()
+            .into_iter()
+            .map(|root| Walker::new(root, &self.skip_dirs, &self.filter))
+            .collect()
+    }
+
+    /// The key `reindex` uses for `path`, e.g. one reported by a file
+    /// watcher, or `None` if it is not a source file in the project. `path`
+    /// may be absolute or relative to the project directory and need not
+    /// exist. Whether the filter skips it is decided by `update_files`.
+    pub fn project_file_key(&self, path: &Path) -> Option<String> {
+        if !is_source(path) {
+            return None;
+        }
+        if !path.is_absolute() {
+            return Some(self.root_paths()[0].join(path).display().to_string());
+        }
+        // the innermost root holding `path`, as roots may nest.
+        let mut keys: Vec<(usize, PathBuf)> = vec![];
+        for (root, key_root) in self.roots().into_iter().zip(self.root_paths()) {
+            let root = Path::new(root);
+            let relative = match std::fs::canonicalize(root) {
+                Ok(canonical) if path.starts_with(&canonical) => path.strip_prefix(canonical),
+                _ => path.strip_prefix(root),
+            };
+            if let Ok(relative) = relative {
+                keys.push((relative.components().count(), key_root.join(relative)));
+            }
+        }
+        keys.into_iter()
+            .min_by_key(|(depth, _)| *depth)
+            .map(|(_, key)| key.display().to_string())
+    }
+
+    /// Re-parses the project files that were added, changed or deleted since
+    /// the last call. The result is the same as parsing everything again.
+    pub fn reindex(&mut self) -> ReindexStats {
+        let mut skipped = vec![];
+        let files: BTreeSet<String> = self
+            .walkers()
+            .iter()
+            .flat_map(|walker| walker.walk(&mut skipped))
+            .collect();
+        if !skipped.is_empty() {
+            info!("Skipped {:?}", summarize(&skipped));
+        }
+        self.skipped = skipped;
+        // including files that failed to read last time.
+        let gone: BTreeSet<String> = self
+            .files
+            .keys()
+            .chain(self.diagnostics.keys())
+            .filter(|f| !files.contains(*f))
+            .cloned()
+            .collect();
+        self.apply(files.into_iter().chain(gone), &BTreeSet::new())
+    }
+
+    /// Re-reads `paths`, e.g. from file change notifications. Paths that no
+    /// longer exist, or that the filter now skips, are removed from the
+    /// index.
+    pub fn update_files(&mut self, paths: impl IntoIterator<Item = String>) -> ReindexStats {
+        let mut indexed = vec![];
+        let mut dropped = BTreeSet::new();
+        for path in paths {
+            self.skipped.retain(|s| s.path != path);
+            match self.skip_reason(Path::new(&path)) {
+                Some(reason) => {
+                    self.skipped.push(Skipped {
+                        path: path.clone(),
+                        reason,
+                    });
+                    dropped.insert(path.clone());
+                    indexed.push(path);
+                }
+                None => indexed.push(path),
+            }
+        }
+        self.apply(indexed, &dropped)
+    }
+
+    /// Why the walker of the innermost root holding `path` would skip it.
+    fn skip_reason(&self, path: &Path) -> Option<SkipReason> {
+        let walkers = self.walkers();
+        walkers
+            .iter()
+            .filter(|w| path.starts_with(w.root()))
+            .max_by_key(|w| w.root().components().count())
+            .unwrap_or(&walkers[0])
+            .skip_reason(path)
+    }
+
+    /// Re-reads `paths`, except `dropped` which are taken out of the index.
+    fn apply(
+        &mut self,
+        paths: impl IntoIterator<Item = String>,
+        dropped: &BTreeSet<String>,
+    ) -> ReindexStats {
+        // Indexes built by `parse_source` or loaded from other formats do not
+        // know which file contributed what, so start over.
+        if self.files.is_empty() && !self.functions.is_empty() {
+            let root = std::mem::take(&mut self.root);
+            let skip_dirs = std::mem::take(&mut self.skip_dirs);
+            let filter = std::mem::take(&mut self.filter);
+            let path_aliases = std::mem::take(&mut self.path_aliases);
+            let extra_roots = std::mem::take(&mut self.extra_roots);
+            let skipped = std::mem::take(&mut self.skipped);
+            *self = CodeIndex {
+                root,
+                skip_dirs,
+                filter,
+                path_aliases,
+                extra_roots,
+                skipped,
+                ..CodeIndex::new()
+            };
+        }
+
+        // How imports resolve is part of what a file's record says, so a
+        // changed tsconfig.json or package.json changes every hash.
+        let resolver = Resolver::new(&self.root_paths(), &self.path_aliases);
+        let hash = |content: &str| content_hash(content) ^ resolver.fingerprint();
+        let mut stats = ReindexStats::default();
+        let mut contents: BTreeMap<String, String> = BTreeMap::new();
+        let mut dirty: BTreeSet<String> = BTreeSet::new();
+        let paths: Vec<String> = paths.into_iter().collect();
+        let read: Vec<_> = paths
+            .into_par_iter()
+            .map(|path| {
+                let content = match dropped.contains(&path) {
+                    true => Ok(None),
+                    false => read_source(&path),
+                };
+                (path, content)
+            })
+            .collect();
+        let mut failed = vec![];
+        for (path, content) in read {
+            let content = match content {
+                Ok(content) => content,
+                Err(diagnostic) => {
+                    warn!("cannot read {}: {}", path, diagnostic.message);
+                    stats.failed += 1;
+                    failed.push((path.clone(), vec![diagnostic]));
+                    None
+                }
+            };
+            match content {
+                Some(content) => {
+                    match self.files.get(&path) {
+                        Some(record) if record.hash == hash(&content) => {
+                            stats.unchanged += 1;
+                            continue;
+                        }
+                        Some(_) => stats.changed += 1,
+                        None => stats.added += 1,
+                    }
+                    contents.insert(path.clone(), content);
+                    dirty.insert(path);
+                }
+                None if self.files.contains_key(&path) => {
+                    stats.removed += 1;
+                    dirty.insert(path);
+                }
+                None => {
+                    self.diagnostics.remove(&path);
+                }
+            }
+        }
+        // whether an import finds a file can change when files come or go.
+        if stats.added + stats.removed > 0 {
+            for (path, record) in self.files.iter() {
+                let moved = record
+                    .imports
+                    .iter()
+                    .any(|(specifier, file)| resolver.resolve(path, specifier) != *file);
+                if moved && !dirty.contains(path) {
+                    if let Ok(content) = std::fs::read_to_string(path) {
+                        contents.insert(path.clone(), content);
+                        dirty.insert(path.clone());
+                    }
+                }
+            }
+        }
+        if !dirty.is_empty() {
+            self.replace_files(dirty, contents, &resolver);
+        }
+        self.diagnostics.extend(failed);
+        stats
+    }
+
+    fn definers(&self) -> Definers {
+        let mut definers = Definers::default();
+        for (path, record) in self.files.iter() {
+            definers.add_file(path, &record.package, record.functions.iter());
+        }
+        definers
+    }
+
+    /// Parses `contents` in parallel, in path order, so IDs do not depend on
+    /// which thread finished first.
+    fn parse_files(
+        &self,
+        contents: BTreeMap<String, String>,
+        resolver: &Resolver,
+    ) -> Vec<ParsedFile> {
+        let (root, extra_roots) = (&self.root, &self.extra_roots);
+        contents
+            .into_par_iter()
+            .map(|(path, content)| {
+                // roots make the keys of test callbacks relative.
+                let mut index = CodeIndex {
+                    root: root.clone(),
+                    extra_roots: extra_roots.clone(),
+                    ..CodeIndex::new()
+                };
+                let imports = index.parse_module(&path, &content, Some(resolver));
+                let package = resolver.package_of(&path).unwrap_or_default();
+                for func in index.functions.values_mut() {
+                    func.package = package.to_string();
+                }
+                for class in index.classes.values_mut() {
+                    class.package = package.to_string();
+                }
+                ParsedFile {
+                    hash: content_hash(&content) ^ resolver.fingerprint(),
+                    package: package.to_string(),
+                    index,
+                    imports: imports.into_resolved(),
+                    path,
+                }
+            })
+            .collect()
+    }
+
+    fn replace_files(
+        &mut self,
+        mut dirty: BTreeSet<String>,
+        mut contents: BTreeMap<String, String>,
+        resolver: &Resolver,
+    ) {
+        let before = self.definers();
+        // The first file, by path, defining a name wins. When a winner goes
+        // away the next one takes over, so files sharing a name with a dirty
+        // file are redone too.
+        let mut definers: BTreeMap<&String, Vec<&String>> = BTreeMap::new();
+        for (path, record) in self.files.iter() {
+            for key in record.functions.iter().chain(record.classes.iter()) {
+                definers.entry(key).or_default().push(path);
+            }
+        }
+        let mut queue: Vec<String> = dirty.iter().cloned().collect();
+        while let Some(path) = queue.pop() {
+            let record = match self.files.get(&path) {
+                Some(record) => record,
+                None => continue,
+            };
+            for key in record.functions.iter().chain(record.classes.iter()) {
+                for other in definers.get(key).into_iter().flatten() {
+                    if dirty.insert((*other).clone()) {
+                        queue.push((*other).clone());
+                        // unchanged, so re-read as it is.
+                        if let Ok(content) = std::fs::read_to_string(other) {
+                            contents.insert((*other).clone(), content);
+                        }
+                    }
+                }
+            }
+        }
+        let mut parsed = self.parse_files(contents, resolver);
+
+        // The keys of a name change with the packages defining it, and so do
+        // the calls of it in files that did not change.
+        let mut after = before.clone();
+        after.remove_files(&dirty);
+        for file in parsed.iter() {
+            after.add_file(&file.path, &file.package, file.index.functions.keys());
+        }
+        let changed = before.changed_names(&after);
+        let mut linked: BTreeSet<u64> = BTreeSet::new();
+        for name in changed.iter() {
+            for key in before.keys(name).into_iter().chain(after.keys(name)) {
+                linked.extend(self.id_gen.get(&key));
+            }
+        }
+        if !changed.is_empty() {
+            let mut relink = BTreeMap::new();
+            for (path, record) in self.files.iter() {
+                let defines = record.functions.iter().any(|name| changed.contains(name));
+                if !dirty.contains(path) && (defines || record.uses(&linked)) {
+                    if let Ok(content) = std::fs::read_to_string(path) {
+                        relink.insert(path.clone(), content);
+                    }
+                }
+            }
+            dirty.extend(relink.keys().cloned());
+            parsed.extend(self.parse_files(relink, resolver));
+            parsed.sort_by(|a, b| a.path.cmp(&b.path));
+        }
+
+        let mut callers: BTreeSet<u64> = BTreeSet::new();
+        for path in dirty.iter() {
+            self.diagnostics.remove(path);
+            if let Some(old) = self.files.remove(path) {
+                for name in old.functions.iter() {
+                    let key = before.key(name, &old.package);
+                    if self.functions.get(&key).is_some_and(|f| &f.file == path) {
+                        self.functions.remove(&key);
+                    }
+                }
+                for key in old.classes.iter() {
+                    if self.classes.get(key).is_some_and(|c| &c.file == path) {
+                        self.classes.remove(key);
+                    }
+                }
+                callers.extend(old.calls.keys().chain(old.refs.keys()));
+            }
+        }
+
+        for file in parsed {
+            let ParsedFile {
+                path,
+                hash,
+                package,
+                index: mut parsed,
+                imports,
+            } = file;
+            if let Some(diagnostics) = parsed.diagnostics.remove(&path) {
+                self.diagnostics.insert(path.clone(), diagnostics);
+            }
+            // calls are named as written, or with the package their import
+            // resolved to.
+            let link = |id: &u64| {
+                let name = parsed.id_gen.name(*id).unwrap();
+                match name.split_once(PACKAGE_TAG) {
+                    Some((name, package)) => after.key(name, package),
+                    None => after.key(name, &package),
+                }
+            };
+            let mut record = FileRecord {
+                hash,
+                package: package.clone(),
+                imports,
+                ..FileRecord::default()
+            };
+            for (name, func) in parsed.functions.iter() {
+                let key = after.key(name, &package);
+                self.id_gen.id(&key);
+                record.functions.push(name.clone());
+                match self.functions.get(&key) {
+                    Some(existing) if existing.file < path => {}
+                    _ => {
+                        self.functions.insert(key, func.clone());
+                    }
+                }
+            }
+            for (key, class) in parsed.classes.iter() {
+                record.classes.push(key.clone());
+                match self.classes.get(key) {
+                    Some(existing) if existing.file < path => {}
+                    _ => {
+                        self.classes.insert(key.clone(), class.clone());
+                    }
+                }
+            }
+            for (from, sites) in parsed.call_sites.iter() {
+                let from = self.id_gen.id(&link(from));
+                let sites = sites
+                    .iter()
+                    .map(|site| CallSite {
+                        callee: self.id_gen.id(&link(&site.callee)),
+                        span: site.span,
+                        kind: site.kind,
+                    })
+                    .collect();
+                record.calls.insert(from, sites);
+                callers.insert(from);
+            }
+            for (from, targets) in parsed.refs.iter() {
+                let from = self.id_gen.id(&link(from));
+                let targets = targets.iter().map(|t| self.id_gen.id(&link(t))).collect();
+                record.refs.insert(from, targets);
+                callers.insert(from);
+            }
+            for route in parsed.routes.iter() {
+                record.routes.push(self.id_gen.id(&link(route)));
+            }
+            self.files.insert(path, record);
+        }
+
+        // Calls of a function defined in several files are concatenated in
+        // path order, as a full parse of the sorted file list does.
+        for caller in callers {
+            let calls: Vec<CallSite> = self
+                .files
+                .values()
+                .filter_map(|r| r.calls.get(&caller))
+                .flatten()
+                .cloned()
+                .collect();
+            let refs: Vec<u64> = self
+                .files
+                .values()
+                .filter_map(|r| r.refs.get(&caller))
+                .flatten()
+                .copied()
+                .collect();
+            if calls.is_empty() {
+                self.edges.remove(&caller);
+                self.call_sites.remove(&caller);
+            } else {
+                self.edges
+                    .insert(caller, calls.iter().map(|c| c.callee).collect());
+                self.call_sites.insert(caller, calls);
+            }
+            if refs.is_empty() {
+                self.refs.remove(&caller);
+            } else {
+                self.refs.insert(caller, refs);
+            }
+        }
+        self.routes = self
+            .files
+            .values()
+            .flat_map(|r| r.routes.iter().copied())
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(index: &mut CodeIndex, root: &str) -> serde_json::Value {
+        serde_json::to_value(index.serde_tree(root, 5)).unwrap()
+    }
+
+    #[test]
+    fn test_reindex_matches_full_parse() {
+        let dir = std::env::temp_dir().join(format!("graphgen-reindex-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        let write = |name: &str, content: &str| std::fs::write(dir.join(name), content).unwrap();
+        write(
+            "src/a.ts",
+            "function main() { helper(); util(); }\nfunction helper() {}",
+        );
+        write("src/b.ts", "function util() { helper(); }");
+        write(
+            "src/c.ts",
+            "function helper() { deep(); }\nfunction deep() {}",
+        );
+        let root = dir.display().to_string();
+
+        let mut index = CodeIndex::new();
+        index.parse_project(&root).unwrap();
+        assert_eq!(index.reindex().unchanged, 3);
+
+        // a.ts loses its helper, so c.ts's definition takes over.
+        write("src/a.ts", "function main() { helper(); }");
+        std::fs::remove_file(dir.join("src/b.ts")).unwrap();
+        write("src/d.ts", "export function api() { main(); }");
+        let stats = index.reindex();
+        assert_eq!(
+            stats,
+            ReindexStats {
+                added: 1,
+                changed: 1,
+                removed: 1,
+                unchanged: 1,
+                failed: 0,
+            }
+        );
+
+        let mut full = CodeIndex::new();
+        full.parse_project(&root).unwrap();
+        assert_eq!(index.function_list(), full.function_list());
+        assert_eq!(tree(&mut index, "api"), tree(&mut full, "api"));
+        assert_eq!(
+            index.functions["helper"].file,
+            full.functions["helper"].file
+        );
+        assert_eq!(index.documents(), full.documents());
+        assert_eq!(index.metrics().functions, full.metrics().functions);
+
+        let canonical = std::fs::canonicalize(&dir).unwrap();
+        let key = index.project_file_key(&canonical.join("src/a.ts"));
+        assert_eq!(
+            key.as_deref(),
+            index.files.keys().next().map(|k| k.as_str())
+        );
+        assert_eq!(
+            index.project_file_key(Path::new("src/e.ts")),
+            Some(format!("{}/src/e.ts", root))
+        );
+        let key = index.project_file_key(&canonical.join("node_modules/x.ts"));
+        assert_eq!(index.update_files(key).added, 0);
+        assert_eq!(index.project_file_key(&canonical.join("src/a.js")), None);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_imports_follow_added_files() {
+        let dir = std::env::temp_dir().join(format!("graphgen-imports-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, content: &str| std::fs::write(dir.join(name), content).unwrap();
+        write(
+            "a.ts",
+            "import { log as writeLog } from \"./logger\";\nexport function main() { writeLog(); }",
+        );
+        let root = dir.display().to_string();
+        let mut index = CodeIndex::new();
+        index.parse_project(&root).unwrap();
+        assert_eq!(tree(&mut index, "main")["children"][0]["name"], "writeLog");
+
+        // a.ts is unchanged, but its import now finds a file.
+        write("logger.ts", "export function log() {}");
+        let logger = dir.join("logger.ts").display().to_string();
+        assert_eq!(index.update_files(vec![logger.clone()]).added, 1);
+        let mut full = CodeIndex::new();
+        full.parse_project(&root).unwrap();
+        assert_eq!(tree(&mut index, "main"), tree(&mut full, "main"));
+        assert_eq!(tree(&mut index, "main")["children"][0]["name"], "log");
+
+        std::fs::remove_file(&logger).unwrap();
+        assert_eq!(index.reindex().removed, 1);
+        assert_eq!(tree(&mut index, "main")["children"][0]["name"], "writeLog");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_parallel_parse_is_deterministic() {
+        let dir = std::env::temp_dir().join(format!("graphgen-jobs-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for i in 0..24 {
+            let source = format!(
+                "function f{i}() {{ shared(); f{}(); }}\nfunction shared() {{ g{i}(); }}",
+                (i + 1) % 24
+            );
+            std::fs::write(dir.join(format!("m{i}.ts")), source).unwrap();
+        }
+        let root = dir.display().to_string();
+        let build = |jobs: usize| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(jobs)
+                .build()
+                .unwrap();
+            let mut index = CodeIndex::new();
+            pool.install(|| index.parse_project(&root)).unwrap();
+            bincode::serialize(&index).unwrap()
+        };
+        let serial = build(1);
+        for _ in 0..3 {
+            assert_eq!(build(8), serial);
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
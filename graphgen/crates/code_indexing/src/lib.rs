@@ -1,20 +1,50 @@
+pub mod aggregate;
+pub mod callees;
+pub mod config;
+pub mod deadcode;
+pub mod diagnostics;
+pub mod export;
+pub mod filter;
+pub mod format;
 pub mod graph;
+pub mod impact;
+pub mod incremental;
+pub mod metrics;
 mod misc;
+pub mod mmap;
+pub mod packages;
+mod resolve;
+pub mod rules;
+pub mod sqlite;
+pub mod testsel;
 
 extern crate serde;
 
-use bincode;
-use glob::glob;
+use callees::{classify, CallKind};
+use diagnostics::Diagnostic;
 use graph::*;
-use log::{error, info, warn};
+use log::info;
 use misc::*;
+use resolve::{ImportMap, Resolver};
 
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
-use std::io::prelude::*;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
 use tree_sitter::Node;
 use tree_sitter::Parser;
-use tree_sitter_typescript;
+
+thread_local! {
+    // tree-sitter parsers are reusable but not `Sync`, so one per thread.
+    static PARSER: RefCell<Parser> = RefCell::new(typescript_parser());
+}
+
+fn typescript_parser() -> Parser {
+    let mut parser = Parser::new();
+    parser
+        .set_language(tree_sitter_typescript::language_typescript())
+        .expect("Error loading TypeScript grammar");
+    parser
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct IDGenerator {
@@ -32,77 +62,156 @@ impl IDGenerator {
         }
     }
 
-    pub fn id(&mut self, sig: &String) -> u64 {
-        *self.id_map.entry(sig.clone()).or_insert_with(|| {
+    pub fn id(&mut self, sig: &str) -> u64 {
+        *self.id_map.entry(sig.to_string()).or_insert_with(|| {
             let id = self.next_id;
             self.next_id += 1;
-            self.name_map.insert(id, sig.clone());
+            self.name_map.insert(id, sig.to_string());
             id
         })
     }
 
+    pub fn get(&self, sig: &str) -> Option<u64> {
+        self.id_map.get(sig).copied()
+    }
+
     pub fn name(&self, id: u64) -> Option<&String> {
         self.name_map.get(&id)
     }
 }
 
+/// Where a name appears in its file: a 1-based line and 0-based start and
+/// end columns counted in UTF-16 code units, as editors and LSP count them.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub end_column: usize,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct Class {
     name: String,
+    file: String,
+    extends: Option<String>,
     declaration: String,
+    // the workspace package or project root of `file`, empty if unknown.
+    package: String,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct Function {
     name: String,
     pkg: String,
+    file: String,
+    // 1-based line range of the declaration.
+    line: usize,
+    end_line: usize,
+    // the declared name, or the `it`/`test` call of a test callback.
+    name_span: Span,
+    exported: bool,
+    // test functions and `it`/`test` callbacks.
+    test: bool,
     body: String,
+    // the workspace package or project root of `file`, empty if unknown.
+    package: String,
 }
 
 impl Function {
     pub fn str(&self) -> String {
-        if self.pkg == "" {
+        if self.pkg.is_empty() {
             return self.name.clone();
         }
         format!("{}.{}", self.pkg, self.name)
     }
     pub fn new(name: String, pkg: String) -> Self {
         Function {
-            name: name,
-            pkg: pkg,
+            name,
+            pkg,
+            file: "".to_string(),
+            line: 0,
+            end_line: 0,
+            name_span: Span::default(),
+            exported: false,
+            test: false,
             body: "".to_string(),
+            package: String::new(),
         }
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct CallSite {
+    callee: u64,
+    // the called name, i.e. the property of a member call.
+    span: Span,
+    // as far as the calling file tells, see `CodeIndex::call_kind`.
+    kind: CallKind,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CodeIndex {
+    // project directory passed to `parse_project`, empty for single files.
+    root: String,
     edges: BTreeMap<u64, Vec<u64>>,
+    // the same calls as `edges`, with the line of each call expression.
+    call_sites: BTreeMap<u64, Vec<CallSite>>,
+    // functions passed or stored as values rather than called, e.g. callbacks.
+    refs: BTreeMap<u64, Vec<u64>>,
+    // handlers registered through `app.get("/path", handler)` style calls.
+    routes: BTreeSet<u64>,
     functions: BTreeMap<String, Function>,
     classes: BTreeMap<String, Class>,
     skip_dirs: Vec<String>,
     pub(crate) id_gen: IDGenerator,
+    // content hash and contributions of each project file, for `reindex`.
+    files: BTreeMap<String, incremental::FileRecord>,
+    // what went wrong reading or parsing each file.
+    diagnostics: BTreeMap<String, Vec<Diagnostic>>,
+    #[serde(skip)]
+    filter: filter::FileFilter,
+    // import aliases from graphgen.toml, on top of tsconfig's.
+    #[serde(skip)]
+    path_aliases: BTreeMap<String, Vec<String>>,
+    // more project directories indexed along with `root`.
+    #[serde(skip)]
+    extra_roots: Vec<String>,
+    // what the last `reindex` left out.
+    #[serde(skip)]
+    skipped: Vec<filter::Skipped>,
+}
+
+impl Default for CodeIndex {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CodeIndex {
     pub fn new() -> Self {
         CodeIndex {
+            root: String::new(),
             edges: BTreeMap::new(),
+            call_sites: BTreeMap::new(),
+            refs: BTreeMap::new(),
+            routes: BTreeSet::new(),
             functions: BTreeMap::new(),
             classes: BTreeMap::new(),
-            skip_dirs: vec!["node_modules".to_string(), ".pnpm".to_string()],
+            skip_dirs: vec![
+                "node_modules".to_string(),
+                ".pnpm".to_string(),
+                ".git".to_string(),
+            ],
             id_gen: IDGenerator::new(),
+            files: BTreeMap::new(),
+            diagnostics: BTreeMap::new(),
+            filter: filter::FileFilter::default(),
+            path_aliases: BTreeMap::new(),
+            extra_roots: vec![],
+            skipped: vec![],
         }
     }
 
-    pub fn load(filename: &String) -> Self {
-        let mut file = std::fs::File::open(filename).unwrap();
-        let mut buffer = Vec::new();
-        file.read_to_end(&mut buffer).unwrap();
-        let data: CodeIndex = bincode::deserialize(&buffer).unwrap();
-        data
-    }
-
     pub fn function_list(&self) -> Vec<String> {
         let mut result = vec![];
         for k in self.functions.keys() {
@@ -111,7 +220,21 @@ impl CodeIndex {
         result
     }
 
+    /// `file` relative to the project directory, or the other root it is
+    /// in, as written in rules and configs.
+    pub(crate) fn relative_path<'a>(&self, file: &'a str) -> &'a str {
+        let path = std::path::Path::new(file);
+        std::iter::once(&self.root)
+            .chain(self.extra_roots.iter())
+            .filter(|root| !root.is_empty())
+            .filter_map(|root| path.strip_prefix(root).ok())
+            .min_by_key(|rel| rel.components().count())
+            .and_then(|rel| rel.to_str())
+            .unwrap_or(file)
+    }
+
     fn add_function(&mut self, func: &Function) {
+        self.id_gen.id(&func.str());
         self.functions
             .entry(func.str())
             .or_insert_with(|| func.clone());
@@ -123,7 +246,7 @@ impl CodeIndex {
             .or_insert_with(|| cls.clone());
     }
 
-    fn add_edge(&mut self, from: &String, to: &String) {
+    fn add_edge(&mut self, from: &str, to: &str, span: Span, kind: CallKind) {
         let from_id = self.id_gen.id(from);
         let to_id: u64 = self.id_gen.id(to);
         match self.edges.get_mut(&from_id) {
@@ -132,107 +255,142 @@ impl CodeIndex {
                 self.edges.insert(from_id, vec![to_id]);
             }
         }
+        self.call_sites.entry(from_id).or_default().push(CallSite {
+            callee: to_id,
+            span,
+            kind,
+        });
     }
 
-    pub fn serde_tree(&mut self, funcname: &String, depth: i32) -> Option<GraphNode> {
-        let id = self.id_gen.id(funcname);
-        self._serde_tree_helper(id, depth)
+    fn add_ref(&mut self, from: &str, to: &str) {
+        let from_id = self.id_gen.id(from);
+        let to_id = self.id_gen.id(to);
+        self.refs.entry(from_id).or_default().push(to_id);
     }
 
-    fn _serde_tree_helper(&self, id: u64, depth: i32) -> Option<GraphNode> {
-        if depth == 0 {
-            return None;
-        }
-        let mut children = vec![];
-        let mut value = 0;
-        if let Some(outv) = self.edges.get(&id) {
-            value = outv.len();
-            for out in outv.iter() {
-                if let Some(child) = self._serde_tree_helper(*out, depth - 1) {
-                    children.push(child);
-                }
-            }
-        }
-        Some(GraphNode {
-            name: self.id_gen.name(id).unwrap_or(&"nil".to_string()).clone(),
-            children: children,
-            value: value,
-        })
+    pub fn serde_tree(&mut self, funcname: &str, depth: i32) -> Option<GraphNode> {
+        self.serde_tree_without(funcname, depth, &[])
     }
 
-    pub fn into_file(&self, filename: &String) {
-        let file = std::fs::File::create(filename).unwrap();
-        bincode::serialize_into(file, self).unwrap();
+    /// Parses the TypeScript files under `dir`. Called again on the same
+    /// index, only files changed since the last call are re-parsed.
+    pub fn parse_project(
+        &mut self,
+        dir: &str,
+    ) -> Result<incremental::ReindexStats, std::io::Error> {
+        self.root = dir.to_string();
+        let stats = self.reindex();
+        info!("Indexed {:?}", stats);
+        Ok(stats)
     }
 
-    pub fn parse_project(&mut self, dir: &String) -> Result<(), std::io::Error> {
-        // TODO: supports more languages.
-        let pattern = format!("{}/**/*.ts", dir);
-        let entries = glob(&pattern).expect("Failed to read glob pattern");
-        for entry in entries {
-            match entry {
-                Ok(path) => {
-                    let path_str = path.display().to_string();
-                    if !self.skip_dirs.iter().any(|s| path_str.contains(s)) {
-                        if let Err(e) = self.parse_file(&path_str) {
-                            error!("parse_file error {:?}", e);
-                        }
-                    }
-                }
-                Err(e) => {
-                    error!("Read glob error {:?}", e);
-                }
-            }
-        }
-
+    pub fn parse_file(&mut self, filename: &str) -> Result<(), std::io::Error> {
+        let content = std::fs::read_to_string(filename)?;
+        self.parse_source(filename, &content);
         Ok(())
     }
 
-    pub fn parse_file(&mut self, filename: &String) -> Result<(), std::io::Error> {
-        let content = std::fs::read_to_string(filename)?;
-        let mut parser = Parser::new();
-        parser
-            .set_language(tree_sitter_typescript::language_typescript())
-            .expect("Error loading TypeScript grammar");
+    pub fn parse_source(&mut self, filename: &str, content: &str) {
+        self.parse_module(filename, content, None);
+    }
 
+    /// Parses one file of a project, naming calls of imported project
+    /// functions as they are defined when `resolver` finds the import.
+    /// Returns the file's imports.
+    fn parse_module(
+        &mut self,
+        filename: &str,
+        content: &str,
+        resolver: Option<&Resolver>,
+    ) -> ImportMap {
         info!("parsing {}", filename);
-        if let Some(tree) = parser.parse(&content, None) {
-            let mut queue = vec![tree.root_node()];
-            let mut cursor = tree.root_node().walk();
-            while let Some(node) = queue.pop() {
-                for child in node.children(&mut cursor) {
-                    match child.kind() {
-                        "class_declaration" => self.parse_class_declaration(child, &content),
-                        "function_declaration" => self.parse_function_declaration(child, &content),
-                        _ => {}
+        let tree = match PARSER.with(|parser| parser.borrow_mut().parse(content, None)) {
+            Some(tree) => tree,
+            None => return ImportMap::default(),
+        };
+        let errors = diagnostics::syntax_errors(tree.root_node(), content);
+        if !errors.is_empty() {
+            self.diagnostics
+                .entry(filename.to_string())
+                .or_default()
+                .extend(errors);
+        }
+        let imports = ImportMap::new(tree.root_node(), filename, content, resolver);
+        let mut queue = vec![tree.root_node()];
+        let mut cursor = tree.root_node().walk();
+        while let Some(node) = queue.pop() {
+            for child in node.children(&mut cursor) {
+                match child.kind() {
+                    "class_declaration" => {
+                        self.parse_class_declaration(child, filename, content, &imports)
+                    }
+                    "function_declaration" => {
+                        self.parse_function_declaration(child, filename, content, &imports)
                     }
-                    queue.push(child);
+                    "call_expression" => {
+                        self.parse_route_registration(child, content, &imports);
+                        self.parse_test_callback(child, filename, content, &imports);
+                    }
+                    _ => {}
                 }
+                queue.push(child);
             }
         }
-        Ok(())
+        imports
     }
 
-    fn parse_function_declaration<'a>(&mut self, node: Node<'a>, content: &String) {
-        let caller = str_by_field_name(node, "name", &content).unwrap();
+    fn parse_function_declaration(
+        &mut self,
+        node: Node,
+        filename: &str,
+        content: &str,
+        imports: &ImportMap,
+    ) {
+        let (caller, body) = match (
+            str_by_field_name(node, "name", content),
+            str_by_field_name(node, "body", content),
+        ) {
+            (Some(caller), Some(body)) if !caller.is_empty() => (caller, body),
+            _ => return self.unsupported(filename, node, content, "function declaration"),
+        };
         let function = Function {
-            name: caller.clone(),
-            pkg: "".to_string(),
-            body: str_by_field_name(node, "body", &content).unwrap(),
+            file: filename.to_string(),
+            line: node.start_position().row + 1,
+            end_line: node.end_position().row + 1,
+            name_span: field_span(node, "name", content),
+            exported: is_exported(node),
+            test: is_test_file(filename),
+            body,
+            ..Function::new(caller.clone(), "".to_string())
         };
         self.add_function(&function);
         let calls = walk_collect(node, "call_expression");
         for call in calls {
-            if let Some(callee) = str_by_field_name(call, "function", &content) {
+            if let Some(callee) = str_by_field_name(call, "function", content) {
+                let kind = classify(&callee, imports);
+                let callee = imports.rename(&callee);
                 info!("{} -> {}", caller.clone(), callee);
-                self.add_edge(&caller, &callee);
+                self.add_edge(&caller, &callee, callee_span(call, content), kind);
             }
         }
+        for reference in collect_refs(node, content) {
+            self.add_ref(&caller, &imports.rename(&reference));
+        }
     }
 
-    fn parse_class_declaration<'a>(&mut self, node: Node<'a>, content: &String) {
-        let clsname = str_by_field_name(node, "name", &content).unwrap_or("".to_string());
+    fn parse_class_declaration(
+        &mut self,
+        node: Node,
+        filename: &str,
+        content: &str,
+        imports: &ImportMap,
+    ) {
+        let clsname = match str_by_field_name(node, "name", content) {
+            Some(name) if !name.is_empty() => name,
+            _ => return self.unsupported(filename, node, content, "class declaration"),
+        };
         let clsdot = clsname.clone() + ".";
+        let exported = is_exported(node);
         let methods = walk_collect(node, "method_definition");
         let end_byte = if let Some(first) = methods.first() {
             first.start_byte()
@@ -243,25 +401,101 @@ impl CodeIndex {
         if let Some(declaration) = substr(content, node.start_byte(), end_byte) {
             self.add_class(&Class {
                 name: clsname.clone(),
-                declaration: declaration,
+                file: filename.to_string(),
+                extends: class_extends(node, content),
+                declaration,
+                package: String::new(),
             });
         }
 
         for method in methods {
+            let (name, body) = match (
+                str_by_field_name(method, "name", content),
+                str_by_field_name(method, "body", content),
+            ) {
+                (Some(name), Some(body)) if !name.is_empty() => (name, body),
+                _ => {
+                    self.unsupported(filename, method, content, "method");
+                    continue;
+                }
+            };
             let sig = Function {
-                name: str_by_field_name(method, "name", &content).unwrap(),
-                pkg: clsname.clone(),
-                body: str_by_field_name(method, "body", &content).unwrap(),
+                test: is_test_file(filename),
+                file: filename.to_string(),
+                line: method.start_position().row + 1,
+                end_line: method.end_position().row + 1,
+                name_span: field_span(method, "name", content),
+                exported,
+                body,
+                ..Function::new(name, clsname.clone())
             };
             self.add_function(&sig);
             let caller = sig.str();
             let calls = walk_collect(method, "call_expression");
             for call in calls {
-                if let Some(callee) = str_by_field_name(call, "function", &content) {
-                    let _callee = callee.replace("this.", &clsdot);
-                    self.add_edge(&caller, &_callee);
+                if let Some(callee) = str_by_field_name(call, "function", content) {
+                    let kind = classify(&callee, imports);
+                    let _callee = imports.rename(&callee.replace("this.", &clsdot));
+                    self.add_edge(&caller, &_callee, callee_span(call, content), kind);
                 }
             }
+            for reference in collect_refs(method, content) {
+                self.add_ref(
+                    &caller,
+                    &imports.rename(&reference.replace("this.", &clsdot)),
+                );
+            }
+        }
+    }
+
+    fn parse_test_callback(
+        &mut self,
+        node: Node,
+        filename: &str,
+        content: &str,
+        imports: &ImportMap,
+    ) {
+        let (title, callback) = match test_callback(node, &TEST_FUNCTIONS, content) {
+            Some(test) => test,
+            None => return,
+        };
+        let mut titles = describe_titles(node, content);
+        titles.push(title);
+        let function = Function {
+            file: filename.to_string(),
+            line: node.start_position().row + 1,
+            end_line: node.end_position().row + 1,
+            name_span: field_span(node, "function", content),
+            test: true,
+            body: node_str(callback, content).unwrap_or_default(),
+            ..Function::new(
+                test_key(self.relative_path(filename), &titles),
+                "".to_string(),
+            )
+        };
+        self.add_function(&function);
+        let caller = function.str();
+        let mut calls = walk_collect(callback, "call_expression");
+        calls.extend(enclosing_hooks(node, content));
+        for call in calls {
+            if let Some(callee) = str_by_field_name(call, "function", content) {
+                self.add_edge(
+                    &caller,
+                    &imports.rename(&callee),
+                    callee_span(call, content),
+                    classify(&callee, imports),
+                );
+            }
+        }
+        for reference in collect_refs(callback, content) {
+            self.add_ref(&caller, &imports.rename(&reference));
+        }
+    }
+
+    fn parse_route_registration(&mut self, node: Node, content: &str, imports: &ImportMap) {
+        for handler in route_handlers(node, content) {
+            let id = self.id_gen.id(&imports.rename(&handler));
+            self.routes.insert(id);
         }
     }
 }
@@ -269,25 +503,24 @@ impl CodeIndex {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use env_logger;
 
     #[test]
     fn test_parse() {
         env_logger::init();
         let mut indexing = CodeIndex::new();
-        let res = indexing.parse_file(&"../../tests/test0.txt".to_string());
+        let res = indexing.parse_file("../../tests/test0.txt");
         assert!(res.is_ok());
-        assert!(indexing.classes.get("Parser").is_some());
+        assert!(indexing.classes.contains_key("Parser"));
     }
 
     #[test]
     fn test_load() {
         let mut indexing = CodeIndex::new();
-        let res = indexing.parse_file(&"../../tests/test0.txt".to_string());
+        let res = indexing.parse_file("../../tests/test0.txt");
         assert!(res.is_ok());
         let datafile = "/tmp/code_index.bin".to_string();
-        indexing.into_file(&datafile);
-        let load_indexing = CodeIndex::load(&datafile);
+        indexing.into_file(&datafile).unwrap();
+        let load_indexing = CodeIndex::load(&datafile).unwrap();
         assert_eq!(load_indexing.edges.len(), indexing.edges.len());
     }
 }
@@ -0,0 +1,266 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::str::FromStr;
+
+use crate::graph::GraphNode;
+use crate::CodeIndex;
+
+const DAMPING: f64 = 0.85;
+const PAGERANK_ITERATIONS: usize = 100;
+const PAGERANK_TOLERANCE: f64 = 1e-9;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    FanIn,
+    FanOut,
+    Calls,
+    Reach,
+    Betweenness,
+    PageRank,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 6] = [
+        Metric::FanIn,
+        Metric::FanOut,
+        Metric::Calls,
+        Metric::Reach,
+        Metric::Betweenness,
+        Metric::PageRank,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Metric::FanIn => "fan_in",
+            Metric::FanOut => "fan_out",
+            Metric::Calls => "calls",
+            Metric::Reach => "reach",
+            Metric::Betweenness => "betweenness",
+            Metric::PageRank => "pagerank",
+        }
+    }
+}
+
+impl FromStr for Metric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Metric::ALL
+            .iter()
+            .find(|m| m.name() == s)
+            .copied()
+            .ok_or_else(|| format!("unknown metric {}", s))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct FunctionMetrics {
+    /// Distinct project functions calling this one.
+    pub fan_in: usize,
+    /// Distinct project functions called by this one.
+    pub fan_out: usize,
+    /// Call sites in the body, resolved or not.
+    pub calls: usize,
+    /// Project functions transitively reachable through calls.
+    pub reach: usize,
+    pub betweenness: f64,
+    pub pagerank: f64,
+}
+
+impl FunctionMetrics {
+    pub fn get(&self, metric: Metric) -> f64 {
+        match metric {
+            Metric::FanIn => self.fan_in as f64,
+            Metric::FanOut => self.fan_out as f64,
+            Metric::Calls => self.calls as f64,
+            Metric::Reach => self.reach as f64,
+            Metric::Betweenness => self.betweenness,
+            Metric::PageRank => self.pagerank,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Metrics {
+    pub functions: BTreeMap<String, FunctionMetrics>,
+}
+
+impl Metrics {
+    /// Functions ordered by `metric`, highest first.
+    pub fn top(&self, metric: Metric, limit: usize) -> Vec<(&String, &FunctionMetrics)> {
+        let mut rows: Vec<_> = self.functions.iter().collect();
+        rows.sort_by(|a, b| b.1.get(metric).total_cmp(&a.1.get(metric)));
+        rows.truncate(limit);
+        rows
+    }
+
+    /// Sets `metric` on every node of a tree built by `CodeIndex::serde_tree`.
+    pub fn annotate(&self, node: &mut GraphNode, metric: Metric) {
+        node.metric = self.functions.get(&node.name).map(|m| m.get(metric));
+        for child in node.children.iter_mut() {
+            self.annotate(child, metric);
+        }
+    }
+}
+
+impl CodeIndex {
+    pub fn metrics(&self) -> Metrics {
+        let names: Vec<&String> = self.functions.keys().collect();
+        let index: BTreeMap<&String, usize> =
+            names.iter().enumerate().map(|(i, n)| (*n, i)).collect();
+        let mut calls = vec![0; names.len()];
+        let mut out: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); names.len()];
+        for (from, targets) in self.edges.iter() {
+            let from = match self.id_gen.name(*from).and_then(|n| index.get(n)) {
+                Some(from) => *from,
+                None => continue,
+            };
+            calls[from] += targets.len();
+            for to in targets {
+                if let Some(to) = self.id_gen.name(*to).and_then(|n| index.get(n)) {
+                    out[from].insert(*to);
+                }
+            }
+        }
+        let out: Vec<Vec<usize>> = out.into_iter().map(|s| s.into_iter().collect()).collect();
+        let mut fan_in = vec![0; names.len()];
+        for targets in out.iter() {
+            for to in targets {
+                fan_in[*to] += 1;
+            }
+        }
+        let betweenness = betweenness(&out);
+        let pagerank = pagerank(&out);
+
+        let functions = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let metrics = FunctionMetrics {
+                    fan_in: fan_in[i],
+                    fan_out: out[i].len(),
+                    calls: calls[i],
+                    reach: reach(&out, i),
+                    betweenness: betweenness[i],
+                    pagerank: pagerank[i],
+                };
+                ((*name).clone(), metrics)
+            })
+            .collect();
+        Metrics { functions }
+    }
+}
+
+fn reach(out: &[Vec<usize>], start: usize) -> usize {
+    let mut visited = vec![false; out.len()];
+    visited[start] = true;
+    let mut queue = vec![start];
+    let mut count = 0;
+    while let Some(v) = queue.pop() {
+        for w in out[v].iter() {
+            if !visited[*w] {
+                visited[*w] = true;
+                count += 1;
+                queue.push(*w);
+            }
+        }
+    }
+    count
+}
+
+/// Brandes' algorithm for unweighted directed graphs.
+fn betweenness(out: &[Vec<usize>]) -> Vec<f64> {
+    let n = out.len();
+    let mut result = vec![0.0; n];
+    for s in 0..n {
+        let mut stack = vec![];
+        let mut preds: Vec<Vec<usize>> = vec![vec![]; n];
+        let mut sigma = vec![0.0; n];
+        let mut dist: Vec<i64> = vec![-1; n];
+        sigma[s] = 1.0;
+        dist[s] = 0;
+        let mut queue = VecDeque::from([s]);
+        while let Some(v) = queue.pop_front() {
+            stack.push(v);
+            for w in out[v].iter().copied() {
+                if dist[w] < 0 {
+                    dist[w] = dist[v] + 1;
+                    queue.push_back(w);
+                }
+                if dist[w] == dist[v] + 1 {
+                    sigma[w] += sigma[v];
+                    preds[w].push(v);
+                }
+            }
+        }
+        let mut delta = vec![0.0; n];
+        while let Some(w) = stack.pop() {
+            for v in preds[w].iter().copied() {
+                delta[v] += sigma[v] / sigma[w] * (1.0 + delta[w]);
+            }
+            if w != s {
+                result[w] += delta[w];
+            }
+        }
+    }
+    result
+}
+
+fn pagerank(out: &[Vec<usize>]) -> Vec<f64> {
+    let n = out.len();
+    if n == 0 {
+        return vec![];
+    }
+    let base = (1.0 - DAMPING) / n as f64;
+    let mut rank = vec![1.0 / n as f64; n];
+    for _ in 0..PAGERANK_ITERATIONS {
+        // Functions without callees spread their rank evenly.
+        let dangling: f64 = (0..n).filter(|v| out[*v].is_empty()).map(|v| rank[v]).sum();
+        let mut next = vec![base + DAMPING * dangling / n as f64; n];
+        for (v, targets) in out.iter().enumerate() {
+            for w in targets {
+                next[*w] += DAMPING * rank[v] / targets.len() as f64;
+            }
+        }
+        let diff: f64 = rank
+            .iter()
+            .zip(next.iter())
+            .map(|(a, b)| (a - b).abs())
+            .sum();
+        rank = next;
+        if diff < PAGERANK_TOLERANCE {
+            break;
+        }
+    }
+    rank
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"
+function a() { b(); c(); console.log(1); }
+function b() { d(); }
+function c() { d(); }
+function d() {}
+"#;
+
+    #[test]
+    fn test_metrics() {
+        let mut index = CodeIndex::new();
+        index.parse_source("diamond.ts", SOURCE);
+        let metrics = index.metrics();
+        let a = &metrics.functions["a"];
+        assert_eq!((a.fan_in, a.fan_out, a.calls, a.reach), (0, 2, 3, 3));
+        let d = &metrics.functions["d"];
+        assert_eq!((d.fan_in, d.fan_out, d.reach), (2, 0, 0));
+        // a -> d goes through b or c, each carrying half of the shortest paths.
+        assert_eq!(metrics.functions["b"].betweenness, 0.5);
+        assert_eq!(metrics.functions["a"].betweenness, 0.0);
+        let total: f64 = metrics.functions.values().map(|m| m.pagerank).sum();
+        assert!((total - 1.0).abs() < 1e-6);
+        assert_eq!(metrics.top(Metric::PageRank, 1)[0].0, "d");
+    }
+}
@@ -1,21 +1,60 @@
 use tree_sitter::Node;
 
-pub(crate) fn substr(content: &String, start_byte: usize, end_byte: usize) -> Option<String> {
+use crate::Span;
+
+const ROUTE_METHODS: [&str; 9] = [
+    "get", "post", "put", "patch", "delete", "head", "options", "all", "use",
+];
+
+pub(crate) fn substr(content: &str, start_byte: usize, end_byte: usize) -> Option<String> {
     let bytes = content.as_bytes();
     String::from_utf8(bytes[start_byte..end_byte].to_vec()).ok()
 }
 
-pub(crate) fn str_by_field_name<'a>(
-    node: Node<'a>,
-    field: &str,
-    content: &String,
-) -> Option<String> {
+pub(crate) fn str_by_field_name(node: Node, field: &str, content: &str) -> Option<String> {
     match node.child_by_field_name(field) {
-        None => return None,
-        Some(child) => {
-            let bytes = content.as_bytes();
-            return String::from_utf8(bytes[child.start_byte()..child.end_byte()].to_vec()).ok();
-        }
+        None => None,
+        Some(child) => node_str(child, content),
+    }
+}
+
+pub(crate) fn node_str(node: Node, content: &str) -> Option<String> {
+    substr(content, node.start_byte(), node.end_byte())
+}
+
+pub(crate) fn span(node: Node, content: &str) -> Span {
+    let start = node.start_position();
+    let line_start = node.start_byte() - start.column;
+    let end_byte = if node.end_position().row == start.row {
+        node.end_byte()
+    } else {
+        node.start_byte()
+    };
+    let column = |byte: usize| {
+        content
+            .get(line_start..byte)
+            .map(|s| s.encode_utf16().count())
+            .unwrap_or_default()
+    };
+    Span {
+        line: start.row + 1,
+        column: column(node.start_byte()),
+        end_column: column(end_byte),
+    }
+}
+
+pub(crate) fn field_span(node: Node, field: &str, content: &str) -> Span {
+    node.child_by_field_name(field)
+        .map(|n| span(n, content))
+        .unwrap_or_default()
+}
+
+/// The called name of a call expression, `bar` in both `bar()` and `foo.bar()`.
+pub(crate) fn callee_span(call: Node, content: &str) -> Span {
+    match call.child_by_field_name("function") {
+        Some(f) if f.kind() == "member_expression" => field_span(f, "property", content),
+        Some(f) => span(f, content),
+        None => span(call, content),
     }
 }
 
@@ -33,3 +72,255 @@ pub(crate) fn walk_collect<'a>(node: Node<'a>, kind: &str) -> Vec<Node<'a>> {
     }
     result
 }
+
+pub(crate) const TEST_FUNCTIONS: [&str; 2] = ["it", "test"];
+const SUITE_FUNCTIONS: [&str; 4] = ["describe", "context", "suite", "fdescribe"];
+const HOOK_FUNCTIONS: [&str; 8] = [
+    "beforeEach",
+    "afterEach",
+    "beforeAll",
+    "afterAll",
+    "before",
+    "after",
+    "setup",
+    "teardown",
+];
+
+/// Jest, Vitest and Mocha test files, e.g. `math.test.ts` or `math.spec.ts`.
+pub(crate) fn is_test_file(path: &str) -> bool {
+    path.ends_with(".test.ts") || path.ends_with(".spec.ts")
+}
+
+/// Whether a declaration is wrapped in an `export` statement.
+pub(crate) fn is_exported(node: Node) -> bool {
+    node.parent()
+        .is_some_and(|parent| parent.kind() == "export_statement")
+}
+
+/// The superclass expression of a class declaration, e.g. `Base` or `ns.Base`.
+pub(crate) fn class_extends(class: Node, content: &str) -> Option<String> {
+    let mut cursor = class.walk();
+    let heritage = class
+        .children(&mut cursor)
+        .find(|c| c.kind() == "class_heritage")?;
+    let mut cursor = heritage.walk();
+    let extends = heritage
+        .children(&mut cursor)
+        .find(|c| c.kind() == "extends_clause")?;
+    str_by_field_name(extends, "value", content)
+}
+
+/// Names used as values rather than called: callback arguments, assigned
+/// or returned functions, object literal values.
+pub(crate) fn collect_refs(node: Node, content: &str) -> Vec<String> {
+    let mut result = vec![];
+    let mut queue = vec![node];
+    let mut cursor = node.walk();
+    while let Some(node) = queue.pop() {
+        for child in node.children(&mut cursor) {
+            if is_value_position(child) {
+                if let Some(name) = node_str(child, content) {
+                    result.push(name);
+                }
+            }
+            queue.push(child);
+        }
+    }
+    result
+}
+
+fn is_value_position(node: Node) -> bool {
+    if node.kind() != "identifier" && node.kind() != "member_expression" {
+        return false;
+    }
+    let parent = match node.parent() {
+        Some(parent) => parent,
+        None => return false,
+    };
+    match parent.kind() {
+        "arguments" | "return_statement" | "array" => true,
+        "pair" | "variable_declarator" => is_field(parent, "value", node),
+        "assignment_expression" => is_field(parent, "right", node),
+        _ => false,
+    }
+}
+
+fn is_field(parent: Node, field: &str, node: Node) -> bool {
+    parent
+        .child_by_field_name(field)
+        .is_some_and(|child| child.id() == node.id())
+}
+
+/// Handlers registered by `app.get("/path", handler)` style calls.
+pub(crate) fn route_handlers(call: Node, content: &str) -> Vec<String> {
+    let mut result = vec![];
+    let callee = match call.child_by_field_name("function") {
+        Some(callee) if callee.kind() == "member_expression" => callee,
+        _ => return result,
+    };
+    let method = str_by_field_name(callee, "property", content).unwrap_or_default();
+    if !ROUTE_METHODS.contains(&method.as_str()) {
+        return result;
+    }
+    let args = match call.child_by_field_name("arguments") {
+        Some(args) => args,
+        None => return result,
+    };
+    let mut cursor = args.walk();
+    let args: Vec<Node> = args.named_children(&mut cursor).collect();
+    match args.first() {
+        Some(path) if path.kind() == "string" || path.kind() == "template_string" => {}
+        _ => return result,
+    }
+    for arg in &args[1..] {
+        if arg.kind() == "identifier" || arg.kind() == "member_expression" {
+            if let Some(name) = node_str(*arg, content) {
+                result.push(name);
+            }
+        }
+    }
+    result
+}
+
+/// Title and callback of a Jest/Mocha style `it("title", () => ...)` call
+/// whose callee is one of `names`, including `it.only` and `it.skip`.
+pub(crate) fn test_callback<'a>(
+    call: Node<'a>,
+    names: &[&str],
+    content: &str,
+) -> Option<(String, Node<'a>)> {
+    let callee = call.child_by_field_name("function")?;
+    let callee = match callee.kind() {
+        "identifier" => callee,
+        "member_expression" => callee.child_by_field_name("object")?,
+        _ => return None,
+    };
+    if callee.kind() != "identifier" || !names.contains(&node_str(callee, content)?.as_str()) {
+        return None;
+    }
+    let args = call.child_by_field_name("arguments")?;
+    let mut cursor = args.walk();
+    let args: Vec<Node> = args.named_children(&mut cursor).collect();
+    let title = args.first()?;
+    if title.kind() != "string" && title.kind() != "template_string" {
+        return None;
+    }
+    let title = node_str(*title, content)?;
+    let title = title.trim_matches(|c| c == '"' || c == '\'' || c == '`');
+    let callback = args.iter().skip(1).find(|arg| {
+        matches!(
+            arg.kind(),
+            "arrow_function" | "function" | "function_expression"
+        )
+    })?;
+    Some((title.to_string(), *callback))
+}
+
+/// The key of a test callback: its file, relative to the project, and the
+/// titles of its blocks, e.g. `src/math.test.ts > math > adds`.
+pub(crate) fn test_key(file: &str, titles: &[String]) -> String {
+    std::iter::once(file)
+        .chain(titles.iter().map(|t| t.as_str()))
+        .collect::<Vec<_>>()
+        .join(" > ")
+}
+
+/// Titles of the `describe` blocks around a test, outermost first.
+pub(crate) fn describe_titles(call: Node, content: &str) -> Vec<String> {
+    let mut titles = vec![];
+    let mut parent = call.parent();
+    while let Some(node) = parent {
+        if node.kind() == "call_expression" {
+            if let Some((title, _)) = test_callback(node, &SUITE_FUNCTIONS, content) {
+                titles.push(title);
+            }
+        }
+        parent = node.parent();
+    }
+    titles.reverse();
+    titles
+}
+
+/// Calls made from `beforeEach` style hooks of the suites around a test,
+/// which run as part of it.
+pub(crate) fn enclosing_hooks<'a>(call: Node<'a>, content: &str) -> Vec<Node<'a>> {
+    let mut result = vec![];
+    let mut parent = call.parent();
+    while let Some(node) = parent {
+        if node.kind() == "call_expression" {
+            if let Some((_, body)) = test_callback(node, &SUITE_FUNCTIONS, content) {
+                for hook in walk_collect(body, "call_expression") {
+                    if is_hook(hook, body, content) {
+                        result.extend(walk_collect(hook, "call_expression"));
+                    }
+                }
+            }
+        }
+        parent = node.parent();
+    }
+    result
+}
+
+/// A hook registered as a statement directly inside the suite `body`.
+fn is_hook(call: Node, body: Node, content: &str) -> bool {
+    let name = str_by_field_name(call, "function", content).unwrap_or_default();
+    HOOK_FUNCTIONS.contains(&name.as_str())
+        && call
+            .parent()
+            .and_then(|statement| statement.parent())
+            .and_then(|block| block.parent())
+            .is_some_and(|callback| callback.id() == body.id())
+}
+
+/// A name bound by an `import` statement.
+pub(crate) struct Import {
+    pub local: String,
+    /// The exported name, `default` for a default import, `None` for
+    /// `import * as ns`.
+    pub imported: Option<String>,
+    pub specifier: String,
+}
+
+/// The bindings of the top level `import` statements of a file.
+pub(crate) fn imports(root: Node, content: &str) -> Vec<Import> {
+    let mut result = vec![];
+    let mut cursor = root.walk();
+    for statement in root.children(&mut cursor) {
+        if statement.kind() != "import_statement" {
+            continue;
+        }
+        let specifier = match str_by_field_name(statement, "source", content) {
+            Some(source) => source.trim_matches(|c| c == '"' || c == '\'').to_string(),
+            None => continue,
+        };
+        let mut bind = |local: Option<String>, imported: Option<String>| {
+            if let Some(local) = local {
+                result.push(Import {
+                    local,
+                    imported,
+                    specifier: specifier.clone(),
+                });
+            }
+        };
+        for clause in walk_collect(statement, "import_clause") {
+            let mut cursor = clause.walk();
+            for binding in clause.named_children(&mut cursor) {
+                match binding.kind() {
+                    "identifier" => bind(node_str(binding, content), Some("default".to_string())),
+                    "namespace_import" => {
+                        let mut cursor = binding.walk();
+                        let name = binding.named_children(&mut cursor).next();
+                        bind(name.and_then(|n| node_str(n, content)), None);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        for specifier in walk_collect(statement, "import_specifier") {
+            let name = str_by_field_name(specifier, "name", content);
+            let alias = str_by_field_name(specifier, "alias", content);
+            bind(alias.or_else(|| name.clone()), name);
+        }
+    }
+    result
+}
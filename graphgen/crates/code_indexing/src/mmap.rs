@@ -0,0 +1,878 @@
+//! An index layout that is queried in place from a memory map instead of
+//! being deserialised, for projects where loading the bincode index is too
+//! slow. All integers are little endian u32 and every section is 8 byte
+//! aligned:
+//!
+//! - a string table, whose first `nodes` entries are the sorted symbol
+//!   names, so a node id is also the id of its name;
+//! - call, caller and reference adjacency in CSR form, i.e. an offsets array
+//!   of `nodes + 1` entries into a flat targets array;
+//! - fixed size function and class records. Function bodies are byte spans
+//!   into the source files rather than copies;
+//! - the extra roots, file records and diagnostics as one bincode blob, only
+//!   read by `to_code_index`.
+
+use bincode::Options;
+use log::error;
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::callees::CallKind;
+use crate::diagnostics::Diagnostic;
+use crate::format::IndexError;
+use crate::graph::GraphNode;
+use crate::incremental::FileRecord;
+use crate::{CallSite, Class, CodeIndex, Function, IDGenerator, Span};
+
+pub const MMAP_MAGIC: &[u8; 8] = b"GGMAPIDX";
+pub const MMAP_VERSION: u32 = 1;
+
+const NONE: u32 = u32::MAX;
+const FLAG_ROUTE: u32 = 1;
+const FLAG_EXPORTED: u32 = 1;
+const FLAG_TEST: u32 = 2;
+
+/// node, name, pkg, file, line, end_line, name line, name column, name end
+/// column, flags, inline body string or NONE, the body byte span as low and
+/// high halves of two u64, then the package.
+const FUNCTION_FIELDS: usize = 16;
+/// name, file, extends or NONE, declaration, package.
+const CLASS_FIELDS: usize = 5;
+/// line, column, end column, then the call's kind as its index in
+/// `CallKind::ALL`.
+const SPAN_FIELDS: usize = 4;
+
+/// Sections in file order, each with its byte offset in the header.
+#[derive(Clone, Copy)]
+enum Section {
+    StringOffsets,
+    StringData,
+    CallOffsets,
+    CallTargets,
+    CallSpans,
+    CallerOffsets,
+    CallerSources,
+    RefOffsets,
+    RefTargets,
+    NodeFlags,
+    NodeFunctions,
+    Functions,
+    Classes,
+    Extras,
+}
+
+const SECTIONS: usize = 14;
+
+/// Counts stored after the magic and version, in declaration order.
+#[derive(Debug, Default, Clone, Copy)]
+struct Counts {
+    nodes: u32,
+    strings: u32,
+    calls: u32,
+    refs: u32,
+    functions: u32,
+    classes: u32,
+    root: u32,
+    /// Bytes of the `Extras` section.
+    extras: u32,
+}
+
+const COUNTS: usize = 8;
+const HEADER_LEN: usize = 8 + 4 + 4 * COUNTS + 8 * SECTIONS;
+
+/// What the map keeps of a `CodeIndex` besides the graph, with ids as
+/// `to_code_index` hands them out.
+#[derive(Serialize, Deserialize, Default)]
+struct Extras {
+    extra_roots: Vec<String>,
+    files: BTreeMap<String, FileRecord>,
+    diagnostics: BTreeMap<String, Vec<Diagnostic>>,
+}
+
+/// True if `path` starts with the memory-mapped index magic.
+pub fn is_mapped_index(path: &str) -> bool {
+    let mut magic = [0u8; 8];
+    std::fs::File::open(path)
+        .and_then(|mut f| f.read_exact(&mut magic))
+        .is_ok_and(|_| &magic == MMAP_MAGIC)
+}
+
+#[derive(Default)]
+struct Strings {
+    data: Vec<Vec<u8>>,
+    ids: BTreeMap<String, u32>,
+}
+
+impl Strings {
+    fn intern(&mut self, s: &str) -> u32 {
+        if let Some(id) = self.ids.get(s) {
+            return *id;
+        }
+        let id = self.data.len() as u32;
+        self.data.push(s.as_bytes().to_vec());
+        self.ids.insert(s.to_string(), id);
+        id
+    }
+}
+
+/// CSR arrays for `adjacency`, keyed by node.
+fn csr(nodes: usize, adjacency: &BTreeMap<u32, Vec<u32>>) -> (Vec<u32>, Vec<u32>) {
+    let mut offsets = Vec::with_capacity(nodes + 1);
+    let mut targets = vec![];
+    for node in 0..nodes as u32 {
+        offsets.push(targets.len() as u32);
+        targets.extend(adjacency.get(&node).into_iter().flatten());
+    }
+    offsets.push(targets.len() as u32);
+    (offsets, targets)
+}
+
+/// Byte range of the body in its source file, searched for from the
+/// declaration's first line.
+fn body_span(
+    sources: &mut BTreeMap<String, Option<String>>,
+    func: &Function,
+) -> Option<(u64, u64)> {
+    let content = sources
+        .entry(func.file.clone())
+        .or_insert_with(|| std::fs::read_to_string(&func.file).ok())
+        .as_ref()?;
+    let line_start: usize = content
+        .split_inclusive('\n')
+        .take(func.line.saturating_sub(1))
+        .map(|l| l.len())
+        .sum();
+    let start = line_start + content.get(line_start..)?.find(&func.body)?;
+    Some((start as u64, (start + func.body.len()) as u64))
+}
+
+fn kind_index(kind: CallKind) -> u32 {
+    CallKind::ALL
+        .iter()
+        .position(|k| *k == kind)
+        .unwrap_or_default() as u32
+}
+
+fn split(value: u64) -> [u32; 2] {
+    [value as u32, (value >> 32) as u32]
+}
+
+impl CodeIndex {
+    /// Writes the index in the layout read by `MappedIndex`. Bodies whose
+    /// source file cannot be read, or no longer matches, are stored inline.
+    pub fn into_mapped_file(&self, filename: &str) -> Result<(), IndexError> {
+        let mut strings = Strings::default();
+        // id_map is ordered by name, so node ids follow the sorted names.
+        let mut node_of: BTreeMap<u64, u32> = BTreeMap::new();
+        for (name, id) in self.id_gen.id_map.iter() {
+            node_of.insert(*id, strings.intern(name));
+        }
+        let nodes = node_of.len();
+
+        let mut calls: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
+        let mut spans: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
+        let mut callers: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
+        for (from, sites) in self.call_sites.iter() {
+            let from = node_of[from];
+            for site in sites {
+                let to = node_of[&site.callee];
+                calls.entry(from).or_default().push(to);
+                spans.entry(from).or_default().extend([
+                    site.span.line as u32,
+                    site.span.column as u32,
+                    site.span.end_column as u32,
+                    kind_index(site.kind),
+                ]);
+                callers.entry(to).or_default().push(from);
+            }
+        }
+        let mut refs: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
+        for (from, targets) in self.refs.iter() {
+            refs.entry(node_of[from])
+                .or_default()
+                .extend(targets.iter().map(|t| node_of[t]));
+        }
+
+        let mut node_flags = vec![0u32; nodes];
+        for id in self.routes.iter() {
+            if let Some(node) = node_of.get(id) {
+                node_flags[*node as usize] |= FLAG_ROUTE;
+            }
+        }
+        let mut node_functions = vec![NONE; nodes];
+        let mut functions: Vec<u32> = vec![];
+        let mut sources = BTreeMap::new();
+        for (i, (key, func)) in self.functions.iter().enumerate() {
+            let node = node_of[&self.id_gen.id_map[key]];
+            node_functions[node as usize] = i as u32;
+            let (body, (start, end)) = match body_span(&mut sources, func) {
+                Some(span) => (NONE, span),
+                None => (strings.intern(&func.body), (0, 0)),
+            };
+            let mut flags = 0;
+            if func.exported {
+                flags |= FLAG_EXPORTED;
+            }
+            if func.test {
+                flags |= FLAG_TEST;
+            }
+            functions.extend([
+                node,
+                strings.intern(&func.name),
+                strings.intern(&func.pkg),
+                strings.intern(&func.file),
+                func.line as u32,
+                func.end_line as u32,
+                func.name_span.line as u32,
+                func.name_span.column as u32,
+                func.name_span.end_column as u32,
+                flags,
+                body,
+            ]);
+            functions.extend(split(start));
+            functions.extend(split(end));
+            functions.push(strings.intern(&func.package));
+        }
+        let mut classes: Vec<u32> = vec![];
+        for class in self.classes.values() {
+            let extends = match &class.extends {
+                Some(parent) => strings.intern(parent),
+                None => NONE,
+            };
+            classes.extend([
+                strings.intern(&class.name),
+                strings.intern(&class.file),
+                extends,
+                strings.intern(&class.declaration),
+                strings.intern(&class.package),
+            ]);
+        }
+        let root = strings.intern(&self.root);
+        let renumber = |id: u64| node_of.get(&id).map_or(0, |node| *node as u64 + 1);
+        let extras = bincode::serialize(&Extras {
+            extra_roots: self.extra_roots.clone(),
+            files: self
+                .files
+                .iter()
+                .map(|(path, record)| (path.clone(), record.renumbered(renumber)))
+                .collect(),
+            diagnostics: self.diagnostics.clone(),
+        })?;
+
+        let mut string_offsets: Vec<u32> = Vec::with_capacity(strings.data.len() + 1);
+        let mut string_data: Vec<u8> = vec![];
+        for s in strings.data.iter() {
+            string_offsets.push(string_data.len() as u32);
+            string_data.extend_from_slice(s);
+        }
+        string_offsets.push(string_data.len() as u32);
+
+        let (call_offsets, call_targets) = csr(nodes, &calls);
+        let (_, call_spans) = csr(nodes, &spans);
+        let (caller_offsets, caller_sources) = csr(nodes, &callers);
+        let (ref_offsets, ref_targets) = csr(nodes, &refs);
+
+        let sections: [Vec<u8>; SECTIONS] = [
+            le_bytes(&string_offsets),
+            string_data,
+            le_bytes(&call_offsets),
+            le_bytes(&call_targets),
+            le_bytes(&call_spans),
+            le_bytes(&caller_offsets),
+            le_bytes(&caller_sources),
+            le_bytes(&ref_offsets),
+            le_bytes(&ref_targets),
+            le_bytes(&node_flags),
+            le_bytes(&node_functions),
+            le_bytes(&functions),
+            le_bytes(&classes),
+            extras,
+        ];
+        let counts = [
+            nodes as u32,
+            strings.data.len() as u32,
+            call_targets.len() as u32,
+            ref_targets.len() as u32,
+            self.functions.len() as u32,
+            self.classes.len() as u32,
+            root,
+            sections[Section::Extras as usize].len() as u32,
+        ];
+
+        let mut out =
+            Vec::with_capacity(HEADER_LEN + sections.iter().map(|s| s.len() + 8).sum::<usize>());
+        out.extend_from_slice(MMAP_MAGIC);
+        out.extend_from_slice(&MMAP_VERSION.to_le_bytes());
+        out.extend(le_bytes(&counts));
+        let mut offset = align(HEADER_LEN);
+        for section in sections.iter() {
+            out.extend_from_slice(&(offset as u64).to_le_bytes());
+            offset = align(offset + section.len());
+        }
+        for section in sections.iter() {
+            out.resize(align(out.len()), 0);
+            out.extend_from_slice(section);
+        }
+
+        let tmp = format!("{}.tmp", filename);
+        std::fs::write(&tmp, out)?;
+        std::fs::rename(&tmp, filename)?;
+        Ok(())
+    }
+}
+
+fn le_bytes(values: &[u32]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+fn align(offset: usize) -> usize {
+    offset.div_ceil(8) * 8
+}
+
+/// A function record read from the map.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MappedFunction<'a> {
+    pub name: &'a str,
+    pub class: &'a str,
+    pub file: &'a str,
+    pub line: usize,
+    pub end_line: usize,
+    pub exported: bool,
+    pub test: bool,
+    pub package: &'a str,
+}
+
+pub struct MappedIndex {
+    map: Mmap,
+    counts: Counts,
+    sections: [usize; SECTIONS],
+}
+
+impl MappedIndex {
+    pub fn open(filename: &str) -> Result<Self, IndexError> {
+        let file = std::fs::File::open(filename)?;
+        // Safety: the file is only read, and is replaced by renaming rather
+        // than rewritten in place.
+        let map = unsafe { Mmap::map(&file)? };
+        if map.len() < HEADER_LEN || &map[..8] != MMAP_MAGIC {
+            return Err(IndexError::NotAnIndex);
+        }
+        let word = |i: usize| u32::from_le_bytes(map[i..i + 4].try_into().unwrap());
+        let version = word(8);
+        if version != MMAP_VERSION {
+            return Err(IndexError::UnsupportedVersion(version));
+        }
+        let c: Vec<u32> = (0..COUNTS).map(|i| word(12 + 4 * i)).collect();
+        let counts = Counts {
+            nodes: c[0],
+            strings: c[1],
+            calls: c[2],
+            refs: c[3],
+            functions: c[4],
+            classes: c[5],
+            root: c[6],
+            extras: c[7],
+        };
+        let mut sections = [0; SECTIONS];
+        for (i, section) in sections.iter_mut().enumerate() {
+            let at = 12 + 4 * COUNTS + 8 * i;
+            *section = u64::from_le_bytes(map[at..at + 8].try_into().unwrap()) as usize;
+        }
+        let index = MappedIndex {
+            map,
+            counts,
+            sections,
+        };
+        index.validate()?;
+        Ok(index)
+    }
+
+    /// Checks every section fits in the file, and every offset and id in
+    /// them stays within the section it points into, so later reads cannot
+    /// panic. This reads the whole map once.
+    fn validate(&self) -> Result<(), IndexError> {
+        let malformed = |what: &str| Err(IndexError::Malformed(what.to_string()));
+        let c = self.counts;
+        let nodes = c.nodes as usize + 1;
+        let lengths = [
+            (Section::StringOffsets, 4 * (c.strings as usize + 1)),
+            (Section::CallOffsets, 4 * nodes),
+            (Section::CallTargets, 4 * c.calls as usize),
+            (Section::CallSpans, 4 * SPAN_FIELDS * c.calls as usize),
+            (Section::CallerOffsets, 4 * nodes),
+            (Section::CallerSources, 4 * c.calls as usize),
+            (Section::RefOffsets, 4 * nodes),
+            (Section::RefTargets, 4 * c.refs as usize),
+            (Section::NodeFlags, 4 * (nodes - 1)),
+            (Section::NodeFunctions, 4 * (nodes - 1)),
+            (
+                Section::Functions,
+                4 * FUNCTION_FIELDS * c.functions as usize,
+            ),
+            (Section::Classes, 4 * CLASS_FIELDS * c.classes as usize),
+            (Section::Extras, c.extras as usize),
+        ];
+        for (section, len) in lengths {
+            let start = self.sections[section as usize];
+            if start
+                .checked_add(len)
+                .is_none_or(|end| end > self.map.len())
+            {
+                return Err(IndexError::Malformed(format!(
+                    "section {} out of bounds",
+                    section as usize
+                )));
+            }
+        }
+        if c.nodes > c.strings || c.root >= c.strings {
+            return malformed("string table too short");
+        }
+        let data = self.sections[Section::StringData as usize];
+        let last = self.word(Section::StringOffsets, c.strings as usize) as usize;
+        if data
+            .checked_add(last)
+            .is_none_or(|end| end > self.map.len())
+        {
+            return malformed("string data out of bounds");
+        }
+        if !self.ascending(Section::StringOffsets, c.strings as usize + 1, last) {
+            return malformed("string offsets out of order");
+        }
+
+        let adjacency = [
+            (Section::CallOffsets, Section::CallTargets, c.calls),
+            (Section::CallerOffsets, Section::CallerSources, c.calls),
+            (Section::RefOffsets, Section::RefTargets, c.refs),
+        ];
+        for (offsets, targets, count) in adjacency {
+            if !self.ascending(offsets, nodes, count as usize) {
+                return malformed("adjacency offsets out of order");
+            }
+            if self.words(targets, 0, count as usize).any(|t| t >= c.nodes) {
+                return malformed("adjacency target out of range");
+            }
+        }
+        if self
+            .words(Section::NodeFunctions, 0, c.nodes as usize)
+            .any(|f| f != NONE && f >= c.functions)
+        {
+            return malformed("function record out of range");
+        }
+
+        let string = |id: u32| id < c.strings;
+        let optional = |id: u32| id == NONE || id < c.strings;
+        for i in 0..c.functions as usize {
+            let r = i * FUNCTION_FIELDS;
+            let join = |lo: usize| self.field(r, lo) as u64 | (self.field(r, lo + 1) as u64) << 32;
+            if ![1, 2, 3, 15].into_iter().all(|f| string(self.field(r, f)))
+                || !optional(self.field(r, 10))
+                || join(11) > join(13)
+            {
+                return malformed("function record out of range");
+            }
+        }
+        for i in 0..c.classes as usize {
+            let r = i * CLASS_FIELDS;
+            let word = |f: usize| self.word(Section::Classes, r + f);
+            if ![0, 1, 3, 4].into_iter().all(|f| string(word(f))) || !optional(word(2)) {
+                return malformed("class record out of range");
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the first `len` words of `section` never decrease and end at
+    /// most at `max`.
+    fn ascending(&self, section: Section, len: usize, max: usize) -> bool {
+        let mut previous = 0;
+        self.words(section, 0, len).all(|w| {
+            let ok = w >= previous && w as usize <= max;
+            previous = w;
+            ok
+        })
+    }
+
+    fn word(&self, section: Section, i: usize) -> u32 {
+        let at = self.sections[section as usize] + 4 * i;
+        u32::from_le_bytes(self.map[at..at + 4].try_into().unwrap())
+    }
+
+    fn words(&self, section: Section, start: usize, end: usize) -> impl Iterator<Item = u32> + '_ {
+        (start..end).map(move |i| self.word(section, i))
+    }
+
+    fn string(&self, id: u32) -> &str {
+        let start = self.word(Section::StringOffsets, id as usize) as usize;
+        let end = self.word(Section::StringOffsets, id as usize + 1) as usize;
+        let data = self.sections[Section::StringData as usize];
+        std::str::from_utf8(&self.map[data + start..data + end]).unwrap_or("")
+    }
+
+    fn adjacent(
+        &self,
+        offsets: Section,
+        targets: Section,
+        node: u32,
+    ) -> impl Iterator<Item = u32> + '_ {
+        let start = self.word(offsets, node as usize) as usize;
+        let end = self.word(offsets, node as usize + 1) as usize;
+        self.words(targets, start, end)
+    }
+
+    pub fn root(&self) -> &str {
+        self.string(self.counts.root)
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.counts.nodes as usize
+    }
+
+    /// The node named `name`, by binary search over the sorted names.
+    pub fn node(&self, name: &str) -> Option<u32> {
+        let (mut lo, mut hi) = (0, self.counts.nodes);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match self.string(mid).cmp(name) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => return Some(mid),
+            }
+        }
+        None
+    }
+
+    pub fn name(&self, node: u32) -> &str {
+        self.string(node)
+    }
+
+    /// Callees in call site order, repeated once per call.
+    pub fn callees(&self, node: u32) -> impl Iterator<Item = u32> + '_ {
+        self.adjacent(Section::CallOffsets, Section::CallTargets, node)
+    }
+
+    pub fn callers(&self, node: u32) -> impl Iterator<Item = u32> + '_ {
+        self.adjacent(Section::CallerOffsets, Section::CallerSources, node)
+    }
+
+    pub fn references(&self, node: u32) -> impl Iterator<Item = u32> + '_ {
+        self.adjacent(Section::RefOffsets, Section::RefTargets, node)
+    }
+
+    fn record(&self, node: u32) -> Option<usize> {
+        match self.word(Section::NodeFunctions, node as usize) {
+            NONE => None,
+            i => Some(i as usize * FUNCTION_FIELDS),
+        }
+    }
+
+    fn field(&self, record: usize, i: usize) -> u32 {
+        self.word(Section::Functions, record + i)
+    }
+
+    pub fn function(&self, node: u32) -> Option<MappedFunction<'_>> {
+        let r = self.record(node)?;
+        let flags = self.field(r, 9);
+        Some(MappedFunction {
+            name: self.string(self.field(r, 1)),
+            class: self.string(self.field(r, 2)),
+            file: self.string(self.field(r, 3)),
+            line: self.field(r, 4) as usize,
+            end_line: self.field(r, 5) as usize,
+            exported: flags & FLAG_EXPORTED != 0,
+            test: flags & FLAG_TEST != 0,
+            package: self.string(self.field(r, 15)),
+        })
+    }
+
+    /// The function body, read from its source file unless it was stored
+    /// inline. Edits to the source since indexing show up here.
+    pub fn body(&self, node: u32) -> std::io::Result<Option<String>> {
+        let r = match self.record(node) {
+            Some(r) => r,
+            None => return Ok(None),
+        };
+        let inline = self.field(r, 10);
+        if inline != NONE {
+            return Ok(Some(self.string(inline).to_string()));
+        }
+        let join = |lo: usize| self.field(r, lo) as u64 | (self.field(r, lo + 1) as u64) << 32;
+        let (start, end) = (join(11), join(13));
+        let mut file = std::fs::File::open(self.string(self.field(r, 3)))?;
+        if end > file.metadata()?.len() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "source file shorter than when indexed",
+            ));
+        }
+        file.seek(SeekFrom::Start(start))?;
+        let mut buffer = vec![0; end.saturating_sub(start) as usize];
+        file.read_exact(&mut buffer)?;
+        Ok(Some(String::from_utf8_lossy(&buffer).into_owned()))
+    }
+
+    /// Qualified names of all functions, sorted.
+    pub fn function_list(&self) -> Vec<String> {
+        (0..self.counts.nodes)
+            .filter(|node| self.record(*node).is_some())
+            .map(|node| self.name(node).to_string())
+            .collect()
+    }
+
+    /// The same tree as `CodeIndex::serde_tree`, without loading the index.
+    pub fn serde_tree(&self, funcname: &str, depth: i32) -> Option<GraphNode> {
+        self.serde_tree_without(funcname, depth, &[])
+    }
+
+    /// The same tree as `CodeIndex::serde_tree_without`.
+    pub fn serde_tree_without(
+        &self,
+        funcname: &str,
+        depth: i32,
+        kinds: &[CallKind],
+    ) -> Option<GraphNode> {
+        match self.node(funcname) {
+            Some(node) => self.tree(node, depth, kinds),
+            // serde_tree returns a lone node for unknown names.
+            None if depth != 0 => Some(GraphNode {
+                name: funcname.to_string(),
+                children: vec![],
+                value: 0,
+                metric: None,
+            }),
+            None => None,
+        }
+    }
+
+    fn tree(&self, node: u32, depth: i32, kinds: &[CallKind]) -> Option<GraphNode> {
+        if depth == 0 {
+            return None;
+        }
+        let callees: Vec<u32> = self
+            .callees(node)
+            .enumerate()
+            .filter(|(i, c)| !kinds.contains(&self.resolve_kind(*c, self.call_kind(node, *i))))
+            .map(|(_, c)| c)
+            .collect();
+        Some(GraphNode {
+            name: self.name(node).to_string(),
+            value: callees.len(),
+            children: callees
+                .into_iter()
+                .filter_map(|c| self.tree(c, depth - 1, kinds))
+                .collect(),
+            metric: None,
+        })
+    }
+
+    /// The stored kind of the `i`th call of `node`.
+    fn call_kind(&self, node: u32, i: usize) -> CallKind {
+        let start = self.word(Section::CallOffsets, node as usize) as usize;
+        let kind = self.word(Section::CallSpans, SPAN_FIELDS * (start + i) + 3);
+        CallKind::ALL
+            .get(kind as usize)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// As `CodeIndex::call_kind` decides it.
+    fn resolve_kind(&self, callee: u32, kind: CallKind) -> CallKind {
+        match kind {
+            CallKind::External => CallKind::External,
+            _ if self.record(callee).is_some() => CallKind::Resolved,
+            kind => kind,
+        }
+    }
+
+    /// The extra roots, file records and diagnostics, or none of them if
+    /// they do not decode.
+    fn extras(&self) -> Extras {
+        let start = self.sections[Section::Extras as usize];
+        let len = self.counts.extras as usize;
+        let options = bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .with_limit(len as u64);
+        options
+            .deserialize(&self.map[start..start + len])
+            .unwrap_or_else(|e| {
+                error!("corrupt file records in mapped index: {}", e);
+                Extras::default()
+            })
+    }
+
+    /// Loads everything into a `CodeIndex`, for queries the map cannot
+    /// answer in place.
+    pub fn to_code_index(&self) -> CodeIndex {
+        let mut index = CodeIndex::new();
+        index.root = self.root().to_string();
+        let extras = self.extras();
+        index.extra_roots = extras.extra_roots;
+        index.files = extras.files;
+        index.diagnostics = extras.diagnostics;
+        let mut id_gen = IDGenerator::new();
+        for node in 0..self.counts.nodes {
+            id_gen.id(self.name(node));
+        }
+        // ids are handed out in node order, starting at 1.
+        let id = |node: u32| node as u64 + 1;
+        for node in 0..self.counts.nodes {
+            let start = self.word(Section::CallOffsets, node as usize) as usize;
+            let callees: Vec<u32> = self.callees(node).collect();
+            if !callees.is_empty() {
+                index
+                    .edges
+                    .insert(id(node), callees.iter().map(|c| id(*c)).collect());
+                let sites = callees
+                    .iter()
+                    .enumerate()
+                    .map(|(i, callee)| {
+                        let at = SPAN_FIELDS * (start + i);
+                        CallSite {
+                            callee: id(*callee),
+                            span: Span {
+                                line: self.word(Section::CallSpans, at) as usize,
+                                column: self.word(Section::CallSpans, at + 1) as usize,
+                                end_column: self.word(Section::CallSpans, at + 2) as usize,
+                            },
+                            kind: self.call_kind(node, i),
+                        }
+                    })
+                    .collect();
+                index.call_sites.insert(id(node), sites);
+            }
+            let refs: Vec<u64> = self.references(node).map(id).collect();
+            if !refs.is_empty() {
+                index.refs.insert(id(node), refs);
+            }
+            if self.word(Section::NodeFlags, node as usize) & FLAG_ROUTE != 0 {
+                index.routes.insert(id(node));
+            }
+            if let (Some(func), Some(r)) = (self.function(node), self.record(node)) {
+                let function = Function {
+                    name: func.name.to_string(),
+                    pkg: func.class.to_string(),
+                    file: func.file.to_string(),
+                    line: func.line,
+                    end_line: func.end_line,
+                    name_span: Span {
+                        line: self.field(r, 6) as usize,
+                        column: self.field(r, 7) as usize,
+                        end_column: self.field(r, 8) as usize,
+                    },
+                    exported: func.exported,
+                    test: func.test,
+                    body: self.body(node).ok().flatten().unwrap_or_default(),
+                    package: func.package.to_string(),
+                };
+                index
+                    .functions
+                    .insert(self.name(node).to_string(), function);
+            }
+        }
+        for i in 0..self.counts.classes as usize {
+            let r = i * CLASS_FIELDS;
+            let extends = self.word(Section::Classes, r + 2);
+            let class = Class {
+                name: self.string(self.word(Section::Classes, r)).to_string(),
+                file: self.string(self.word(Section::Classes, r + 1)).to_string(),
+                extends: (extends != NONE).then(|| self.string(extends).to_string()),
+                declaration: self.string(self.word(Section::Classes, r + 3)).to_string(),
+                package: self.string(self.word(Section::Classes, r + 4)).to_string(),
+            };
+            index.classes.insert(class.name.clone(), class);
+        }
+        index.id_gen = id_gen;
+        index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "class Repo {\n  find() { return query(); }\n}\nfunction query() { log(); }\nfunction serve(app) { app.get('/', handler); }\nfunction handler() { query(); }\n";
+
+    #[test]
+    fn test_mapped_index() {
+        let dir = std::env::temp_dir().join(format!("graphgen-mmap-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("repo.ts").display().to_string();
+        std::fs::write(&source, SOURCE).unwrap();
+        let mut index = CodeIndex::new();
+        index.parse_file(&source).unwrap();
+        let path = dir.join("index.map").display().to_string();
+        index.into_mapped_file(&path).unwrap();
+        assert!(is_mapped_index(&path));
+
+        let mapped = MappedIndex::open(&path).unwrap();
+        assert_eq!(mapped.function_list(), index.function_list());
+        let query = mapped.node("query").unwrap();
+        let callers: Vec<&str> = mapped.callers(query).map(|c| mapped.name(c)).collect();
+        assert_eq!(callers, vec!["Repo.find", "handler"]);
+        assert_eq!(mapped.function(query).unwrap().line, 4);
+        assert_eq!(mapped.body(query).unwrap().unwrap(), "{ log(); }");
+        assert_eq!(
+            serde_json::to_value(mapped.serde_tree("Repo.find", 3)).unwrap(),
+            serde_json::to_value(index.serde_tree("Repo.find", 3)).unwrap()
+        );
+
+        let loaded = mapped.to_code_index();
+        assert_eq!(loaded.documents(), index.documents());
+        assert_eq!(loaded.dead_code(&Default::default()).unwrap().reachable, 2);
+
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+        assert!(matches!(
+            MappedIndex::open(&path),
+            Err(IndexError::Malformed(_))
+        ));
+
+        // ids pointing out of their sections are caught on open too.
+        let at = 12 + 4 * COUNTS + 8 * Section::CallTargets as usize;
+        let targets = u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap()) as usize;
+        let mut corrupt = bytes.clone();
+        corrupt[targets..targets + 4].copy_from_slice(&0xffff_fff0u32.to_le_bytes());
+        std::fs::write(&path, &corrupt).unwrap();
+        assert!(matches!(
+            MappedIndex::open(&path),
+            Err(IndexError::Malformed(_))
+        ));
+        let at = 12 + 4 * COUNTS + 8 * Section::StringOffsets as usize;
+        let offsets = u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap()) as usize;
+        let mut corrupt = bytes.clone();
+        corrupt[offsets + 4..offsets + 8].copy_from_slice(&u32::MAX.to_le_bytes());
+        std::fs::write(&path, &corrupt).unwrap();
+        assert!(matches!(
+            MappedIndex::open(&path),
+            Err(IndexError::Malformed(_))
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_mapped_extras() {
+        let dir = std::env::temp_dir().join(format!("graphgen-mmap-extras-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.ts"), "function z() { b(); }\nfunction b() {}").unwrap();
+        std::fs::write(dir.join("broken.ts"), "function ok() { z(; }").unwrap();
+        let mut index = CodeIndex::new();
+        index.parse_project(&dir.display().to_string()).unwrap();
+        index.set_extra_roots(vec!["../shared".to_string()]);
+        let path = dir.join("index.map").display().to_string();
+        index.into_mapped_file(&path).unwrap();
+
+        let mut loaded = MappedIndex::open(&path).unwrap().to_code_index();
+        assert_eq!(loaded.diagnostics(), index.diagnostics());
+        assert_eq!(loaded.roots()[1], "../shared");
+        loaded.set_extra_roots(vec![]);
+        assert_eq!(loaded.reindex().unchanged, 2);
+        // file records refer to the ids of the sorted names, so the old call
+        // of `z` goes.
+        std::fs::write(dir.join("a.ts"), "function z() {}\nfunction b() {}").unwrap();
+        assert_eq!(loaded.reindex().changed, 1);
+        assert_eq!(loaded.serde_tree("z", 2).unwrap().value, 0);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
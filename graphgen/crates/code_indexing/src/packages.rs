@@ -0,0 +1,197 @@
+//! Workspace packages and project roots of the index, and the calls that
+//! cross from one into another: the dependency surface between libraries
+//! of a monorepo.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::CodeIndex;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct PackageSummary {
+    pub name: String,
+    pub files: usize,
+    pub functions: usize,
+    pub classes: usize,
+    /// Call sites in this package calling a function of another.
+    pub outgoing: usize,
+    /// Call sites in other packages calling a function of this one.
+    pub incoming: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CrossPackageCall {
+    pub caller: String,
+    pub caller_package: String,
+    pub callee: String,
+    pub callee_package: String,
+    /// The caller's file, relative to its root.
+    pub file: String,
+    pub line: usize,
+}
+
+impl CodeIndex {
+    /// Packages with at least one function or class, by name.
+    pub fn packages(&self) -> Vec<PackageSummary> {
+        let mut packages: BTreeMap<String, PackageSummary> = BTreeMap::new();
+        let mut files: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+        for func in self.functions.values().filter(|f| !f.package.is_empty()) {
+            summary(&mut packages, &func.package).functions += 1;
+            files.entry(&func.package).or_default().insert(&func.file);
+        }
+        for class in self.classes.values().filter(|c| !c.package.is_empty()) {
+            summary(&mut packages, &class.package).classes += 1;
+            files.entry(&class.package).or_default().insert(&class.file);
+        }
+        for call in self.cross_package_calls(None) {
+            summary(&mut packages, &call.caller_package).outgoing += 1;
+            summary(&mut packages, &call.callee_package).incoming += 1;
+        }
+        for (name, files) in files {
+            summary(&mut packages, name).files = files.len();
+        }
+        packages.into_values().collect()
+    }
+
+    /// Calls from a function of one package to a function of another, by
+    /// file and line. With `package`, only calls into or out of it.
+    pub fn cross_package_calls(&self, package: Option<&str>) -> Vec<CrossPackageCall> {
+        let mut calls = vec![];
+        for (from, sites) in self.call_sites.iter() {
+            let caller = match self.function_by_id(*from) {
+                Some(caller) if !caller.package.is_empty() => caller,
+                _ => continue,
+            };
+            for site in sites {
+                let callee = match self.function_by_id(site.callee) {
+                    Some(callee) if !callee.package.is_empty() => callee,
+                    _ => continue,
+                };
+                if caller.package == callee.package
+                    || package.is_some_and(|p| p != caller.package && p != callee.package)
+                {
+                    continue;
+                }
+                calls.push(CrossPackageCall {
+                    caller: caller.str(),
+                    caller_package: caller.package.clone(),
+                    callee: callee.str(),
+                    callee_package: callee.package.clone(),
+                    file: self.relative_path(&caller.file).to_string(),
+                    line: site.span.line,
+                });
+            }
+        }
+        calls.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+        calls
+    }
+
+    /// Qualified names of the functions of `package`, sorted.
+    pub fn package_function_list(&self, package: &str) -> Vec<String> {
+        self.functions
+            .iter()
+            .filter(|(_, f)| f.package == package)
+            .map(|(k, _)| k.clone())
+            .collect()
+    }
+}
+
+fn summary<'a>(
+    packages: &'a mut BTreeMap<String, PackageSummary>,
+    name: &str,
+) -> &'a mut PackageSummary {
+    packages
+        .entry(name.to_string())
+        .or_insert_with(|| PackageSummary {
+            name: name.to_string(),
+            ..PackageSummary::default()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packages_and_cross_package_calls() {
+        let dir = std::env::temp_dir().join(format!("graphgen-packages-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let write = |name: &str, content: &str| {
+            let file = dir.join(name);
+            std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+            std::fs::write(file, content).unwrap();
+        };
+        write("mono/package.json", r#"{ "workspaces": ["packages/*"] }"#);
+        write(
+            "mono/packages/core/package.json",
+            r#"{ "name": "@acme/core" }"#,
+        );
+        write(
+            "mono/packages/core/src/index.ts",
+            "export function init() { setup(); }\nfunction setup() {}",
+        );
+        write(
+            "mono/packages/web/package.json",
+            r#"{ "name": "@acme/web" }"#,
+        );
+        write(
+            "mono/packages/web/src/app.ts",
+            "import { init } from \"@acme/core\";\nexport class App { start() { init(); render(); format(); } }\nfunction render() {}",
+        );
+        // a second root, outside the monorepo and without package.json.
+        write("shared/fmt.ts", "export function format() {}");
+        let root = dir.join("mono").display().to_string();
+        let shared = dir.join("shared").display().to_string();
+
+        let mut index = CodeIndex::new();
+        index.set_extra_roots(vec![shared.clone()]);
+        index.parse_project(&root).unwrap();
+        assert_eq!(index.roots(), vec![root.as_str(), shared.as_str()]);
+        assert_eq!(index.functions["init"].package, "@acme/core");
+        assert_eq!(index.classes["App"].package, "@acme/web");
+        assert_eq!(index.functions["format"].package, "shared");
+        assert_eq!(
+            index.relative_path(&index.functions["format"].file),
+            "fmt.ts"
+        );
+
+        let packages = index.packages();
+        let names: Vec<&str> = packages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["@acme/core", "@acme/web", "shared"]);
+        assert_eq!(
+            packages[1],
+            PackageSummary {
+                name: "@acme/web".to_string(),
+                files: 1,
+                functions: 2,
+                classes: 1,
+                outgoing: 2,
+                incoming: 0,
+            }
+        );
+        assert_eq!(packages[0].incoming, 1);
+
+        let calls = index.cross_package_calls(Some("@acme/core"));
+        assert_eq!(
+            calls,
+            vec![CrossPackageCall {
+                caller: "App.start".to_string(),
+                caller_package: "@acme/web".to_string(),
+                callee: "init".to_string(),
+                callee_package: "@acme/core".to_string(),
+                file: "packages/web/src/app.ts".to_string(),
+                line: 2,
+            }]
+        );
+        assert_eq!(index.cross_package_calls(None).len(), 2);
+        assert_eq!(
+            index.package_function_list("@acme/core"),
+            vec!["init", "setup"]
+        );
+
+        let graph = index.aggregate(crate::aggregate::Granularity::Package);
+        assert_eq!(graph.nodes.len(), 3);
+        assert_eq!(graph.edges.len(), 2);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
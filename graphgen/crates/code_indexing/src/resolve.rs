@@ -0,0 +1,787 @@
+//! Resolves import specifiers to project files much as `tsc` does: relative
+//! paths, `compilerOptions.paths` and `baseUrl` of the nearest `tsconfig.json`
+//! (following `extends`), the `[paths]` aliases of `graphgen.toml`, and the
+//! packages of a pnpm, yarn or npm workspace. Each project root is a package
+//! too, so imports between the roots of a multi-root index resolve.
+//!
+//! Only imports of project files are resolved; names imported from `lodash`
+//! or `node:path` are only marked external, so their calls never connect to
+//! a project function that happens to share a name.
+
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Component, Path, PathBuf};
+use tree_sitter::Node;
+
+use crate::incremental::content_hash;
+use crate::misc::imports;
+
+// `extends` chains longer than this are assumed to be cycles.
+const MAX_EXTENDS: usize = 16;
+const TSCONFIG: &str = "tsconfig.json";
+
+/// `compilerOptions` that decide where imports go, after `extends`.
+#[derive(Debug, Clone, Default)]
+struct TsConfig {
+    base_url: Option<PathBuf>,
+    paths: Vec<(String, Vec<String>)>,
+    // targets in `paths` are relative to `baseUrl`, or else to the config
+    // that set them.
+    paths_dir: PathBuf,
+}
+
+#[derive(Debug, Clone)]
+struct Package {
+    name: String,
+    dir: PathBuf,
+    // `source`, `types`, `main`, ... as written in package.json.
+    entries: Vec<String>,
+    // false for a root without package.json, named after its directory,
+    // which nothing can import by that name.
+    manifest: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Resolver {
+    roots: Vec<PathBuf>,
+    aliases: TsConfig,
+    // by the directory they apply to.
+    tsconfigs: BTreeMap<PathBuf, TsConfig>,
+    packages: Vec<Package>,
+    fingerprint: u64,
+}
+
+impl Resolver {
+    /// Reads the tsconfigs and workspace packages of the project `roots`,
+    /// with `aliases` from `graphgen.toml`, relative to the first root,
+    /// taking precedence over tsconfig.
+    pub(crate) fn new(roots: &[PathBuf], aliases: &BTreeMap<String, Vec<String>>) -> Self {
+        let mut texts = vec![];
+        if !aliases.is_empty() {
+            texts.push(format!("{:?}", aliases));
+        }
+        if roots.len() > 1 {
+            texts.push(format!("{:?}", roots));
+        }
+        let mut packages: Vec<Package> = vec![];
+        for root in roots {
+            for package in workspace_packages(root, &mut texts) {
+                if !packages
+                    .iter()
+                    .any(|p| p.name == package.name || p.dir == package.dir)
+                {
+                    packages.push(package);
+                }
+            }
+        }
+        // `@acme/core-utils` before `@acme/core`, which is a prefix of it.
+        packages.sort_by(|a, b| b.name.len().cmp(&a.name.len()).then(a.name.cmp(&b.name)));
+        let mut tsconfigs = BTreeMap::new();
+        // each root's config, the projects they reference, and each package's.
+        let mut queue: Vec<PathBuf> = roots.iter().map(|r| r.join(TSCONFIG)).collect();
+        queue.extend(packages.iter().map(|p| p.dir.join(TSCONFIG)));
+        let mut seen = BTreeSet::new();
+        while let Some(file) = queue.pop() {
+            let file = match file.is_dir() {
+                true => normalize(&file.join(TSCONFIG)),
+                false => normalize(&file),
+            };
+            if !seen.insert(file.clone()) {
+                continue;
+            }
+            let json = match read_jsonc(&file, &mut texts) {
+                Some(json) => json,
+                None => continue,
+            };
+            let dir = parent(&file);
+            for reference in json["references"].as_array().into_iter().flatten() {
+                if let Some(path) = reference["path"].as_str() {
+                    queue.push(dir.join(path));
+                }
+            }
+            let config = tsconfig(&file, json, MAX_EXTENDS, &mut texts);
+            tsconfigs.entry(dir).or_insert(config);
+        }
+        let fingerprint = match texts.is_empty() {
+            true => 0,
+            false => content_hash(&texts.join("\0")),
+        };
+        Resolver {
+            roots: roots.to_vec(),
+            aliases: TsConfig {
+                base_url: None,
+                paths: aliases
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect(),
+                paths_dir: roots.first().cloned().unwrap_or_default(),
+            },
+            tsconfigs,
+            packages,
+            fingerprint,
+        }
+    }
+
+    /// Changes whenever a config file the resolver read changes, so files
+    /// parsed with an older one are parsed again.
+    pub(crate) fn fingerprint(&self) -> u64 {
+        self.fingerprint
+    }
+
+    /// The project file `specifier`, imported from `from`, refers to, as
+    /// the key it has in the index.
+    pub(crate) fn resolve(&self, from: &str, specifier: &str) -> Option<String> {
+        let from = Path::new(from);
+        if specifier == "." || specifier.starts_with("./") || specifier.starts_with("../") {
+            return source_file(&parent(from).join(specifier));
+        }
+        if let Some(file) = self.aliases.resolve(specifier) {
+            return Some(file);
+        }
+        if let Some(file) = self.tsconfig(from).and_then(|c| c.resolve(specifier)) {
+            return Some(file);
+        }
+        self.package(specifier)
+    }
+
+    /// The config of the deepest directory above `file` that has one.
+    fn tsconfig(&self, file: &Path) -> Option<&TsConfig> {
+        let mut dir = parent(file);
+        loop {
+            if let Some(config) = self.tsconfigs.get(&dir) {
+                return Some(config);
+            }
+            if self.roots.contains(&dir) || !dir.pop() {
+                return None;
+            }
+        }
+    }
+
+    /// The name of the innermost package or root `file` lies in.
+    pub(crate) fn package_of(&self, file: &str) -> Option<&str> {
+        self.packages
+            .iter()
+            .filter(|p| Path::new(file).starts_with(&p.dir))
+            .max_by_key(|p| p.dir.components().count())
+            .map(|p| p.name.as_str())
+    }
+
+    fn package(&self, specifier: &str) -> Option<String> {
+        for package in self.packages.iter().filter(|p| p.manifest) {
+            if specifier == package.name {
+                return package.entry();
+            }
+            if let Some(sub) = specifier
+                .strip_prefix(&package.name)
+                .and_then(|rest| rest.strip_prefix('/'))
+            {
+                return source_file(&package.dir.join(sub))
+                    .or_else(|| source_file(&package.dir.join("src").join(sub)));
+            }
+        }
+        None
+    }
+}
+
+impl TsConfig {
+    fn resolve(&self, specifier: &str) -> Option<String> {
+        // an exact pattern, or else the one with the longest prefix before
+        // its `*`, wins.
+        let best = self
+            .paths
+            .iter()
+            .filter_map(|(pattern, targets)| {
+                let star = match_pattern(pattern, specifier)?;
+                Some((pattern.find('*').unwrap_or(usize::MAX), star, targets))
+            })
+            .max_by_key(|(prefix, _, _)| *prefix);
+        if let Some((_, star, targets)) = best {
+            let dir = self.base_url.as_ref().unwrap_or(&self.paths_dir);
+            for target in targets {
+                if let Some(file) = source_file(&dir.join(target.replacen('*', star, 1))) {
+                    return Some(file);
+                }
+            }
+        }
+        source_file(&self.base_url.as_ref()?.join(specifier))
+    }
+}
+
+impl Package {
+    fn entry(&self) -> Option<String> {
+        self.entries
+            .iter()
+            .map(|e| e.trim_end_matches(".d.ts"))
+            .chain(["src/index.ts", "index.ts"])
+            .find_map(|e| source_file(&self.dir.join(e)))
+    }
+}
+
+/// What `*` stands for when `specifier` matches `pattern`, e.g. `core/log`
+/// for `@app/*` and `@app/core/log`.
+fn match_pattern<'a>(pattern: &str, specifier: &'a str) -> Option<&'a str> {
+    match pattern.split_once('*') {
+        Some((prefix, suffix)) => specifier
+            .strip_prefix(prefix)?
+            .strip_suffix(suffix)
+            .filter(|_| specifier.len() >= prefix.len() + suffix.len()),
+        None if pattern == specifier => Some(""),
+        None => None,
+    }
+}
+
+/// The TypeScript source an import of `path` loads, trying the extensions
+/// and `index` files the compiler would.
+fn source_file(path: &Path) -> Option<String> {
+    let path = normalize(path);
+    let mut candidates = vec![];
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("ts") => candidates.push(path.clone()),
+        // ESM style imports name the emitted file.
+        Some("js" | "mjs" | "cjs") => candidates.push(path.with_extension("ts")),
+        _ => {}
+    }
+    let mut with_ts = path.clone().into_os_string();
+    with_ts.push(".ts");
+    candidates.push(with_ts.into());
+    candidates.push(path.join("index.ts"));
+    candidates
+        .into_iter()
+        .find(|c| c.is_file())
+        .map(|c| c.display().to_string())
+}
+
+/// Drops `.` and folds `dir/..`, keeping the form of the index's file keys.
+fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(result.components().next_back(), Some(Component::Normal(_))) =>
+            {
+                result.pop();
+            }
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+fn parent(file: &Path) -> PathBuf {
+    file.parent().map(Path::to_path_buf).unwrap_or_default()
+}
+
+/// A tsconfig with the options of the configs it extends folded in.
+fn tsconfig(file: &Path, json: Value, depth: usize, texts: &mut Vec<String>) -> TsConfig {
+    let dir = parent(file);
+    let mut config = TsConfig::default();
+    let extends = match &json["extends"] {
+        Value::String(base) => vec![base.as_str()],
+        Value::Array(bases) => bases.iter().filter_map(|b| b.as_str()).collect(),
+        _ => vec![],
+    };
+    if depth > 0 {
+        // later bases override earlier ones, as in TypeScript 5.
+        for base in extends {
+            if let Some(base) = extended_file(&dir, base) {
+                if let Some(json) = read_jsonc(&base, texts) {
+                    let inherited = tsconfig(&base, json, depth - 1, texts);
+                    config.base_url = inherited.base_url.or(config.base_url);
+                    if !inherited.paths.is_empty() {
+                        config.paths = inherited.paths;
+                        config.paths_dir = inherited.paths_dir;
+                    }
+                }
+            }
+        }
+    }
+    let options = &json["compilerOptions"];
+    if let Some(base_url) = options["baseUrl"].as_str() {
+        config.base_url = Some(normalize(&dir.join(base_url)));
+    }
+    if let Some(paths) = options["paths"].as_object() {
+        config.paths = paths
+            .iter()
+            .map(|(pattern, targets)| {
+                let targets = targets.as_array().into_iter().flatten();
+                let targets = targets.filter_map(|t| t.as_str().map(String::from));
+                (pattern.clone(), targets.collect())
+            })
+            .collect();
+        config.paths_dir = dir;
+    }
+    config
+}
+
+/// The file an `extends` names: a path, or a config shipped in a package.
+fn extended_file(dir: &Path, base: &str) -> Option<PathBuf> {
+    let with_json = |path: PathBuf| {
+        let mut json = path.clone().into_os_string();
+        json.push(".json");
+        [path, json.into()].into_iter().find(|p| p.is_file())
+    };
+    if base.starts_with('.') || Path::new(base).is_absolute() {
+        return with_json(dir.join(base));
+    }
+    let mut dir = dir.to_path_buf();
+    loop {
+        let package = dir.join("node_modules").join(base);
+        if let Some(file) = with_json(package.clone())
+            .or_else(|| Some(package.join(TSCONFIG)).filter(|p| p.is_file()))
+        {
+            return Some(file);
+        }
+        if !dir.pop() {
+            return None;
+        }
+    }
+}
+
+/// Parses a JSON file that may have comments and trailing commas, like
+/// tsconfig.json, keeping its text in `texts`.
+fn read_jsonc(file: &Path, texts: &mut Vec<String>) -> Option<Value> {
+    let text = std::fs::read_to_string(file).ok()?;
+    let json = serde_json::from_str(&strip_jsonc(&text)).ok();
+    texts.push(text);
+    json
+}
+
+fn strip_jsonc(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if in_string {
+            result.push(c);
+            match c {
+                '\\' => result.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('/', Some('/')) => {
+                if let Some(newline) = chars.by_ref().find(|c| *c == '\n') {
+                    result.push(newline);
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut last = ' ';
+                for c in chars.by_ref() {
+                    if last == '*' && c == '/' {
+                        break;
+                    }
+                    last = c;
+                }
+            }
+            _ => {
+                in_string = c == '"';
+                result.push(c);
+            }
+        }
+    }
+    strip_trailing_commas(&result)
+}
+
+fn strip_trailing_commas(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if in_string {
+            result.push(c);
+            match c {
+                '\\' => result.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        in_string = c == '"';
+        let closes = || matches!(chars.clone().find(|c| !c.is_whitespace()), Some('}' | ']'));
+        if c != ',' || !closes() {
+            result.push(c);
+        }
+    }
+    result
+}
+
+/// `root` itself, then the packages listed by `workspaces` in its
+/// package.json or by pnpm-workspace.yaml.
+fn workspace_packages(root: &Path, texts: &mut Vec<String>) -> Vec<Package> {
+    let mut patterns: Vec<String> = vec![];
+    let mut packages: Vec<Package> = vec![];
+    if let Some(json) = read_jsonc(&root.join("package.json"), texts) {
+        let workspaces = match &json["workspaces"] {
+            Value::Object(yarn) => yarn.get("packages").cloned().unwrap_or_default(),
+            workspaces => workspaces.clone(),
+        };
+        for pattern in workspaces.as_array().into_iter().flatten() {
+            patterns.extend(pattern.as_str().map(String::from));
+        }
+        packages.extend(package(root, json));
+    }
+    if packages.is_empty() {
+        let dir = match root.as_os_str().is_empty() {
+            true => std::env::current_dir().unwrap_or_default(),
+            false => root.to_path_buf(),
+        };
+        let dir = std::fs::canonicalize(&dir).unwrap_or(dir);
+        if let Some(name) = dir.file_name() {
+            packages.push(Package {
+                name: name.to_string_lossy().into_owned(),
+                dir: root.to_path_buf(),
+                entries: vec![],
+                manifest: false,
+            });
+        }
+    }
+    if let Ok(yaml) = std::fs::read_to_string(root.join("pnpm-workspace.yaml")) {
+        patterns.extend(pnpm_packages(&yaml));
+        texts.push(yaml);
+    }
+
+    let root_glob = glob::Pattern::escape(&root.display().to_string());
+    for pattern in patterns.iter().filter(|p| !p.starts_with('!')) {
+        let pattern = Path::new(&root_glob).join(pattern).join("package.json");
+        let files = match glob::glob(&pattern.display().to_string()) {
+            Ok(files) => files,
+            Err(_) => continue,
+        };
+        for file in files.filter_map(|f| f.ok()) {
+            let dir = normalize(&parent(&file));
+            if let Some(package) = read_jsonc(&file, texts).and_then(|json| package(&dir, json)) {
+                if !packages
+                    .iter()
+                    .any(|p| p.name == package.name || p.dir == package.dir)
+                {
+                    packages.push(package);
+                }
+            }
+        }
+    }
+    packages
+}
+
+/// The package whose package.json in `dir` is `json`, if it has a name.
+fn package(dir: &Path, json: Value) -> Option<Package> {
+    let name = json["name"].as_str()?.to_string();
+    let exports = match &json["exports"] {
+        Value::Object(exports) => exports.get(".").cloned().unwrap_or_default(),
+        exports => exports.clone(),
+    };
+    let mut entries = vec![];
+    for field in ["source", "types", "typings", "main", "module"] {
+        entries.extend(json[field].as_str().map(String::from));
+    }
+    match exports {
+        Value::String(entry) => entries.push(entry),
+        Value::Object(conditions) => {
+            for condition in ["source", "types", "import", "default"] {
+                let entry = conditions.get(condition).and_then(|c| c.as_str());
+                entries.extend(entry.map(String::from));
+            }
+        }
+        _ => {}
+    }
+    Some(Package {
+        name,
+        dir: dir.to_path_buf(),
+        entries,
+        manifest: true,
+    })
+}
+
+/// The `packages:` list of pnpm-workspace.yaml.
+fn pnpm_packages(yaml: &str) -> Vec<String> {
+    let mut result = vec![];
+    let mut in_packages = false;
+    for line in yaml.lines() {
+        let line = line.split(" #").next().unwrap_or_default().trim_end();
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+        if !line.starts_with([' ', '\t', '-']) {
+            in_packages = line.trim() == "packages:";
+            continue;
+        }
+        if let Some(item) = line.trim().strip_prefix('-').filter(|_| in_packages) {
+            result.push(
+                item.trim()
+                    .trim_matches(|c| c == '"' || c == '\'')
+                    .to_string(),
+            );
+        }
+    }
+    result
+}
+
+/// Separates a callee from the package its import resolved to in the keys
+/// of a file parsed on its own, until `CodeIndex::replace_files` knows what
+/// every package defines and picks the key it links to.
+pub(crate) const PACKAGE_TAG: char = '\0';
+
+/// A binding of a project file.
+#[derive(Debug)]
+struct Import {
+    // the name in the file that defines it; `None` for `import * as ns`.
+    imported: Option<String>,
+    // the workspace package or project root of that file.
+    package: String,
+}
+
+/// The names a file imports.
+#[derive(Debug, Default)]
+pub(crate) struct ImportMap {
+    // imports of project files, by local name.
+    project: BTreeMap<String, Import>,
+    // imports of packages and Node.js modules outside the project.
+    external: BTreeSet<String>,
+    // the file each specifier resolved to, kept in the file's record, as
+    // adding or removing a file can change it.
+    resolved: BTreeMap<String, Option<String>>,
+}
+
+impl ImportMap {
+    pub(crate) fn new(
+        root: Node,
+        filename: &str,
+        content: &str,
+        resolver: Option<&Resolver>,
+    ) -> Self {
+        let mut map = ImportMap::default();
+        for import in imports(root, content) {
+            let resolved = resolver.and_then(|r| r.resolve(filename, &import.specifier));
+            map.resolved
+                .insert(import.specifier.clone(), resolved.clone());
+            let file = match resolved {
+                Some(file) => file,
+                None => {
+                    let relative =
+                        import.specifier.starts_with('.') || import.specifier.starts_with('/');
+                    if !relative {
+                        map.external.insert(import.local);
+                    }
+                    continue;
+                }
+            };
+            // a default export can have any name, or none.
+            if import.imported.as_deref() != Some("default") {
+                let package = resolver.and_then(|r| r.package_of(&file));
+                map.project.insert(
+                    import.local,
+                    Import {
+                        imported: import.imported,
+                        package: package.unwrap_or_default().to_string(),
+                    },
+                );
+            }
+        }
+        map
+    }
+
+    /// Whether `local` was imported from outside the project.
+    pub(crate) fn is_external(&self, local: &str) -> bool {
+        self.external.contains(local)
+    }
+
+    /// The file each import specifier resolved to, `None` if to none.
+    pub(crate) fn into_resolved(self) -> BTreeMap<String, Option<String>> {
+        self.resolved
+    }
+
+    /// `name` as called or referenced, renamed to what the imported file
+    /// defines: `log` for `writeLog` after `import { log as writeLog }`,
+    /// `init` for `core.init` after `import * as core`. Names imported from
+    /// a workspace package are tagged with it, see `PACKAGE_TAG`.
+    pub(crate) fn rename(&self, name: &str) -> String {
+        let (head, rest) = match name.split_once('.') {
+            Some((head, rest)) => (head, Some(rest)),
+            None => (name, None),
+        };
+        let import = match self.project.get(head) {
+            Some(import) => import,
+            None => return name.to_string(),
+        };
+        let renamed = match (&import.imported, rest) {
+            (Some(imported), Some(rest)) => format!("{}.{}", imported, rest),
+            (Some(imported), None) => imported.clone(),
+            (None, Some(rest)) => rest.to_string(),
+            (None, None) => return name.to_string(),
+        };
+        match import.package.is_empty() {
+            true => renamed,
+            false => format!("{}{}{}", renamed, PACKAGE_TAG, import.package),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CodeIndex;
+
+    fn callees(index: &CodeIndex, caller: &str) -> Vec<String> {
+        let id = index.id_gen.get(caller).unwrap();
+        let mut callees: Vec<String> = index.edges[&id]
+            .iter()
+            .map(|c| index.id_gen.name(*c).unwrap().clone())
+            .collect();
+        callees.sort();
+        callees
+    }
+
+    #[test]
+    fn test_resolve_workspace_imports() {
+        let dir = std::env::temp_dir().join(format!("graphgen-resolve-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let write = |name: &str, content: &str| {
+            let file = dir.join(name);
+            std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+            std::fs::write(file, content).unwrap();
+        };
+        write(
+            "tsconfig.base.json",
+            r#"{
+                // shared by every package
+                "compilerOptions": {
+                    "baseUrl": ".",
+                    "paths": { "@app/*": ["packages/app/src/*"], },
+                },
+            }"#,
+        );
+        write(
+            "tsconfig.json",
+            r#"{ "extends": "./tsconfig.base", "references": [{ "path": "packages/app" }] }"#,
+        );
+        write("package.json", r#"{ "private": true }"#);
+        write("pnpm-workspace.yaml", "packages:\n  - 'packages/*'\n");
+        write(
+            "packages/core/package.json",
+            r#"{ "name": "@acme/core", "main": "dist/index.js" }"#,
+        );
+        write(
+            "packages/core/src/index.ts",
+            "export function init() {}\nexport class Logger { static create() {} }",
+        );
+        write(
+            "packages/app/tsconfig.json",
+            r#"{ "extends": "../../tsconfig.base.json" }"#,
+        );
+        write("packages/app/src/logger.ts", "export function log() {}");
+        write(
+            "packages/app/src/main.ts",
+            r#"import { log as writeLog } from "@app/logger";
+import * as core from "@acme/core";
+import { Logger as L } from "@acme/core/src/index.js";
+import * as path from "node:path";
+export function main() { writeLog(); core.init(); L.create(); path.join(); }"#,
+        );
+        let root = dir.display().to_string();
+
+        let resolver = Resolver::new(std::slice::from_ref(&dir), &BTreeMap::new());
+        let main = format!("{}/packages/app/src/main.ts", root);
+        let core = format!("{}/packages/core/src/index.ts", root);
+        assert_eq!(resolver.resolve(&main, "@acme/core"), Some(core.clone()));
+        assert_eq!(resolver.resolve(&main, "./../../core/src"), Some(core));
+        assert_eq!(
+            resolver.resolve(&main, "@app/logger"),
+            Some(format!("{}/packages/app/src/logger.ts", root))
+        );
+        assert_eq!(resolver.resolve(&main, "@app/missing"), None);
+        assert_eq!(resolver.resolve(&main, "node:path"), None);
+
+        let mut index = CodeIndex::new();
+        index.parse_project(&root).unwrap();
+        assert_eq!(
+            callees(&index, "main"),
+            vec!["Logger.create", "init", "log", "path.join"]
+        );
+
+        // without the alias `@app/logger` is no project file, so every file
+        // is parsed again and `writeLog` stays as written.
+        write("tsconfig.base.json", "{}");
+        assert_eq!(index.reindex().changed, 3);
+        assert!(callees(&index, "main").contains(&"writeLog".to_string()));
+        let mut aliases = BTreeMap::new();
+        aliases.insert("@app/*".to_string(), vec!["packages/app/src/*".to_string()]);
+        index.set_path_aliases(aliases);
+        assert_eq!(index.reindex().changed, 3);
+        assert!(callees(&index, "main").contains(&"log".to_string()));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_same_name_in_two_packages() {
+        let dir = std::env::temp_dir().join(format!("graphgen-collide-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let write = |name: &str, content: &str| {
+            let file = dir.join(name);
+            std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+            std::fs::write(file, content).unwrap();
+        };
+        write("package.json", r#"{ "workspaces": ["packages/*"] }"#);
+        write("packages/a/package.json", r#"{ "name": "@x/a" }"#);
+        write("packages/b/package.json", r#"{ "name": "@x/b" }"#);
+        write("packages/c/package.json", r#"{ "name": "@x/c" }"#);
+        write(
+            "packages/a/src/index.ts",
+            "export function init() { localA(); }\nfunction localA() {}",
+        );
+        write(
+            "packages/b/src/index.ts",
+            "export function init() { localB(); }\nfunction localB() {}\nexport function start() { init(); }",
+        );
+        write(
+            "packages/c/src/index.ts",
+            "import { init as initB } from \"@x/b\";\nexport function run() { initB(); }",
+        );
+        let root = dir.display().to_string();
+
+        let mut index = CodeIndex::new();
+        index.parse_project(&root).unwrap();
+        assert_eq!(callees(&index, "init"), vec!["localA"]);
+        assert_eq!(callees(&index, "init (@x/b)"), vec!["localB"]);
+        assert_eq!(callees(&index, "start"), vec!["init (@x/b)"]);
+        assert_eq!(callees(&index, "run"), vec!["init (@x/b)"]);
+        let calls = index.cross_package_calls(None);
+        assert_eq!(calls.len(), 1);
+        assert_eq!(
+            (calls[0].caller.as_str(), calls[0].callee_package.as_str()),
+            ("run", "@x/b")
+        );
+
+        // once @x/a no longer defines it, @x/b's `init` is the plain one.
+        write("packages/a/src/index.ts", "function localA() {}");
+        index.reindex();
+        let mut full = CodeIndex::new();
+        full.parse_project(&root).unwrap();
+        assert_eq!(index.function_list(), full.function_list());
+        assert_eq!(callees(&index, "start"), vec!["init"]);
+        assert_eq!(callees(&index, "run"), vec!["init"]);
+        assert_eq!(index.functions["init"].package, "@x/b");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_config_formats() {
+        assert_eq!(
+            strip_jsonc("{ \"a\": \"//x\", /* b */ \"c\": [1, 2,], // d\n }"),
+            "{ \"a\": \"//x\",  \"c\": [1, 2] \n }"
+        );
+        assert_eq!(
+            pnpm_packages(
+                "packages:\n  - \"apps/*\"\n  - libs/* # shared\n  - '!**/test'\ncatalog:\n  - x\n"
+            ),
+            vec!["apps/*", "libs/*", "!**/test"]
+        );
+        assert_eq!(match_pattern("@app/*", "@app/core/log"), Some("core/log"));
+        assert_eq!(match_pattern("@app/*.js", "@app/a.js"), Some("a"));
+        assert_eq!(match_pattern("config", "config"), Some(""));
+        assert_eq!(match_pattern("@app/*", "@lib/x"), None);
+        assert_eq!(normalize(Path::new("../a/./b/../c")), Path::new("../a/c"));
+    }
+}
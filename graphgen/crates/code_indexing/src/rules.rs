@@ -0,0 +1,224 @@
+use glob::{MatchOptions, Pattern};
+use serde::{Deserialize, Serialize};
+
+use crate::CodeIndex;
+
+pub(crate) const PATH_MATCH: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// A forbidden kind of call. Every pattern that is set must match for a call
+/// to violate the rule, and at least one must be set; file patterns are
+/// relative to the project directory.
+///
+/// ```toml
+/// [[rule]]
+/// name = "ui must not reach into db"
+/// from = "src/ui/**"
+/// to = "src/db/**"
+///
+/// [[rule]]
+/// name = "internals stay private to their file"
+/// to_function = "*Internal*"
+/// allow_same_file = true
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Rule {
+    pub name: String,
+    /// Glob of the caller's file.
+    pub from: Option<String>,
+    /// Glob of the callee's file.
+    pub to: Option<String>,
+    /// Glob of the caller's qualified name.
+    pub from_function: Option<String>,
+    /// Glob of the callee's qualified name.
+    pub to_function: Option<String>,
+    /// Calls within a single file never violate this rule.
+    #[serde(default)]
+    pub allow_same_file: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct RuleSet {
+    #[serde(default, rename = "rule")]
+    pub rules: Vec<Rule>,
+}
+
+impl RuleSet {
+    pub fn from_toml(text: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(text)
+    }
+
+    /// Rejects invalid globs, and rules without any pattern, which every
+    /// call would violate.
+    pub fn validate(&self) -> Result<(), String> {
+        for rule in self.rules.iter() {
+            let patterns = [&rule.from, &rule.to, &rule.from_function, &rule.to_function];
+            if patterns.iter().all(|p| p.is_none()) {
+                return Err(format!(
+                    "rule \"{}\" needs from, to, from_function or to_function",
+                    rule.name
+                ));
+            }
+            for pattern in patterns.into_iter().flatten() {
+                Pattern::new(pattern).map_err(|e| {
+                    format!(
+                        "invalid pattern {} in rule \"{}\": {}",
+                        pattern, rule.name, e
+                    )
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Violation {
+    pub rule: String,
+    pub caller: String,
+    pub callee: String,
+    /// File and line of the call expression.
+    pub file: String,
+    pub line: usize,
+    pub callee_file: String,
+}
+
+struct CompiledRule<'a> {
+    rule: &'a Rule,
+    from: Option<Pattern>,
+    to: Option<Pattern>,
+    from_function: Option<Pattern>,
+    to_function: Option<Pattern>,
+}
+
+fn compile(pattern: &Option<String>) -> Option<Pattern> {
+    pattern.as_deref().and_then(|p| Pattern::new(p).ok())
+}
+
+fn matches(pattern: &Option<Pattern>, value: &str, options: MatchOptions) -> bool {
+    pattern
+        .as_ref()
+        .is_none_or(|p| p.matches_with(value, options))
+}
+
+impl CodeIndex {
+    /// Every call between project functions that breaks one of `rules`,
+    /// in caller order. Fails as `RuleSet::validate` does.
+    pub fn check_rules(&self, rules: &RuleSet) -> Result<Vec<Violation>, String> {
+        rules.validate()?;
+        let compiled: Vec<CompiledRule> = rules
+            .rules
+            .iter()
+            .map(|rule| CompiledRule {
+                rule,
+                from: compile(&rule.from),
+                to: compile(&rule.to),
+                from_function: compile(&rule.from_function),
+                to_function: compile(&rule.to_function),
+            })
+            .collect();
+
+        let mut violations = vec![];
+        for (from, sites) in self.call_sites.iter() {
+            let (caller_name, caller) = match self.id_gen.name(*from) {
+                Some(name) => match self.functions.get(name) {
+                    Some(func) => (name, func),
+                    None => continue,
+                },
+                None => continue,
+            };
+            let caller_file = self.relative_path(&caller.file);
+            for site in sites {
+                let (callee_name, callee) = match self.id_gen.name(site.callee) {
+                    Some(name) => match self.functions.get(name) {
+                        Some(func) => (name, func),
+                        None => continue,
+                    },
+                    None => continue,
+                };
+                let callee_file = self.relative_path(&callee.file);
+                for c in compiled.iter() {
+                    if c.rule.allow_same_file && caller.file == callee.file {
+                        continue;
+                    }
+                    if matches(&c.from, caller_file, PATH_MATCH)
+                        && matches(&c.to, callee_file, PATH_MATCH)
+                        && matches(&c.from_function, caller_name, MatchOptions::new())
+                        && matches(&c.to_function, callee_name, MatchOptions::new())
+                    {
+                        violations.push(Violation {
+                            rule: c.rule.name.clone(),
+                            caller: caller_name.clone(),
+                            callee: callee_name.clone(),
+                            file: caller_file.to_string(),
+                            line: site.span.line,
+                            callee_file: callee_file.to_string(),
+                        });
+                    }
+                }
+            }
+        }
+        violations.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+        Ok(violations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RULES: &str = r#"
+[[rule]]
+name = "ui must not reach into db"
+from = "src/ui/**"
+to = "src/db/**"
+
+[[rule]]
+name = "internals stay private to their file"
+to_function = "*Internal*"
+allow_same_file = true
+"#;
+
+    #[test]
+    fn test_check_rules() {
+        let mut index = CodeIndex::new();
+        index.root = "/work".to_string();
+        index.parse_source(
+            "/work/src/ui/view.ts",
+            "function render() {\n  query();\n  format();\n  cacheInternal();\n}",
+        );
+        index.parse_source(
+            "/work/src/ui/format.ts",
+            "function format() {}\nfunction cacheInternal() {}\nfunction warm() { cacheInternal(); }",
+        );
+        index.parse_source("/work/src/db/query.ts", "function query() {}");
+
+        let rules = RuleSet::from_toml(RULES).unwrap();
+        let violations = index.check_rules(&rules).unwrap();
+        let found: Vec<_> = violations
+            .iter()
+            .map(|v| (v.rule.as_str(), v.callee.as_str(), v.file.as_str(), v.line))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("ui must not reach into db", "query", "src/ui/view.ts", 2),
+                (
+                    "internals stay private to their file",
+                    "cacheInternal",
+                    "src/ui/view.ts",
+                    4
+                ),
+            ]
+        );
+
+        let unbounded = RuleSet::from_toml("[[rule]]\nname = \"everything\"").unwrap();
+        assert!(unbounded.validate().unwrap_err().contains("everything"));
+        assert!(index.check_rules(&unbounded).is_err());
+        let invalid = RuleSet::from_toml("[[rule]]\nname = \"x\"\nfrom = \"[\"").unwrap();
+        assert!(index.check_rules(&invalid).is_err());
+    }
+}
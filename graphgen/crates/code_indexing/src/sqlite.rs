@@ -0,0 +1,481 @@
+//! SQLite storage for the index. Unlike the bincode file, the database can
+//! be updated one file at a time and queried by other processes with plain
+//! SQL while graphgen keeps it open.
+
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Transaction};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Read;
+
+use crate::{CallSite, Class, CodeIndex, Function, IDGenerator, Span};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS meta (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS files (
+    id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL UNIQUE
+);
+-- every name the index refers to, including callees outside the project.
+CREATE TABLE IF NOT EXISTS symbols (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE
+);
+CREATE TABLE IF NOT EXISTS functions (
+    symbol INTEGER PRIMARY KEY REFERENCES symbols(id),
+    file INTEGER NOT NULL REFERENCES files(id),
+    name TEXT NOT NULL,
+    class TEXT NOT NULL,
+    line INTEGER NOT NULL,
+    end_line INTEGER NOT NULL,
+    name_line INTEGER NOT NULL,
+    name_column INTEGER NOT NULL,
+    name_end_column INTEGER NOT NULL,
+    exported INTEGER NOT NULL,
+    test INTEGER NOT NULL,
+    body TEXT NOT NULL,
+    package TEXT NOT NULL DEFAULT ''
+);
+CREATE INDEX IF NOT EXISTS functions_file ON functions(file);
+CREATE TABLE IF NOT EXISTS classes (
+    name TEXT PRIMARY KEY,
+    file INTEGER NOT NULL REFERENCES files(id),
+    extends TEXT,
+    declaration TEXT NOT NULL,
+    package TEXT NOT NULL DEFAULT ''
+);
+CREATE INDEX IF NOT EXISTS classes_file ON classes(file);
+-- calls, with the position of the called name, and references, without.
+CREATE TABLE IF NOT EXISTS edges (
+    caller INTEGER NOT NULL REFERENCES symbols(id),
+    callee INTEGER NOT NULL REFERENCES symbols(id),
+    kind TEXT NOT NULL CHECK (kind IN ('call', 'reference')),
+    file INTEGER NOT NULL REFERENCES files(id),
+    seq INTEGER NOT NULL,
+    line INTEGER,
+    column INTEGER,
+    end_column INTEGER,
+    callee_kind TEXT NOT NULL DEFAULT 'dynamic'
+);
+CREATE INDEX IF NOT EXISTS edges_caller ON edges(caller);
+CREATE INDEX IF NOT EXISTS edges_callee ON edges(callee);
+CREATE INDEX IF NOT EXISTS edges_file ON edges(file);
+CREATE TABLE IF NOT EXISTS routes (
+    symbol INTEGER PRIMARY KEY REFERENCES symbols(id)
+);
+";
+
+/// True if `path` starts with the SQLite file header.
+pub fn is_sqlite(path: &str) -> bool {
+    let mut header = [0u8; 16];
+    std::fs::File::open(path)
+        .and_then(|mut f| f.read_exact(&mut header))
+        .is_ok_and(|_| &header == b"SQLite format 3\0")
+}
+
+pub struct SqliteStore {
+    conn: Connection,
+}
+
+impl SqliteStore {
+    /// Opens or creates a database for writing.
+    pub fn open(path: &str) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        // WAL lets other processes read while the index is being updated.
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.execute_batch(SCHEMA)?;
+        let store = SqliteStore { conn };
+        // databases written before functions and classes had a package.
+        for table in ["functions", "classes"] {
+            if !store.has_column(table, "package")? {
+                store.conn.execute_batch(&format!(
+                    "ALTER TABLE {} ADD COLUMN package TEXT NOT NULL DEFAULT ''",
+                    table
+                ))?;
+            }
+        }
+        // and before calls had a kind.
+        if !store.has_column("edges", "callee_kind")? {
+            store.conn.execute_batch(
+                "ALTER TABLE edges ADD COLUMN callee_kind TEXT NOT NULL DEFAULT 'dynamic'",
+            )?;
+        }
+        Ok(store)
+    }
+
+    pub fn open_read_only(path: &str) -> rusqlite::Result<Self> {
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        Ok(SqliteStore { conn })
+    }
+
+    /// Replaces the whole database with `index`.
+    pub fn save(&mut self, index: &CodeIndex) -> rusqlite::Result<()> {
+        let tx = self.conn.transaction()?;
+        tx.execute_batch(
+            "DELETE FROM edges; DELETE FROM functions; DELETE FROM classes;
+             DELETE FROM routes; DELETE FROM files; DELETE FROM symbols;",
+        )?;
+        let files: BTreeSet<&str> = index
+            .functions
+            .values()
+            .map(|f| f.file.as_str())
+            .chain(index.classes.values().map(|c| c.file.as_str()))
+            .collect();
+        for file in files {
+            insert_file(&tx, index, file)?;
+        }
+        finish(&tx, index)?;
+        tx.commit()
+    }
+
+    /// Rewrites the rows of `files` from `index`, which must already reflect
+    /// their current content. Files missing from `index` are removed.
+    pub fn update_files(&mut self, index: &CodeIndex, files: &[&str]) -> rusqlite::Result<()> {
+        let tx = self.conn.transaction()?;
+        // all old rows go first, as functions and classes may have moved
+        // between the files.
+        for file in files {
+            let id: Option<i64> = tx
+                .query_row("SELECT id FROM files WHERE path = ?1", [file], |r| r.get(0))
+                .optional()?;
+            if let Some(id) = id {
+                tx.execute("DELETE FROM edges WHERE file = ?1", [id])?;
+                tx.execute("DELETE FROM functions WHERE file = ?1", [id])?;
+                tx.execute("DELETE FROM classes WHERE file = ?1", [id])?;
+                tx.execute("DELETE FROM files WHERE id = ?1", [id])?;
+            }
+        }
+        for file in files {
+            let indexed = index.functions.values().any(|f| f.file == *file)
+                || index.classes.values().any(|c| c.file == *file);
+            if indexed {
+                insert_file(&tx, index, file)?;
+            }
+        }
+        tx.execute("DELETE FROM routes", [])?;
+        finish(&tx, index)?;
+        tx.commit()
+    }
+
+    pub fn load(&self) -> rusqlite::Result<CodeIndex> {
+        let mut index = CodeIndex::new();
+        index.root = self.meta("root")?.unwrap_or_default();
+
+        let mut id_gen = IDGenerator::new();
+        let mut stmt = self.conn.prepare("SELECT id, name FROM symbols")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let id: i64 = row.get(0)?;
+            let name: String = row.get(1)?;
+            id_gen.id_map.insert(name.clone(), id as u64);
+            id_gen.name_map.insert(id as u64, name);
+            id_gen.next_id = id_gen.next_id.max(id as u64 + 1);
+        }
+        index.id_gen = id_gen;
+
+        // read-only databases from before packages cannot be upgraded.
+        let package = match self.has_column("functions", "package")? {
+            true => "package",
+            false => "''",
+        };
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT s.name, fn.name, fn.class, f.path, fn.line, fn.end_line, fn.name_line,
+                    fn.name_column, fn.name_end_column, fn.exported, fn.test, fn.body, fn.{0}
+             FROM functions fn JOIN symbols s ON s.id = fn.symbol JOIN files f ON f.id = fn.file",
+            package
+        ))?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let function = Function {
+                name: row.get(1)?,
+                pkg: row.get(2)?,
+                file: row.get(3)?,
+                line: row.get(4)?,
+                end_line: row.get(5)?,
+                name_span: Span {
+                    line: row.get(6)?,
+                    column: row.get(7)?,
+                    end_column: row.get(8)?,
+                },
+                exported: row.get(9)?,
+                test: row.get(10)?,
+                body: row.get(11)?,
+                package: row.get(12)?,
+            };
+            index.functions.insert(row.get(0)?, function);
+        }
+
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT c.name, f.path, c.extends, c.declaration, c.{0}
+             FROM classes c JOIN files f ON f.id = c.file",
+            package
+        ))?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let class = Class {
+                name: row.get(0)?,
+                file: row.get(1)?,
+                extends: row.get(2)?,
+                declaration: row.get(3)?,
+                package: row.get(4)?,
+            };
+            index.classes.insert(class.name.clone(), class);
+        }
+
+        let callee_kind = match self.has_column("edges", "callee_kind")? {
+            true => "callee_kind",
+            false => "'dynamic'",
+        };
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT caller, callee, kind, line, column, end_column, {}
+             FROM edges ORDER BY seq",
+            callee_kind
+        ))?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let caller = row.get::<_, i64>(0)? as u64;
+            let callee = row.get::<_, i64>(1)? as u64;
+            if row.get::<_, String>(2)? == "reference" {
+                index.refs.entry(caller).or_default().push(callee);
+                continue;
+            }
+            index.edges.entry(caller).or_default().push(callee);
+            index.call_sites.entry(caller).or_default().push(CallSite {
+                callee,
+                span: Span {
+                    line: row.get(3)?,
+                    column: row.get(4)?,
+                    end_column: row.get(5)?,
+                },
+                kind: row.get::<_, String>(6)?.parse().unwrap_or_default(),
+            });
+        }
+
+        let mut stmt = self.conn.prepare("SELECT symbol FROM routes")?;
+        let routes = stmt.query_map([], |r| r.get::<_, i64>(0))?;
+        for route in routes {
+            index.routes.insert(route? as u64);
+        }
+        Ok(index)
+    }
+
+    /// Names called by `name`, without loading the index.
+    pub fn callees(&self, name: &str) -> rusqlite::Result<Vec<String>> {
+        self.names(
+            "SELECT DISTINCT callee.name FROM edges e
+             JOIN symbols caller ON caller.id = e.caller
+             JOIN symbols callee ON callee.id = e.callee
+             WHERE caller.name = ?1 AND e.kind = 'call' ORDER BY callee.name",
+            name,
+        )
+    }
+
+    /// Functions calling `name`, without loading the index.
+    pub fn callers(&self, name: &str) -> rusqlite::Result<Vec<String>> {
+        self.names(
+            "SELECT DISTINCT caller.name FROM edges e
+             JOIN symbols caller ON caller.id = e.caller
+             JOIN symbols callee ON callee.id = e.callee
+             WHERE callee.name = ?1 AND e.kind = 'call' ORDER BY caller.name",
+            name,
+        )
+    }
+
+    fn names(&self, sql: &str, name: &str) -> rusqlite::Result<Vec<String>> {
+        let mut stmt = self.conn.prepare(sql)?;
+        let names = stmt.query_map([name], |r| r.get(0))?;
+        names.collect()
+    }
+
+    fn has_column(&self, table: &str, column: &str) -> rusqlite::Result<bool> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!("SELECT name FROM pragma_table_info('{}')", table))?;
+        let names = stmt.query_map([], |r| r.get::<_, String>(0))?;
+        for name in names {
+            if name? == column {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn meta(&self, key: &str) -> rusqlite::Result<Option<String>> {
+        self.conn
+            .query_row("SELECT value FROM meta WHERE key = ?1", [key], |r| r.get(0))
+            .optional()
+    }
+}
+
+fn symbol(tx: &Transaction, name: &str) -> rusqlite::Result<i64> {
+    tx.prepare_cached("INSERT OR IGNORE INTO symbols (name) VALUES (?1)")?
+        .execute([name])?;
+    tx.prepare_cached("SELECT id FROM symbols WHERE name = ?1")?
+        .query_row([name], |r| r.get(0))
+}
+
+/// Inserts the functions and classes defined in `file`, and their edges.
+fn insert_file(tx: &Transaction, index: &CodeIndex, file: &str) -> rusqlite::Result<()> {
+    tx.execute("INSERT INTO files (path) VALUES (?1)", [file])?;
+    let file_id = tx.last_insert_rowid();
+
+    let mut seq = 0;
+    for (key, func) in index.functions.iter().filter(|(_, f)| f.file == file) {
+        let caller = symbol(tx, key)?;
+        tx.prepare_cached(
+            "INSERT INTO functions (symbol, file, name, class, line, end_line, name_line,
+                 name_column, name_end_column, exported, test, body, package)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+        )?
+        .execute(params![
+            caller,
+            file_id,
+            func.name,
+            func.pkg,
+            func.line,
+            func.end_line,
+            func.name_span.line,
+            func.name_span.column,
+            func.name_span.end_column,
+            func.exported,
+            func.test,
+            func.body,
+            func.package,
+        ])?;
+
+        let id = match index.id_gen.get(key) {
+            Some(id) => id,
+            None => continue,
+        };
+        for site in index.call_sites.get(&id).into_iter().flatten() {
+            let callee = symbol(tx, index.id_gen.name(site.callee).unwrap())?;
+            tx.prepare_cached(
+                "INSERT INTO edges (caller, callee, kind, file, seq, line, column, end_column,
+                                    callee_kind)
+                 VALUES (?1, ?2, 'call', ?3, ?4, ?5, ?6, ?7, ?8)",
+            )?
+            .execute(params![
+                caller,
+                callee,
+                file_id,
+                seq,
+                site.span.line,
+                site.span.column,
+                site.span.end_column,
+                site.kind.name(),
+            ])?;
+            seq += 1;
+        }
+        for target in index.refs.get(&id).into_iter().flatten() {
+            let callee = symbol(tx, index.id_gen.name(*target).unwrap())?;
+            tx.prepare_cached(
+                "INSERT INTO edges (caller, callee, kind, file, seq)
+                 VALUES (?1, ?2, 'reference', ?3, ?4)",
+            )?
+            .execute(params![caller, callee, file_id, seq])?;
+            seq += 1;
+        }
+    }
+
+    for class in index.classes.values().filter(|c| c.file == file) {
+        tx.prepare_cached(
+            "INSERT INTO classes (name, file, extends, declaration, package)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?
+        .execute(params![
+            class.name,
+            file_id,
+            class.extends,
+            class.declaration,
+            class.package
+        ])?;
+    }
+    Ok(())
+}
+
+/// Writes the project wide rows: route handlers and the project root.
+fn finish(tx: &Transaction, index: &CodeIndex) -> rusqlite::Result<()> {
+    let routes: BTreeMap<u64, &String> = index
+        .routes
+        .iter()
+        .filter_map(|id| index.id_gen.name(*id).map(|name| (*id, name)))
+        .collect();
+    for name in routes.values() {
+        let id = symbol(tx, name)?;
+        tx.execute("INSERT OR IGNORE INTO routes (symbol) VALUES (?1)", [id])?;
+    }
+    tx.execute(
+        "INSERT OR REPLACE INTO meta (key, value) VALUES ('root', ?1)",
+        [&index.root],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_db(name: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("graphgen-{}-{}.db", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path.display().to_string()
+    }
+
+    #[test]
+    fn test_sqlite_round_trip() {
+        let mut index = CodeIndex::new();
+        index.parse_source(
+            "a.ts",
+            "class Api { list() { return fetchAll(); } }\nfunction fetchAll() { log(); }\nfunction serve(app) { app.get('/', handler); }\nfunction handler() {}",
+        );
+        let path = temp_db("round-trip");
+        SqliteStore::open(&path).unwrap().save(&index).unwrap();
+        assert!(is_sqlite(&path));
+
+        let store = SqliteStore::open_read_only(&path).unwrap();
+        assert_eq!(store.callers("fetchAll").unwrap(), vec!["Api.list"]);
+        assert_eq!(store.callees("fetchAll").unwrap(), vec!["log"]);
+        let loaded = store.load().unwrap();
+        assert_eq!(loaded.function_list(), index.function_list());
+        assert_eq!(loaded.documents(), index.documents());
+        let report = loaded
+            .dead_code(&crate::deadcode::EntryPoints::default())
+            .unwrap();
+        assert_eq!(report.reachable, 1);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_sqlite_update_files() {
+        let mut index = CodeIndex::new();
+        index.parse_source("a.ts", "function a() { b(); }");
+        index.parse_source("b.ts", "function b() {}");
+        let path = temp_db("update");
+        let mut store = SqliteStore::open(&path).unwrap();
+        store.save(&index).unwrap();
+
+        let mut index = CodeIndex::new();
+        index.parse_source("a.ts", "function a() { c(); }");
+        index.parse_source("b.ts", "function b() {}");
+        store.update_files(&index, &["a.ts", "gone.ts"]).unwrap();
+        assert_eq!(store.callees("a").unwrap(), vec!["c"]);
+        assert!(store.callers("b").unwrap().is_empty());
+        assert_eq!(store.load().unwrap().function_list(), vec!["a", "b"]);
+
+        // `b` and class `B` move from b.ts to a.ts.
+        let mut index = CodeIndex::new();
+        index.parse_source("a.ts", "function a() {}");
+        index.parse_source("b.ts", "function b() {}\nclass B {}");
+        store.save(&index).unwrap();
+        let mut index = CodeIndex::new();
+        index.parse_source("a.ts", "function a() {}\nfunction b() {}\nclass B {}");
+        index.parse_source("b.ts", "function d() {}");
+        store.update_files(&index, &["a.ts", "b.ts"]).unwrap();
+        let loaded = store.load().unwrap();
+        assert_eq!(loaded.function_list(), vec!["a", "b", "d"]);
+        assert_eq!(loaded.classes["B"].file, "a.ts");
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,171 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+use crate::CodeIndex;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TestCoverage {
+    pub test: String,
+    pub file: String,
+    pub line: usize,
+    /// Production functions the test reaches through calls and references.
+    pub reaches: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SelectedTest {
+    pub test: String,
+    pub file: String,
+    pub line: usize,
+    /// Changed functions the test reaches, or the test itself if it changed.
+    pub because: Vec<String>,
+}
+
+impl CodeIndex {
+    pub fn test_coverage(&self) -> Vec<TestCoverage> {
+        let mut result = vec![];
+        for (key, func) in self.functions.iter().filter(|(_, f)| f.test) {
+            let root = match self.id_gen.get(key) {
+                Some(id) => id,
+                None => continue,
+            };
+            let reaches: BTreeSet<String> = self
+                .reachable_from(&[root])
+                .into_iter()
+                .filter_map(|id| self.function_by_id(id))
+                .filter(|f| !f.test)
+                .map(|f| f.str())
+                .collect();
+            result.push(TestCoverage {
+                test: key.clone(),
+                file: func.file.clone(),
+                line: func.line,
+                reaches: reaches.into_iter().collect(),
+            });
+        }
+        result
+    }
+
+    /// The tests that reach at least one of `changed`, which is the smallest
+    /// set the static graph can justify running.
+    pub fn select_tests(&self, changed: &BTreeSet<String>) -> Vec<SelectedTest> {
+        let mut result = vec![];
+        for coverage in self.test_coverage() {
+            let mut because: Vec<String> = coverage
+                .reaches
+                .into_iter()
+                .filter(|f| changed.contains(f))
+                .collect();
+            if changed.contains(&coverage.test) {
+                because.insert(0, coverage.test.clone());
+            }
+            if !because.is_empty() {
+                result.push(SelectedTest {
+                    test: coverage.test,
+                    file: coverage.file,
+                    line: coverage.line,
+                    because,
+                });
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"
+import { add, sub, reset } from "./math";
+
+describe("math", () => {
+    beforeEach(() => { reset(); });
+
+    it("adds", () => {
+        expect(add(1, 2)).toBe(3);
+    });
+
+    describe("sub", () => {
+        test.only(`subtracts`, function () {
+            sub(2, 1);
+        });
+    });
+});
+
+function helper() { sub(1, 1); }
+"#;
+
+    const LIB: &str = r#"
+export function add(a, b) { return a + b; }
+export function sub(a, b) { return add(a, -b); }
+export function reset() {}
+export function unrelated() {}
+"#;
+
+    fn index() -> CodeIndex {
+        let mut index = CodeIndex::new();
+        index.parse_source("src/math.ts", LIB);
+        index.parse_source("src/math.test.ts", SOURCE);
+        index
+    }
+
+    #[test]
+    fn test_coverage() {
+        let coverage = index().test_coverage();
+        let tests: Vec<_> = coverage.iter().map(|c| c.test.as_str()).collect();
+        assert_eq!(
+            tests,
+            vec![
+                "helper",
+                "src/math.test.ts > math > adds",
+                "src/math.test.ts > math > sub > subtracts",
+            ]
+        );
+        assert_eq!(coverage[1].reaches, vec!["add", "reset"]);
+        assert_eq!(coverage[2].reaches, vec!["add", "reset", "sub"]);
+    }
+
+    #[test]
+    fn test_select_tests() {
+        let index = index();
+        let changed = BTreeSet::from(["sub".to_string()]);
+        let selected: Vec<_> = index
+            .select_tests(&changed)
+            .into_iter()
+            .map(|t| t.test)
+            .collect();
+        assert_eq!(
+            selected,
+            vec!["helper", "src/math.test.ts > math > sub > subtracts"]
+        );
+        let changed = BTreeSet::from(["unrelated".to_string()]);
+        assert!(index.select_tests(&changed).is_empty());
+    }
+
+    #[test]
+    fn test_detection() {
+        let mut index = index();
+        // production functions named like tests are not tests.
+        index.parse_source(
+            "src/db.ts",
+            "export function testConnection() { add(1, 1); }",
+        );
+        index.parse_source("src/other.spec.ts", SOURCE);
+        let changed = BTreeSet::from(["testConnection".to_string()]);
+        assert!(index.select_tests(&changed).is_empty());
+        let tests: Vec<_> = index
+            .test_coverage()
+            .into_iter()
+            .map(|c| c.test)
+            .filter(|t| t.ends_with("> adds"))
+            .collect();
+        assert_eq!(
+            tests,
+            vec![
+                "src/math.test.ts > math > adds",
+                "src/other.spec.ts > math > adds"
+            ]
+        );
+    }
+}
@@ -0,0 +1,1100 @@
+use std::collections::BTreeSet;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use code_indexing::callees::CallKind;
+use code_indexing::config::{Config, CONFIG_FILE};
+use code_indexing::deadcode::EntryPoints;
+use code_indexing::export::dot::{to_dot, Cluster};
+use code_indexing::export::folded::{FoldedWeight, MAX_STACKS};
+use code_indexing::export::gexf::to_gexf;
+use code_indexing::export::graphml::to_graphml;
+use code_indexing::export::jgf::to_jgf;
+use code_indexing::export::lsif::to_lsif;
+use code_indexing::export::mermaid::to_mermaid;
+use code_indexing::export::plantuml::to_plantuml;
+use code_indexing::export::scip::to_scip;
+use code_indexing::export::svg::to_svg;
+use code_indexing::export::{Selection, Subgraph};
+use code_indexing::filter::{summarize, DEFAULT_MAX_FILE_SIZE};
+use code_indexing::impact::{git_diff, parse_unified_diff};
+use code_indexing::metrics::{Metric, Metrics};
+use code_indexing::rules::RuleSet;
+use code_indexing::sqlite::SqliteStore;
+use code_indexing::CodeIndex;
+
+pub fn commands() -> Vec<Command> {
+    vec![
+        Command::new("deadcode")
+            .about("List functions unreachable from the configured entry points")
+            .arg(
+                Arg::new("entry")
+                    .long("entry")
+                    .action(ArgAction::Append)
+                    .help("Glob of qualified function names treated as entry points"),
+            )
+            .arg(
+                Arg::new("no-exported")
+                    .long("no-exported")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("no-main")
+                    .long("no-main")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("no-routes")
+                    .long("no-routes")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("no-tests")
+                    .long("no-tests")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(Arg::new("json").long("json").action(ArgAction::SetTrue)),
+        Command::new("metrics")
+            .about("Print fan-in, fan-out, reach, betweenness and PageRank per function")
+            .arg(
+                Arg::new("sort")
+                    .long("sort")
+                    .default_value("pagerank")
+                    .help("fan_in, fan_out, calls, reach, betweenness or pagerank"),
+            )
+            .arg(
+                Arg::new("limit")
+                    .long("limit")
+                    .value_parser(clap::value_parser!(usize))
+                    .default_value("30"),
+            )
+            .arg(Arg::new("json").long("json").action(ArgAction::SetTrue)),
+        Command::new("impact")
+            .about("List functions affected by a diff, following callers up to a depth")
+            .args(diff_args())
+            .arg(
+                Arg::new("depth")
+                    .long("depth")
+                    .value_parser(clap::value_parser!(usize))
+                    .default_value("5"),
+            )
+            .arg(
+                Arg::new("format")
+                    .long("format")
+                    .value_parser(["text", "markdown", "json"])
+                    .default_value("text"),
+            ),
+        Command::new("tests")
+            .about("Select the tests reaching changed functions, or print test coverage")
+            .arg(
+                Arg::new("changed")
+                    .long("changed")
+                    .action(ArgAction::Append)
+                    .help("Qualified name of a changed function"),
+            )
+            .args(diff_args())
+            .arg(
+                Arg::new("coverage")
+                    .long("coverage")
+                    .action(ArgAction::SetTrue)
+                    .help("Print the production functions each test reaches"),
+            )
+            .arg(Arg::new("json").long("json").action(ArgAction::SetTrue)),
+        Command::new("check")
+            .about("Check architecture rules and exit non-zero on violations")
+            .arg(Arg::new("rules").long("rules").help(
+                "Rules file, defaults to the rules in graphgen.toml, \
+                         then graphgen-rules.toml in --project-dir",
+            ))
+            .arg(Arg::new("json").long("json").action(ArgAction::SetTrue)),
+        Command::new("dot")
+            .about("Export a call tree or the whole graph in Graphviz DOT")
+            .args(selection_args())
+            .arg(
+                Arg::new("cluster")
+                    .long("cluster")
+                    .value_parser(["none", "class", "file", "package"])
+                    .default_value("class"),
+            )
+            .arg(
+                Arg::new("metrics")
+                    .long("metrics")
+                    .action(ArgAction::SetTrue)
+                    .help("Attach fan-in, fan-out, reach, betweenness and PageRank to nodes"),
+            )
+            .arg(output_arg()),
+        Command::new("mermaid")
+            .about("Export a call tree or path as a Mermaid flowchart")
+            .args(selection_args())
+            .arg(max_nodes_arg())
+            .arg(output_arg()),
+        Command::new("export")
+            .about("Export the graph as GraphML, GEXF or JSON Graph Format")
+            .arg(
+                Arg::new("format")
+                    .long("format")
+                    .value_parser(["graphml", "gexf", "jgf"])
+                    .required(true),
+            )
+            .args(selection_args())
+            .arg(
+                Arg::new("metrics")
+                    .long("metrics")
+                    .action(ArgAction::SetTrue)
+                    .help("Attach fan-in, fan-out, reach, betweenness and PageRank to nodes"),
+            )
+            .arg(output_arg()),
+        Command::new("plantuml")
+            .about("Export a call tree or path as a PlantUML diagram")
+            .args(selection_args())
+            .arg(max_nodes_arg())
+            .arg(output_arg()),
+        Command::new("folded")
+            .about("Print static call paths from a function as folded stacks for flame graphs")
+            .arg(Arg::new("function").long("function").required(true))
+            .arg(
+                Arg::new("depth")
+                    .long("depth")
+                    .value_parser(clap::value_parser!(i32))
+                    .default_value("6"),
+            )
+            .arg(
+                Arg::new("weight")
+                    .long("weight")
+                    .default_value("paths")
+                    .help("paths, calls, or a metric such as fan_in or pagerank"),
+            )
+            .arg(hide_arg())
+            .arg(output_arg()),
+        Command::new("render")
+            .about("Draw a call tree or path as an SVG or PNG image")
+            .args(selection_args())
+            .arg(max_nodes_arg())
+            .arg(
+                Arg::new("format")
+                    .long("format")
+                    .value_parser(["svg", "png"])
+                    .help("Defaults to the output file extension, or svg"),
+            )
+            .arg(output_arg()),
+        Command::new("codeintel")
+            .about("Write a SCIP index or LSIF dump with definitions, references and hovers")
+            .arg(
+                Arg::new("format")
+                    .long("format")
+                    .value_parser(["scip", "lsif"])
+                    .default_value("scip"),
+            )
+            .arg(
+                Arg::new("output")
+                    .long("output")
+                    .short('o')
+                    .help("Output file, defaults to index.scip or dump.lsif"),
+            ),
+        Command::new("save")
+            .about("Save the index for --index, as bincode or as a memory-mappable file")
+            .arg(
+                Arg::new("out")
+                    .long("out")
+                    .help("Index file, defaults to index in graphgen.toml"),
+            )
+            .arg(
+                Arg::new("format")
+                    .long("format")
+                    .value_parser(["bincode", "mmap"])
+                    .default_value("bincode"),
+            ),
+        Command::new("sqlite")
+            .about("Store the index in a SQLite database, or update some of its files")
+            .arg(Arg::new("db").long("db").required(true))
+            .arg(Arg::new("update").long("update").num_args(1..).help(
+                "Only rewrite these files, given as <project-dir>/path like the index stores them",
+            )),
+        Command::new("neo4j")
+            .about("Write neo4j-admin import CSV files and an equivalent Cypher script")
+            .arg(
+                Arg::new("out-dir")
+                    .long("out-dir")
+                    .required(true)
+                    .help("Directory receiving the CSV files and import.cypher"),
+            ),
+        Command::new("config")
+            .about("Show graphgen.toml merged with the command line")
+            .arg(
+                Arg::new("print")
+                    .long("print")
+                    .action(ArgAction::SetTrue)
+                    .help("Print the effective configuration as TOML"),
+            ),
+        Command::new("files")
+            .about("List the indexed files, or with --skipped what was left out and why")
+            .arg(
+                Arg::new("skipped")
+                    .long("skipped")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(Arg::new("json").long("json").action(ArgAction::SetTrue)),
+        Command::new("diagnostics")
+            .about("Report files that failed to read or parse, and unresolved calls")
+            .arg(
+                Arg::new("unresolved")
+                    .long("unresolved")
+                    .action(ArgAction::SetTrue)
+                    .help("Also list files whose functions make dynamic calls"),
+            )
+            .arg(Arg::new("json").long("json").action(ArgAction::SetTrue)),
+        Command::new("packages")
+            .about("List workspace packages and roots, or with --calls the calls between them")
+            .arg(Arg::new("calls").long("calls").action(ArgAction::SetTrue))
+            .arg(
+                Arg::new("package")
+                    .long("package")
+                    .help("Only calls into or out of this package"),
+            )
+            .arg(Arg::new("json").long("json").action(ArgAction::SetTrue)),
+        Command::new("callees")
+            .about("Count calls by kind and list the most called callees")
+            .arg(
+                Arg::new("kind")
+                    .long("kind")
+                    .value_parser(CallKind::parse_list)
+                    .help("Only list callees of these kinds, e.g. dynamic"),
+            )
+            .arg(
+                Arg::new("limit")
+                    .long("limit")
+                    .value_parser(clap::value_parser!(usize))
+                    .default_value("20"),
+            )
+            .arg(Arg::new("json").long("json").action(ArgAction::SetTrue)),
+    ]
+}
+
+/// Global options choosing which project files are indexed.
+pub fn filter_args() -> [Arg; 5] {
+    [
+        Arg::new("include")
+            .long("include")
+            .global(true)
+            .action(ArgAction::Append)
+            .help("Only index files matching this glob, relative to --project-dir"),
+        Arg::new("exclude")
+            .long("exclude")
+            .global(true)
+            .action(ArgAction::Append)
+            .help("Do not index files or directories matching this glob"),
+        Arg::new("max-file-size")
+            .long("max-file-size")
+            .global(true)
+            .value_parser(clap::value_parser!(u64))
+            .help(format!(
+                "Skip files larger than this many bytes, 0 for no limit [default: {}]",
+                DEFAULT_MAX_FILE_SIZE
+            )),
+        Arg::new("no-gitignore")
+            .long("no-gitignore")
+            .global(true)
+            .action(ArgAction::SetTrue)
+            .help("Index files matched by .gitignore and .ignore files too"),
+        Arg::new("include-generated")
+            .long("include-generated")
+            .global(true)
+            .action(ArgAction::SetTrue)
+            .help("Index *.d.ts files and files with a generated header too"),
+    ]
+}
+
+/// graphgen.toml, from `--config` or the project directory, with the
+/// global flags applied over it.
+pub fn config(args: &ArgMatches) -> Result<Config, String> {
+    let file = match args.get_one::<String>("config") {
+        Some(file) => PathBuf::from(file),
+        None => {
+            let dir = args
+                .get_one::<String>("project-dir")
+                .map_or(".", |d| d.as_str());
+            Path::new(dir).join(CONFIG_FILE)
+        }
+    };
+    if args.contains_id("config") && !file.is_file() {
+        return Err(format!("{} not found", file.display()));
+    }
+    let mut config = Config::load(&file)?;
+    if let Some(addr) = args.get_one::<String>("listen-addr") {
+        config.listen_addr = Some(addr.clone());
+    }
+    if let Some(roots) = args.get_many::<String>("root") {
+        config.roots.extend(roots.cloned());
+    }
+    if let Some(include) = args.get_many::<String>("include") {
+        config.files.include = include.cloned().collect();
+    }
+    if let Some(exclude) = args.get_many::<String>("exclude") {
+        config.files.exclude = exclude.cloned().collect();
+    }
+    if let Some(max) = args.get_one::<u64>("max-file-size") {
+        config.files.max_file_size = *max;
+    }
+    if args.get_flag("no-gitignore") {
+        config.files.gitignore = false;
+    }
+    if args.get_flag("include-generated") {
+        config.files.skip_generated = false;
+    }
+    config.validate()?;
+    Ok(config)
+}
+
+pub fn print_config(config: &Config, args: &ArgMatches) -> i32 {
+    if !args.get_flag("print") {
+        eprintln!("nothing to do, try --print");
+        return 2;
+    }
+    print!("{}", config.to_toml());
+    0
+}
+
+fn max_nodes_arg() -> Arg {
+    Arg::new("max-nodes")
+        .long("max-nodes")
+        .value_parser(clap::value_parser!(usize))
+        .default_value("50")
+        .help("Drop functions beyond this many, noting it in the diagram")
+}
+
+fn selection_args() -> [Arg; 6] {
+    [
+        Arg::new("function")
+            .long("function")
+            .help("Root function of the exported tree, defaults to the whole graph"),
+        Arg::new("to")
+            .long("to")
+            .requires("function")
+            .help("Export only call paths from --function to this function"),
+        Arg::new("depth")
+            .long("depth")
+            .value_parser(clap::value_parser!(i32))
+            .help("Depth of the tree, defaults to depth in graphgen.toml or 4"),
+        Arg::new("filter")
+            .long("filter")
+            .conflicts_with("function")
+            .help("Glob of qualified function names to keep"),
+        Arg::new("package")
+            .long("package")
+            .conflicts_with_all(["function", "filter"])
+            .help("Keep the functions of this workspace package or root"),
+        hide_arg(),
+    ]
+}
+
+fn hide_arg() -> Arg {
+    Arg::new("hide")
+        .long("hide")
+        .value_parser(CallKind::parse_list)
+        .help("Leave out callees of these kinds, e.g. external,builtin")
+}
+
+fn selection(args: &ArgMatches, config: &Config) -> Selection {
+    let depth = args
+        .get_one::<i32>("depth")
+        .copied()
+        .unwrap_or(config.depth);
+    match (
+        args.get_one::<String>("function"),
+        args.get_one::<String>("filter"),
+    ) {
+        (Some(from), _) if args.contains_id("to") => Selection::Path {
+            from: from.clone(),
+            to: args.get_one::<String>("to").unwrap().clone(),
+            depth,
+        },
+        (Some(root), _) => Selection::Tree {
+            root: root.clone(),
+            depth,
+        },
+        (None, Some(pattern)) => Selection::Matching(pattern.clone()),
+        (None, None) => match args.get_one::<String>("package") {
+            Some(package) => Selection::Package(package.clone()),
+            None => Selection::All,
+        },
+    }
+}
+
+fn output_arg() -> Arg {
+    Arg::new("output")
+        .long("output")
+        .short('o')
+        .help("Output file, defaults to stdout")
+}
+
+fn write_output(args: &ArgMatches, text: &str) -> i32 {
+    match args.get_one::<String>("output") {
+        Some(path) => match std::fs::write(path, text) {
+            Ok(_) => 0,
+            Err(e) => {
+                eprintln!("failed to write {}: {}", path, e);
+                1
+            }
+        },
+        None => {
+            print!("{}", text);
+            0
+        }
+    }
+}
+
+fn diff_args() -> [Arg; 3] {
+    [
+        Arg::new("diff")
+            .long("diff")
+            .help("Unified diff file, or - for stdin")
+            .conflicts_with("base"),
+        Arg::new("base")
+            .long("base")
+            .help("Git revision to diff from, read from --project-dir"),
+        Arg::new("head")
+            .long("head")
+            .requires("base")
+            .help("Git revision to diff to, defaults to the working tree"),
+    ]
+}
+
+/// `--project-dir`, or else the project directory the index was built from.
+fn project_dir<'a>(args: &'a ArgMatches, code_index: &'a CodeIndex) -> Option<&'a str> {
+    match args.get_one::<String>("project-dir") {
+        Some(dir) => Some(dir.as_str()),
+        None => code_index
+            .roots()
+            .first()
+            .copied()
+            .filter(|r| !r.is_empty()),
+    }
+}
+
+/// The diff selected by `--diff` or `--base`/`--head`, if any. An
+/// `InvalidInput` error is a usage error.
+fn read_diff(args: &ArgMatches, code_index: &CodeIndex) -> std::io::Result<Option<String>> {
+    match (
+        args.get_one::<String>("diff"),
+        args.get_one::<String>("base"),
+    ) {
+        (Some(path), _) => read_input(path).map(Some),
+        (None, Some(base)) => {
+            let repo = project_dir(args, code_index).ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "--base needs --project-dir",
+                )
+            })?;
+            let head = args.get_one::<String>("head").map_or("", |h| h.as_str());
+            git_diff(repo, base, head).map(Some)
+        }
+        (None, None) => Ok(None),
+    }
+}
+
+fn read_input(path: &str) -> std::io::Result<String> {
+    if path == "-" {
+        let mut buf = String::new();
+        std::io::stdin().read_to_string(&mut buf)?;
+        return Ok(buf);
+    }
+    std::fs::read_to_string(path)
+}
+
+/// The selected subgraph, without the callees `--hide` names.
+fn subgraph(
+    code_index: &CodeIndex,
+    config: &Config,
+    args: &ArgMatches,
+    metrics: Option<&Metrics>,
+) -> Result<Subgraph, String> {
+    let mut graph = code_index
+        .subgraph(&selection(args, config), metrics)
+        .map_err(|e| e.to_string())?;
+    if let Some(kinds) = args.get_one::<Vec<CallKind>>("hide") {
+        code_index.hide_callees(&mut graph, kinds);
+    }
+    Ok(graph)
+}
+
+/// Runs a subcommand against the parsed index and returns the process exit code.
+pub fn run(name: &str, code_index: &CodeIndex, config: &Config, args: &ArgMatches) -> i32 {
+    match name {
+        "deadcode" => deadcode(code_index, config, args),
+        "metrics" => metrics(code_index, args),
+        "impact" => impact(code_index, args),
+        "tests" => tests(code_index, args),
+        "check" => check(code_index, config, args),
+        "dot" => dot(code_index, config, args),
+        "mermaid" => diagram(code_index, config, args, to_mermaid),
+        "plantuml" => diagram(code_index, config, args, to_plantuml),
+        "export" => export(code_index, config, args),
+        "folded" => folded(code_index, args),
+        "render" => render(code_index, config, args),
+        "codeintel" => codeintel(code_index, args),
+        "save" => save(code_index, config, args),
+        "sqlite" => sqlite(code_index, args),
+        "neo4j" => neo4j(code_index, args),
+        "files" => files(code_index, args),
+        "packages" => packages(code_index, args),
+        "diagnostics" => diagnostics(code_index, args),
+        "callees" => callees(code_index, args),
+        _ => {
+            eprintln!("unknown command {}", name);
+            2
+        }
+    }
+}
+
+fn deadcode(code_index: &CodeIndex, config: &Config, args: &ArgMatches) -> i32 {
+    let defaults = &config.entry_points;
+    let entries = EntryPoints {
+        exported: defaults.exported && !args.get_flag("no-exported"),
+        main: defaults.main && !args.get_flag("no-main"),
+        routes: defaults.routes && !args.get_flag("no-routes"),
+        tests: defaults.tests && !args.get_flag("no-tests"),
+        patterns: args
+            .get_many::<String>("entry")
+            .map(|v| v.cloned().collect())
+            .unwrap_or_else(|| defaults.patterns.clone()),
+    };
+    let report = match code_index.dead_code(&entries) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("invalid entry pattern: {}", e);
+            return 2;
+        }
+    };
+    if args.get_flag("json") {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        return 0;
+    }
+    for (file, funcs) in report.files.iter() {
+        println!("{}", file);
+        for func in funcs {
+            let confidence = format!("{:?}", func.confidence).to_lowercase();
+            println!(
+                "  {:>6}  {:<40} {:<7} {}",
+                func.line, func.name, confidence, func.reason
+            );
+        }
+    }
+    println!(
+        "{} unreachable, {} reachable from {} entry points",
+        report.total(),
+        report.reachable,
+        report.entries
+    );
+    0
+}
+
+fn metrics(code_index: &CodeIndex, args: &ArgMatches) -> i32 {
+    let sort = match args.get_one::<String>("sort").unwrap().parse::<Metric>() {
+        Ok(sort) => sort,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 2;
+        }
+    };
+    let limit = *args.get_one::<usize>("limit").unwrap();
+    let metrics = code_index.metrics();
+    let rows = metrics.top(sort, limit);
+    if args.get_flag("json") {
+        let rows: Vec<_> = rows
+            .into_iter()
+            .map(|(name, m)| serde_json::json!({ "name": name, "metrics": m }))
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&rows).unwrap());
+        return 0;
+    }
+    println!(
+        "{:<48} {:>6} {:>7} {:>6} {:>6} {:>12} {:>9}",
+        "function", "fan_in", "fan_out", "calls", "reach", "betweenness", "pagerank"
+    );
+    for (name, m) in rows {
+        println!(
+            "{:<48} {:>6} {:>7} {:>6} {:>6} {:>12.1} {:>9.5}",
+            name, m.fan_in, m.fan_out, m.calls, m.reach, m.betweenness, m.pagerank
+        );
+    }
+    0
+}
+
+fn impact(code_index: &CodeIndex, args: &ArgMatches) -> i32 {
+    let diff = match read_diff(args, code_index) {
+        Ok(Some(diff)) => diff,
+        Ok(None) => {
+            eprintln!("either --diff or --base is required");
+            return 2;
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::InvalidInput => {
+            eprintln!("{}", e);
+            return 2;
+        }
+        Err(e) => {
+            eprintln!("failed to read diff: {}", e);
+            return 1;
+        }
+    };
+    let depth = *args.get_one::<usize>("depth").unwrap();
+    let report = code_index.impact(&parse_unified_diff(&diff), depth);
+    match args.get_one::<String>("format").unwrap().as_str() {
+        "json" => println!("{}", serde_json::to_string_pretty(&report).unwrap()),
+        "markdown" => print!("{}", report.to_markdown()),
+        _ => {
+            for f in report.changed.iter().chain(report.affected.iter()) {
+                let via = f.via.as_ref().map(|v| format!("via {}", v));
+                println!(
+                    "{:>3}  {:<40} {}:{} {}",
+                    f.depth,
+                    f.name,
+                    f.file,
+                    f.line,
+                    via.unwrap_or_default()
+                );
+            }
+        }
+    }
+    0
+}
+
+fn tests(code_index: &CodeIndex, args: &ArgMatches) -> i32 {
+    let json = args.get_flag("json");
+    if args.get_flag("coverage") {
+        let coverage = code_index.test_coverage();
+        if json {
+            println!("{}", serde_json::to_string_pretty(&coverage).unwrap());
+            return 0;
+        }
+        for test in coverage {
+            println!("{} ({}:{})", test.test, test.file, test.line);
+            for func in test.reaches {
+                println!("  {}", func);
+            }
+        }
+        return 0;
+    }
+
+    let mut changed: BTreeSet<String> = args
+        .get_many::<String>("changed")
+        .map(|v| v.cloned().collect())
+        .unwrap_or_default();
+    match read_diff(args, code_index) {
+        Ok(Some(diff)) => {
+            let report = code_index.impact(&parse_unified_diff(&diff), 0);
+            changed.extend(report.changed.into_iter().map(|f| f.name));
+        }
+        Ok(None) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::InvalidInput => {
+            eprintln!("{}", e);
+            return 2;
+        }
+        Err(e) => {
+            eprintln!("failed to read diff: {}", e);
+            return 1;
+        }
+    }
+    let selected = code_index.select_tests(&changed);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&selected).unwrap());
+        return 0;
+    }
+    for test in selected {
+        println!("{}:{}\t{}", test.file, test.line, test.test);
+    }
+    0
+}
+
+fn read_rules(path: &str) -> Result<RuleSet, String> {
+    match read_input(path).map(|text| RuleSet::from_toml(&text)) {
+        Ok(Ok(rules)) => Ok(rules),
+        Ok(Err(e)) => Err(format!("invalid rules file {}: {}", path, e)),
+        Err(e) => Err(format!("failed to read rules file {}: {}", path, e)),
+    }
+}
+
+fn check(code_index: &CodeIndex, config: &Config, args: &ArgMatches) -> i32 {
+    let (path, rules) = match args.get_one::<String>("rules") {
+        Some(path) => (path.clone(), read_rules(path)),
+        None if !config.rules.is_empty() => (CONFIG_FILE.to_string(), Ok(config.rule_set())),
+        None => {
+            let project_dir = match project_dir(args, code_index) {
+                Some(dir) => dir,
+                None => {
+                    eprintln!("check needs --rules, [[rule]] in graphgen.toml or --project-dir");
+                    return 2;
+                }
+            };
+            let path = format!("{}/graphgen-rules.toml", project_dir);
+            let rules = read_rules(&path);
+            (path, rules)
+        }
+    };
+    let rules = match rules {
+        Ok(rules) => rules,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 2;
+        }
+    };
+    let violations = match code_index.check_rules(&rules) {
+        Ok(violations) => violations,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            return 2;
+        }
+    };
+    if args.get_flag("json") {
+        println!("{}", serde_json::to_string_pretty(&violations).unwrap());
+    } else {
+        for v in violations.iter() {
+            println!(
+                "{}:{}: {} -> {} ({}) violates \"{}\"",
+                v.file, v.line, v.caller, v.callee, v.callee_file, v.rule
+            );
+        }
+        println!(
+            "{} violations of {} rules",
+            violations.len(),
+            rules.rules.len()
+        );
+    }
+    if violations.is_empty() {
+        0
+    } else {
+        1
+    }
+}
+
+fn dot(code_index: &CodeIndex, config: &Config, args: &ArgMatches) -> i32 {
+    let cluster: Cluster = args.get_one::<String>("cluster").unwrap().parse().unwrap();
+    let metrics = args.get_flag("metrics").then(|| code_index.metrics());
+    match subgraph(code_index, config, args, metrics.as_ref()) {
+        Ok(graph) => write_output(args, &to_dot(&graph, cluster)),
+        Err(e) => {
+            eprintln!("invalid filter: {}", e);
+            2
+        }
+    }
+}
+
+fn diagram(
+    code_index: &CodeIndex,
+    config: &Config,
+    args: &ArgMatches,
+    render: fn(&Subgraph) -> String,
+) -> i32 {
+    match subgraph(code_index, config, args, None) {
+        Ok(mut graph) => {
+            graph.truncate(*args.get_one::<usize>("max-nodes").unwrap());
+            write_output(args, &render(&graph))
+        }
+        Err(e) => {
+            eprintln!("invalid filter: {}", e);
+            2
+        }
+    }
+}
+
+fn export(code_index: &CodeIndex, config: &Config, args: &ArgMatches) -> i32 {
+    let metrics = args.get_flag("metrics").then(|| code_index.metrics());
+    let graph = match subgraph(code_index, config, args, metrics.as_ref()) {
+        Ok(graph) => graph,
+        Err(e) => {
+            eprintln!("invalid filter: {}", e);
+            return 2;
+        }
+    };
+    let text = match args.get_one::<String>("format").unwrap().as_str() {
+        "graphml" => to_graphml(&graph),
+        "gexf" => to_gexf(&graph),
+        _ => serde_json::to_string_pretty(&to_jgf(&graph)).unwrap(),
+    };
+    write_output(args, &text)
+}
+
+fn folded(code_index: &CodeIndex, args: &ArgMatches) -> i32 {
+    let weight = match args
+        .get_one::<String>("weight")
+        .unwrap()
+        .parse::<FoldedWeight>()
+    {
+        Ok(weight) => weight,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 2;
+        }
+    };
+    let root = args.get_one::<String>("function").unwrap();
+    let depth = *args.get_one::<i32>("depth").unwrap();
+    let hidden = args
+        .get_one::<Vec<CallKind>>("hide")
+        .map_or(&[][..], |kinds| kinds.as_slice());
+    let stacks = match code_index.folded_stacks(root, depth, weight, None, hidden) {
+        Some(stacks) => stacks,
+        None => {
+            eprintln!("function {} not found", root);
+            return 2;
+        }
+    };
+    if stacks.truncated {
+        eprintln!("stopped after {} stacks, lower --depth", MAX_STACKS);
+    }
+    write_output(args, &stacks.to_text())
+}
+
+fn render(code_index: &CodeIndex, config: &Config, args: &ArgMatches) -> i32 {
+    let png = match args.get_one::<String>("format") {
+        Some(format) => format == "png",
+        None => args
+            .get_one::<String>("output")
+            .is_some_and(|path| path.ends_with(".png")),
+    };
+    let svg = match subgraph(code_index, config, args, None) {
+        Ok(mut graph) => {
+            graph.truncate(*args.get_one::<usize>("max-nodes").unwrap());
+            to_svg(&graph)
+        }
+        Err(e) => {
+            eprintln!("invalid filter: {}", e);
+            return 2;
+        }
+    };
+    if !png {
+        return write_output(args, &svg);
+    }
+    let path = match args.get_one::<String>("output") {
+        Some(path) => path,
+        None => {
+            eprintln!("png output needs --output");
+            return 2;
+        }
+    };
+    write_png(path, &svg)
+}
+
+#[cfg(feature = "png")]
+fn write_png(path: &str, svg: &str) -> i32 {
+    let png = match code_indexing::export::svg::svg_to_png(svg) {
+        Ok(png) => png,
+        Err(e) => {
+            eprintln!("failed to render png: {}", e);
+            return 1;
+        }
+    };
+    match std::fs::write(path, png) {
+        Ok(_) => 0,
+        Err(e) => {
+            eprintln!("failed to write {}: {}", path, e);
+            1
+        }
+    }
+}
+
+#[cfg(not(feature = "png"))]
+fn write_png(_path: &str, _svg: &str) -> i32 {
+    eprintln!("graphgen was built without png support, rebuild with --features png");
+    2
+}
+
+fn codeintel(code_index: &CodeIndex, args: &ArgMatches) -> i32 {
+    let (data, default_path) = match args.get_one::<String>("format").unwrap().as_str() {
+        "lsif" => (to_lsif(code_index).into_bytes(), "dump.lsif"),
+        _ => (to_scip(code_index), "index.scip"),
+    };
+    let path = args
+        .get_one::<String>("output")
+        .map(|s| s.as_str())
+        .unwrap_or(default_path);
+    if let Err(e) = std::fs::write(path, data) {
+        eprintln!("failed to write {}: {}", path, e);
+        return 1;
+    }
+    println!("wrote {}", path);
+    0
+}
+
+fn save(code_index: &CodeIndex, config: &Config, args: &ArgMatches) -> i32 {
+    let out = match args.get_one::<String>("out").or(config.index.as_ref()) {
+        Some(out) => out,
+        None => {
+            eprintln!("--out or index in graphgen.toml is required");
+            return 2;
+        }
+    };
+    let result = match args.get_one::<String>("format").unwrap().as_str() {
+        "mmap" => code_index.into_mapped_file(out),
+        _ => code_index.into_file(out),
+    };
+    match result {
+        Ok(_) => 0,
+        Err(e) => {
+            eprintln!("failed to write {}: {}", out, e);
+            1
+        }
+    }
+}
+
+fn files(code_index: &CodeIndex, args: &ArgMatches) -> i32 {
+    let json = args.get_flag("json");
+    if !args.get_flag("skipped") {
+        let files = code_index.indexed_files();
+        match json {
+            true => println!("{}", serde_json::to_string_pretty(&files).unwrap()),
+            false => files.iter().for_each(|f| println!("{}", f)),
+        }
+        return 0;
+    }
+    let skipped = code_index.skipped();
+    if json {
+        println!("{}", serde_json::to_string_pretty(skipped).unwrap());
+        return 0;
+    }
+    for s in skipped {
+        println!("{}\t{}", s.path, s.reason);
+    }
+    for (reason, count) in summarize(skipped) {
+        eprintln!("{:>6} {}", count, reason);
+    }
+    0
+}
+
+fn diagnostics(code_index: &CodeIndex, args: &ArgMatches) -> i32 {
+    let report = code_index.diagnostics();
+    if args.get_flag("json") {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        return 0;
+    }
+    for file in report.files.iter() {
+        for d in file.diagnostics.iter() {
+            match d.span.line {
+                0 => println!("{}: {}: {}", file.file, d.kind, d.message),
+                line => println!(
+                    "{}:{}:{}: {}: {}",
+                    file.file,
+                    line,
+                    d.span.column + 1,
+                    d.kind,
+                    d.message
+                ),
+            }
+        }
+        if args.get_flag("unresolved") && file.unresolved_callees > 0 {
+            println!(
+                "{}: {} unresolved callees",
+                file.file, file.unresolved_callees
+            );
+        }
+    }
+    eprintln!(
+        "{} files indexed, {} unreadable, {} with errors; {} of {} calls unresolved",
+        report.indexed_files,
+        report.unreadable_files,
+        report.files_with_errors,
+        report.unresolved_callees,
+        report.call_sites
+    );
+    0
+}
+
+fn callees(code_index: &CodeIndex, args: &ArgMatches) -> i32 {
+    let kinds = args
+        .get_one::<Vec<CallKind>>("kind")
+        .cloned()
+        .unwrap_or_default();
+    let mut report = code_index.callee_report(&kinds);
+    report
+        .callees
+        .truncate(*args.get_one::<usize>("limit").unwrap());
+    if args.get_flag("json") {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        return 0;
+    }
+    for c in report.callees.iter() {
+        println!("{}\t{}\t{}", c.calls, c.kind, c.name);
+    }
+    let counts: Vec<String> = CallKind::ALL
+        .iter()
+        .map(|k| format!("{} {}", report.counts.get(k).unwrap_or(&0), k))
+        .collect();
+    eprintln!("{} calls", counts.join(", "));
+    0
+}
+
+fn packages(code_index: &CodeIndex, args: &ArgMatches) -> i32 {
+    let json = args.get_flag("json");
+    let package = args.get_one::<String>("package").map(|p| p.as_str());
+    if !args.get_flag("calls") && package.is_none() {
+        let packages = code_index.packages();
+        if json {
+            println!("{}", serde_json::to_string_pretty(&packages).unwrap());
+            return 0;
+        }
+        for p in packages {
+            println!(
+                "{}\t{} files\t{} functions\t{} classes\t{} out\t{} in",
+                p.name, p.files, p.functions, p.classes, p.outgoing, p.incoming
+            );
+        }
+        return 0;
+    }
+    let calls = code_index.cross_package_calls(package);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&calls).unwrap());
+        return 0;
+    }
+    for c in calls {
+        println!(
+            "{}:{}\t{} ({}) -> {} ({})",
+            c.file, c.line, c.caller, c.caller_package, c.callee, c.callee_package
+        );
+    }
+    0
+}
+
+fn sqlite(code_index: &CodeIndex, args: &ArgMatches) -> i32 {
+    let db = args.get_one::<String>("db").unwrap();
+    let result =
+        SqliteStore::open(db).and_then(|mut store| match args.get_many::<String>("update") {
+            Some(files) => {
+                let files: Vec<&str> = files.map(|f| f.as_str()).collect();
+                store.update_files(code_index, &files)
+            }
+            None => store.save(code_index),
+        });
+    match result {
+        Ok(_) => 0,
+        Err(e) => {
+            eprintln!("failed to write {}: {}", db, e);
+            1
+        }
+    }
+}
+
+fn neo4j(code_index: &CodeIndex, args: &ArgMatches) -> i32 {
+    let dir = std::path::Path::new(args.get_one::<String>("out-dir").unwrap());
+    let csv = code_index.neo4j_csv();
+    let mut files: Vec<(String, String)> = csv
+        .iter()
+        .map(|f| (f.name.clone(), f.content.clone()))
+        .collect();
+    files.push(("import.cypher".to_string(), code_index.neo4j_cypher()));
+    let written = std::fs::create_dir_all(dir).and_then(|_| {
+        files
+            .iter()
+            .try_for_each(|(name, content)| std::fs::write(dir.join(name), content))
+    });
+    if let Err(e) = written {
+        eprintln!("failed to write {}: {}", dir.display(), e);
+        return 1;
+    }
+    let mut command = vec!["neo4j-admin database import full".to_string()];
+    for f in csv.iter() {
+        command.push(format!("{}={}", f.kind, dir.join(&f.name).display()));
+    }
+    println!("wrote {} files to {}", files.len(), dir.display());
+    println!("{}", command.join(" \\\n  "));
+    0
+}
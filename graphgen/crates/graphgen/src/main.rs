@@ -1,12 +1,35 @@
+mod cli;
+mod watch;
+
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::Mutex;
 
-use clap::{Arg, Command};
-use code_indexing::graph::GraphNode;
+use clap::{Arg, ArgAction, Command};
+use code_indexing::aggregate::Granularity;
+use code_indexing::callees::CallKind;
+use code_indexing::config::Config;
+use code_indexing::deadcode::EntryPoints;
+use code_indexing::export::dot::{to_dot, Cluster};
+use code_indexing::export::folded::FoldedWeight;
+use code_indexing::export::gexf::to_gexf;
+use code_indexing::export::graphml::to_graphml;
+use code_indexing::export::jgf::to_jgf;
+use code_indexing::export::mermaid::to_mermaid;
+use code_indexing::export::plantuml::to_plantuml;
+#[cfg(feature = "png")]
+use code_indexing::export::svg::svg_to_png;
+use code_indexing::export::svg::to_svg;
+use code_indexing::export::{Selection, Subgraph};
+use code_indexing::impact::{git_diff, parse_unified_diff};
+use code_indexing::metrics::{Metric, Metrics};
+use code_indexing::mmap::{is_mapped_index, MappedIndex};
+use code_indexing::sqlite::{is_sqlite, SqliteStore};
 use code_indexing::CodeIndex;
-use env_logger;
 use http_types::headers::HeaderValue;
 use lazy_static::lazy_static;
-use log::{error, info};
+use log::error;
 use serde::Deserialize;
 
 use tide::prelude::*;
@@ -14,15 +37,124 @@ use tide::security::{CorsMiddleware, Origin};
 use tide::{Request, Response, StatusCode};
 
 struct GlobalSingleton {
+    project_dir: String,
     code_index: CodeIndex,
+    // a memory-mapped index answering tree and function list queries until
+    // another endpoint needs `code_index`, which is then loaded from it.
+    mapped: Option<MappedIndex>,
+    // computed on first use, dropped whenever the index changes.
+    metrics: Option<Metrics>,
+    // graphgen.toml merged with the command line.
+    config: Config,
+}
+
+impl GlobalSingleton {
+    fn set_code_index(&mut self, code_index: CodeIndex) {
+        self.code_index = code_index;
+        self.mapped = None;
+        self.metrics = None;
+    }
+
+    fn set_index(&mut self, index: LoadedIndex) {
+        match index {
+            LoadedIndex::Full(code_index) => self.set_code_index(*code_index),
+            LoadedIndex::Mapped(mapped) => {
+                self.set_code_index(CodeIndex::new());
+                self.mapped = Some(mapped);
+            }
+        }
+    }
+
+    fn materialize(&mut self) {
+        if let Some(mapped) = self.mapped.take() {
+            self.code_index = mapped.to_code_index();
+            self.metrics = None;
+        }
+    }
+
+    fn metrics(&mut self) -> &Metrics {
+        let code_index = &self.code_index;
+        self.metrics.get_or_insert_with(|| code_index.metrics())
+    }
 }
 
 lazy_static! {
     static ref CONTEXT: Mutex<GlobalSingleton> = Mutex::new(GlobalSingleton {
+        project_dir: String::new(),
         code_index: CodeIndex::new(),
+        mapped: None,
+        metrics: None,
+        config: Config::default(),
     });
 }
 
+/// Endpoints served from a memory-mapped index without loading it.
+const MAPPED_ROUTES: [&str; 5] = [
+    "/callgraph/json",
+    "/callgraph/html",
+    "/codeindex/functions",
+    "/codeindex/load",
+    "/codeindex/events",
+];
+
+enum LoadedIndex {
+    Full(Box<CodeIndex>),
+    Mapped(MappedIndex),
+}
+
+impl LoadedIndex {
+    fn into_code_index(self) -> CodeIndex {
+        match self {
+            LoadedIndex::Full(code_index) => *code_index,
+            LoadedIndex::Mapped(mapped) => mapped.to_code_index(),
+        }
+    }
+}
+
+/// Opens a bincode, SQLite or memory-mapped index file.
+fn load_index(file: &str) -> Result<LoadedIndex, String> {
+    if is_sqlite(file) {
+        SqliteStore::open_read_only(file)
+            .and_then(|store| store.load())
+            .map(|index| LoadedIndex::Full(Box::new(index)))
+            .map_err(|e| e.to_string())
+    } else if is_mapped_index(file) {
+        MappedIndex::open(file)
+            .map(LoadedIndex::Mapped)
+            .map_err(|e| e.to_string())
+    } else {
+        CodeIndex::load(file)
+            .map(|index| LoadedIndex::Full(Box::new(index)))
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Parses `project_dir`, re-using what a saved index already has for files
+/// that did not change.
+fn open_project(index: Option<LoadedIndex>, project_dir: &str, config: &Config) -> CodeIndex {
+    let mut code_index = index.map(LoadedIndex::into_code_index).unwrap_or_default();
+    // checked by `main` already.
+    code_index.set_filter(config.files.clone()).unwrap();
+    code_index.set_path_aliases(config.paths.clone());
+    code_index.set_extra_roots(config.roots.clone());
+    if let Err(e) = code_index.parse_project(project_dir) {
+        error!("parse_project error {}", e);
+    }
+    code_index
+}
+
+fn materialize_index<'a>(
+    req: Request<()>,
+    next: tide::Next<'a, ()>,
+) -> Pin<Box<dyn Future<Output = tide::Result> + Send + 'a>> {
+    Box::pin(async move {
+        if !MAPPED_ROUTES.contains(&req.url().path()) {
+            CONTEXT.lock().unwrap().materialize();
+        }
+        Ok(next.run(req).await)
+    })
+}
+
 #[derive(Debug, Deserialize)]
 struct ParseFileReq {
     file: String,
@@ -38,11 +170,127 @@ struct LoadCodeIndexReq {
 struct CallGraphRenderReq {
     function: String,
     depth: i32,
+    metric: Option<String>,
+    // comma separated call kinds to leave out of the tree.
+    hide: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 struct CallGraphHtmlReq {
-    depth: i32,
+    depth: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportReq {
+    // a tree rooted at `function`, paths from `function` to `to`, functions
+    // matching `filter`, the functions of `package`, or everything.
+    function: Option<String>,
+    to: Option<String>,
+    depth: Option<i32>,
+    filter: Option<String>,
+    package: Option<String>,
+    cluster: Option<String>,
+    metrics: Option<bool>,
+    max_nodes: Option<usize>,
+    format: Option<String>,
+    // comma separated call kinds to leave out, e.g. `external,builtin`.
+    hide: Option<String>,
+}
+
+impl ExportReq {
+    fn selection(&self, default_depth: i32) -> Selection {
+        match (&self.function, &self.filter) {
+            (Some(from), _) if self.to.is_some() => Selection::Path {
+                from: from.clone(),
+                to: self.to.clone().unwrap(),
+                depth: self.depth.unwrap_or(default_depth),
+            },
+            (Some(root), _) => Selection::Tree {
+                root: root.clone(),
+                depth: self.depth.unwrap_or(default_depth),
+            },
+            (None, Some(pattern)) => Selection::Matching(pattern.clone()),
+            (None, None) => match &self.package {
+                Some(package) => Selection::Package(package.clone()),
+                None => Selection::All,
+            },
+        }
+    }
+
+    fn hidden(&self) -> Result<Vec<CallKind>, String> {
+        CallKind::parse_list(self.hide.as_deref().unwrap_or_default())
+    }
+
+    fn subgraph(
+        &self,
+        code_index: &CodeIndex,
+        default_depth: i32,
+        metrics: Option<&Metrics>,
+        hidden: &[CallKind],
+    ) -> Result<Subgraph, String> {
+        let mut graph = code_index
+            .subgraph(&self.selection(default_depth), metrics)
+            .map_err(|e| e.to_string())?;
+        code_index.hide_callees(&mut graph, hidden);
+        Ok(graph)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PackageReq {
+    package: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CalleesReq {
+    // comma separated call kinds to list, all of them when missing.
+    kind: Option<String>,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FoldedReq {
+    function: String,
+    depth: Option<i32>,
+    weight: Option<String>,
+    // comma separated call kinds to leave out, e.g. `external,builtin`.
+    hide: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AggregateReq {
+    by: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeadCodeReq {
+    exported: Option<bool>,
+    main: Option<bool>,
+    routes: Option<bool>,
+    tests: Option<bool>,
+    // comma separated globs of qualified function names.
+    patterns: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImpactReq {
+    // either a unified diff, or git revisions of the project directory.
+    diff: Option<String>,
+    base: Option<String>,
+    head: Option<String>,
+    depth: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SelectTestsReq {
+    changed: Option<Vec<String>>,
+    diff: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MetricsReq {
+    sort: Option<String>,
+    limit: Option<usize>,
 }
 
 #[async_std::main]
@@ -51,19 +299,116 @@ async fn main() -> tide::Result<()> {
 
     let args = Command::new("graphgen")
         .arg(Arg::new("listen-addr").long("listen-addr"))
-        .arg(Arg::new("project-dir").long("project-dir"))
+        .arg(Arg::new("project-dir").long("project-dir").global(true))
+        .arg(
+            Arg::new("root")
+                .long("root")
+                .global(true)
+                .action(ArgAction::Append)
+                .help("Another directory to index with --project-dir, e.g. a sibling repository"),
+        )
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .global(true)
+                .help("Configuration file, defaults to graphgen.toml in --project-dir"),
+        )
+        .arg(
+            Arg::new("watch")
+                .long("watch")
+                .action(ArgAction::SetTrue)
+                .help("Re-index changed files under --project-dir while serving"),
+        )
+        .arg(Arg::new("index").long("index").global(true).help(
+            "Load a saved bincode, SQLite or memory-mapped index instead of parsing; \
+                     with --project-dir only files changed since it was saved are re-parsed",
+        ))
+        .arg(
+            Arg::new("jobs")
+                .long("jobs")
+                .short('j')
+                .global(true)
+                .value_parser(clap::value_parser!(usize))
+                .help("Number of files parsed in parallel, defaults to the number of CPUs"),
+        )
+        .args(cli::filter_args())
+        .subcommands(cli::commands())
         .get_matches();
 
-    let addr = args.get_one::<String>("listen-addr").unwrap();
-    let project_dir = args.get_one::<String>("project-dir").unwrap();
+    if let Some(jobs) = args.get_one::<usize>("jobs") {
+        if let Err(e) = rayon::ThreadPoolBuilder::new()
+            .num_threads(*jobs)
+            .build_global()
+        {
+            error!("failed to set --jobs: {}", e);
+        }
+    }
 
-    if let Err(e) = CONTEXT
-        .lock()
-        .unwrap()
-        .code_index
-        .parse_project(project_dir)
+    let config = match cli::config(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("invalid configuration: {}", e);
+            std::process::exit(2);
+        }
+    };
+    if let Some(("config", sub_args)) = args.subcommand() {
+        std::process::exit(cli::print_config(&config, sub_args));
+    }
+
+    let project_dir = match args.get_one::<String>("project-dir") {
+        Some(dir) => dir.clone(),
+        None if args.contains_id("index") => String::new(),
+        None => {
+            eprintln!("--project-dir or --index is required");
+            std::process::exit(2);
+        }
+    };
+    let index = match args.get_one::<String>("index").map(|f| load_index(f)) {
+        Some(Ok(index)) => Some(index),
+        Some(Err(e)) => {
+            eprintln!("failed to load index: {}", e);
+            std::process::exit(1);
+        }
+        None => None,
+    };
+
+    if let Some((name, sub_args)) = args.subcommand() {
+        let code_index = match index {
+            Some(index) if project_dir.is_empty() => index.into_code_index(),
+            index => open_project(index, &project_dir, &config),
+        };
+        std::process::exit(cli::run(name, &code_index, &config, sub_args));
+    }
+
+    let addr = match config.listen_addr.clone() {
+        Some(addr) => addr,
+        None => {
+            eprintln!("--listen-addr or listen_addr in graphgen.toml is required");
+            std::process::exit(2);
+        }
+    };
     {
-        error!("parse_project error {}", e);
+        let mut context = CONTEXT.lock().unwrap();
+        context.project_dir = project_dir.clone();
+        match index {
+            Some(index) if project_dir.is_empty() => context.set_index(index),
+            index => {
+                let code_index = open_project(index, &project_dir, &config);
+                context.set_code_index(code_index);
+            }
+        }
+        context.config = config;
+        if args.get_flag("watch") {
+            if project_dir.is_empty() {
+                eprintln!("--watch requires --project-dir");
+                std::process::exit(2);
+            }
+            context.materialize();
+            if let Err(e) = watch::spawn(context.code_index.clone()) {
+                eprintln!("failed to watch {}", e);
+                std::process::exit(1);
+            }
+        }
     }
 
     let mut app = tide::new();
@@ -72,12 +417,36 @@ async fn main() -> tide::Result<()> {
         .allow_origin(Origin::from("*"))
         .allow_credentials(false);
     app.with(cors);
+    app.with(materialize_index);
 
     app.at("/codeindex/parse/file").post(api_parse_file);
     app.at("/codeindex/load").post(api_load_codeindex);
+    app.at("/codeindex/reindex").post(api_reindex);
+    app.at("/codeindex/events")
+        .get(tide::sse::endpoint(watch::api_events));
     app.at("/callgraph/json").post(api_callgraph_json);
     app.at("/codeindex/functions").get(api_function_list);
     app.at("/callgraph/html").get(api_callgraph_html);
+    app.at("/callgraph/aggregate").get(api_callgraph_aggregate);
+    app.at("/callgraph/dot").get(api_callgraph_dot);
+    app.at("/callgraph/export").get(api_callgraph_export);
+    app.at("/callgraph/mermaid")
+        .get(|req| api_callgraph_diagram(req, to_mermaid, tide::http::mime::PLAIN));
+    app.at("/callgraph/plantuml")
+        .get(|req| api_callgraph_diagram(req, to_plantuml, tide::http::mime::PLAIN));
+    app.at("/callgraph/folded").get(api_callgraph_folded);
+    app.at("/callgraph/svg")
+        .get(|req| api_callgraph_diagram(req, to_svg, tide::http::mime::SVG));
+    #[cfg(feature = "png")]
+    app.at("/callgraph/png").get(api_callgraph_png);
+    app.at("/codeindex/packages").get(api_packages);
+    app.at("/codeindex/diagnostics").get(api_diagnostics);
+    app.at("/codeindex/callees").get(api_callees);
+    app.at("/codeindex/deadcode").get(api_dead_code);
+    app.at("/codeindex/metrics").get(api_metrics);
+    app.at("/codeindex/impact").post(api_impact);
+    app.at("/codeindex/tests").get(api_test_coverage);
+    app.at("/codeindex/tests/select").post(api_select_tests);
     app.listen(addr).await?;
     Ok(())
 }
@@ -88,7 +457,9 @@ async fn api_parse_file(mut req: Request<()>) -> tide::Result {
     match indexing.parse_file(&file) {
         Ok(_) => {
             if load {
-                CONTEXT.lock().unwrap().code_index = indexing.clone();
+                let loaded = indexing.clone();
+                watch::stop();
+                CONTEXT.lock().unwrap().set_code_index(loaded);
             }
             Ok(json!({
                 "code": 200,
@@ -107,6 +478,18 @@ async fn api_parse_file(mut req: Request<()>) -> tide::Result {
 
 async fn api_load_codeindex(mut req: Request<()>) -> tide::Result {
     let LoadCodeIndexReq { file } = req.body_json().await?;
+    let index = match load_index(&file) {
+        Ok(index) => index,
+        Err(e) => {
+            return Ok(json!({
+                "code": 5001,
+                "message": format!("failed to load {}: {}", file, e),
+            })
+            .into())
+        }
+    };
+    watch::stop();
+    CONTEXT.lock().unwrap().set_index(index);
     Ok(json!({
         "code": 200,
         "message": "success",
@@ -114,8 +497,68 @@ async fn api_load_codeindex(mut req: Request<()>) -> tide::Result {
     .into())
 }
 
+async fn api_reindex(_req: Request<()>) -> tide::Result {
+    // the watcher would swap its own copy over ours, so it does the work.
+    if let Some(rx) = watch::reindex() {
+        if let Ok(stats) = rx.recv().await {
+            return Ok(json!({
+                "code": 200,
+                "message": "success",
+                "data": stats,
+            })
+            .into());
+        }
+    }
+    let mut context = CONTEXT.lock().unwrap();
+    if context.project_dir.is_empty() {
+        return Ok(json!({
+            "code": 4003,
+            "message": "no project directory to re-index",
+        })
+        .into());
+    }
+    let project_dir = context.project_dir.clone();
+    let stats = context.code_index.parse_project(&project_dir);
+    context.metrics = None;
+    let stats = match stats {
+        Ok(stats) => {
+            watch::notify(&stats);
+            stats
+        }
+        Err(e) => {
+            return Ok(json!({
+                "code": 5001,
+                "message": format!("failed to re-index {}: {}", project_dir, e),
+            })
+            .into())
+        }
+    };
+    Ok(json!({
+        "code": 200,
+        "message": "success",
+        "data": stats,
+    })
+    .into())
+}
+
 async fn api_function_list(req: Request<()>) -> tide::Result {
-    let result = CONTEXT.lock().unwrap().code_index.function_list();
+    let PackageReq { package } = req.query()?;
+    let context = CONTEXT.lock().unwrap();
+    let result = match (&context.mapped, package) {
+        (Some(mapped), None) => mapped.function_list(),
+        (Some(mapped), Some(package)) => mapped
+            .function_list()
+            .into_iter()
+            .filter(|name| {
+                mapped
+                    .node(name)
+                    .and_then(|node| mapped.function(node))
+                    .is_some_and(|f| f.package == package)
+            })
+            .collect(),
+        (None, None) => context.code_index.function_list(),
+        (None, Some(package)) => context.code_index.package_function_list(&package),
+    };
 
     Ok(json!({
         "code": 200,
@@ -125,13 +568,229 @@ async fn api_function_list(req: Request<()>) -> tide::Result {
     .into())
 }
 
-async fn api_callgraph_json(mut req: Request<()>) -> tide::Result {
-    let CallGraphRenderReq { function, depth } = req.body_json().await?;
-    let result = CONTEXT
+async fn api_diagnostics(_req: Request<()>) -> tide::Result {
+    let report = CONTEXT.lock().unwrap().code_index.diagnostics();
+    Ok(json!({
+        "code": 200,
+        "message": "success",
+        "data": report,
+    })
+    .into())
+}
+
+async fn api_callees(req: Request<()>) -> tide::Result {
+    let query: CalleesReq = req.query()?;
+    let kinds = match CallKind::parse_list(query.kind.as_deref().unwrap_or_default()) {
+        Ok(kinds) => kinds,
+        Err(e) => {
+            return Ok(json!({
+                "code": 4002,
+                "message": e,
+            })
+            .into())
+        }
+    };
+    let mut report = CONTEXT.lock().unwrap().code_index.callee_report(&kinds);
+    report.callees.truncate(query.limit.unwrap_or(20));
+    Ok(json!({
+        "code": 200,
+        "message": "success",
+        "data": report,
+    })
+    .into())
+}
+
+async fn api_packages(req: Request<()>) -> tide::Result {
+    let PackageReq { package } = req.query()?;
+    let context = CONTEXT.lock().unwrap();
+    let packages = context.code_index.packages();
+    let calls = context.code_index.cross_package_calls(package.as_deref());
+    Ok(json!({
+        "code": 200,
+        "message": "success",
+        "data": { "packages": packages, "calls": calls },
+    })
+    .into())
+}
+
+async fn api_dead_code(req: Request<()>) -> tide::Result {
+    let query: DeadCodeReq = req.query()?;
+    let context = CONTEXT.lock().unwrap();
+    let defaults = context.config.entry_points.clone();
+    let entries = EntryPoints {
+        exported: query.exported.unwrap_or(defaults.exported),
+        main: query.main.unwrap_or(defaults.main),
+        routes: query.routes.unwrap_or(defaults.routes),
+        tests: query.tests.unwrap_or(defaults.tests),
+        patterns: query
+            .patterns
+            .map(|p| p.split(',').map(|s| s.trim().to_string()).collect())
+            .unwrap_or(defaults.patterns),
+    };
+    match context.code_index.dead_code(&entries) {
+        Ok(report) => Ok(json!({
+            "code": 200,
+            "message": "success",
+            "data": report,
+        })
+        .into()),
+        Err(e) => Ok(json!({
+            "code": 4001,
+            "message": format!("{} invalid entry pattern", e)
+        })
+        .into()),
+    }
+}
+
+async fn api_metrics(req: Request<()>) -> tide::Result {
+    let MetricsReq { sort, limit } = req.query()?;
+    let sort = match sort.unwrap_or("pagerank".to_string()).parse::<Metric>() {
+        Ok(sort) => sort,
+        Err(e) => {
+            return Ok(json!({
+                "code": 4002,
+                "message": e,
+            })
+            .into())
+        }
+    };
+    let mut context = CONTEXT.lock().unwrap();
+    let rows: Vec<_> = context
+        .metrics()
+        .top(sort, limit.unwrap_or(usize::MAX))
+        .into_iter()
+        .map(|(name, metrics)| json!({ "name": name, "metrics": metrics }))
+        .collect();
+    Ok(json!({
+        "code": 200,
+        "message": "success",
+        "data": rows,
+    })
+    .into())
+}
+
+async fn api_impact(mut req: Request<()>) -> tide::Result {
+    let ImpactReq {
+        diff,
+        base,
+        head,
+        depth,
+    } = req.body_json().await?;
+    let diff = match (diff, base) {
+        (Some(diff), _) => diff,
+        (None, Some(base)) => {
+            let project_dir = CONTEXT.lock().unwrap().project_dir.clone();
+            match git_diff(&project_dir, &base, &head.unwrap_or_default()) {
+                Ok(diff) => diff,
+                Err(e) if e.kind() == std::io::ErrorKind::InvalidInput => {
+                    return Ok(json!({
+                        "code": 4001,
+                        "message": e.to_string(),
+                    })
+                    .into())
+                }
+                Err(e) => {
+                    return Ok(json!({
+                        "code": 5002,
+                        "message": format!("{} Failed to run git diff", e)
+                    })
+                    .into())
+                }
+            }
+        }
+        (None, None) => {
+            return Ok(json!({
+                "code": 4003,
+                "message": "either diff or base is required"
+            })
+            .into())
+        }
+    };
+    let report = CONTEXT
         .lock()
         .unwrap()
         .code_index
-        .serde_tree(&function, depth);
+        .impact(&parse_unified_diff(&diff), depth.unwrap_or(5));
+    Ok(json!({
+        "code": 200,
+        "message": "success",
+        "data": report,
+        "markdown": report.to_markdown(),
+    })
+    .into())
+}
+
+async fn api_test_coverage(_req: Request<()>) -> tide::Result {
+    let result = CONTEXT.lock().unwrap().code_index.test_coverage();
+    Ok(json!({
+        "code": 200,
+        "message": "success",
+        "data": result,
+    })
+    .into())
+}
+
+async fn api_select_tests(mut req: Request<()>) -> tide::Result {
+    let SelectTestsReq { changed, diff } = req.body_json().await?;
+    let context = CONTEXT.lock().unwrap();
+    let mut changed: BTreeSet<String> = changed.unwrap_or_default().into_iter().collect();
+    if let Some(diff) = diff {
+        let report = context.code_index.impact(&parse_unified_diff(&diff), 0);
+        changed.extend(report.changed.into_iter().map(|f| f.name));
+    }
+    let result = context.code_index.select_tests(&changed);
+    Ok(json!({
+        "code": 200,
+        "message": "success",
+        "data": result,
+    })
+    .into())
+}
+
+async fn api_callgraph_json(mut req: Request<()>) -> tide::Result {
+    let CallGraphRenderReq {
+        function,
+        depth,
+        metric,
+        hide,
+    } = req.body_json().await?;
+    let hidden = match CallKind::parse_list(hide.as_deref().unwrap_or_default()) {
+        Ok(hidden) => hidden,
+        Err(e) => {
+            return Ok(json!({
+                "code": 4002,
+                "message": e,
+            })
+            .into())
+        }
+    };
+    let metric = match metric
+        .filter(|m| !m.is_empty())
+        .map(|m| m.parse::<Metric>())
+    {
+        Some(Err(e)) => {
+            return Ok(json!({
+                "code": 4002,
+                "message": e,
+            })
+            .into())
+        }
+        Some(Ok(metric)) => Some(metric),
+        None => None,
+    };
+    let mut context = CONTEXT.lock().unwrap();
+    if metric.is_some() {
+        context.materialize();
+    }
+    let mut result = match &context.mapped {
+        Some(mapped) => mapped.serde_tree_without(&function, depth, &hidden),
+        None => context
+            .code_index
+            .serde_tree_without(&function, depth, &hidden),
+    };
+    if let (Some(graph), Some(metric)) = (result.as_mut(), metric) {
+        context.metrics().annotate(graph, metric);
+    }
     match result {
         None => Ok(json!({
             "code": 300,
@@ -147,8 +806,266 @@ async fn api_callgraph_json(mut req: Request<()>) -> tide::Result {
     }
 }
 
+async fn api_callgraph_aggregate(req: Request<()>) -> tide::Result {
+    let AggregateReq { by } = req.query()?;
+    let by = match by.parse::<Granularity>() {
+        Ok(by) => by,
+        Err(e) => {
+            return Ok(json!({
+                "code": 4002,
+                "message": e,
+            })
+            .into())
+        }
+    };
+    let result = CONTEXT.lock().unwrap().code_index.aggregate(by);
+    Ok(json!({
+        "code": 200,
+        "message": "success",
+        "data": result,
+    })
+    .into())
+}
+
+async fn api_callgraph_dot(req: Request<()>) -> tide::Result {
+    let query: ExportReq = req.query()?;
+    let cluster = match query
+        .cluster
+        .as_deref()
+        .unwrap_or("class")
+        .parse::<Cluster>()
+    {
+        Ok(cluster) => cluster,
+        Err(e) => {
+            return Ok(json!({
+                "code": 4002,
+                "message": e,
+            })
+            .into())
+        }
+    };
+    let hidden = match query.hidden() {
+        Ok(hidden) => hidden,
+        Err(e) => {
+            return Ok(json!({
+                "code": 4002,
+                "message": e,
+            })
+            .into())
+        }
+    };
+    let mut context = CONTEXT.lock().unwrap();
+    let with_metrics = query.metrics.unwrap_or(false);
+    if with_metrics {
+        context.metrics();
+    }
+    let metrics = context.metrics.as_ref().filter(|_| with_metrics);
+    match query.subgraph(&context.code_index, context.config.depth, metrics, &hidden) {
+        Ok(graph) => {
+            let mut res = Response::new(StatusCode::Ok);
+            res.set_body(to_dot(&graph, cluster));
+            res.set_content_type("text/vnd.graphviz");
+            Ok(res)
+        }
+        Err(e) => Ok(json!({
+            "code": 4001,
+            "message": format!("{} invalid filter", e)
+        })
+        .into()),
+    }
+}
+
+async fn api_callgraph_export(req: Request<()>) -> tide::Result {
+    let query: ExportReq = req.query()?;
+    let hidden = match query.hidden() {
+        Ok(hidden) => hidden,
+        Err(e) => {
+            return Ok(json!({
+                "code": 4002,
+                "message": e,
+            })
+            .into())
+        }
+    };
+    let mut context = CONTEXT.lock().unwrap();
+    let with_metrics = query.metrics.unwrap_or(false);
+    if with_metrics {
+        context.metrics();
+    }
+    let metrics = context.metrics.as_ref().filter(|_| with_metrics);
+    let graph = match query.subgraph(&context.code_index, context.config.depth, metrics, &hidden) {
+        Ok(graph) => graph,
+        Err(e) => {
+            return Ok(json!({
+                "code": 4001,
+                "message": format!("{} invalid filter", e)
+            })
+            .into())
+        }
+    };
+    let mut res = Response::new(StatusCode::Ok);
+    match query.format.as_deref().unwrap_or("jgf") {
+        "graphml" => {
+            res.set_body(to_graphml(&graph));
+            res.set_content_type(tide::http::mime::XML);
+        }
+        "gexf" => {
+            res.set_body(to_gexf(&graph));
+            res.set_content_type(tide::http::mime::XML);
+        }
+        "jgf" => {
+            res.set_body(to_jgf(&graph));
+            res.set_content_type(tide::http::mime::JSON);
+        }
+        format => {
+            return Ok(json!({
+                "code": 4002,
+                "message": format!("unknown export format {}", format),
+            })
+            .into())
+        }
+    }
+    Ok(res)
+}
+
+async fn api_callgraph_diagram(
+    req: Request<()>,
+    render: fn(&Subgraph) -> String,
+    mime: tide::http::Mime,
+) -> tide::Result {
+    let query: ExportReq = req.query()?;
+    let hidden = match query.hidden() {
+        Ok(hidden) => hidden,
+        Err(e) => {
+            return Ok(json!({
+                "code": 4002,
+                "message": e,
+            })
+            .into())
+        }
+    };
+    let result = {
+        let context = CONTEXT.lock().unwrap();
+        query.subgraph(&context.code_index, context.config.depth, None, &hidden)
+    };
+    match result {
+        Ok(mut graph) => {
+            graph.truncate(query.max_nodes.unwrap_or(50));
+            let mut res = Response::new(StatusCode::Ok);
+            res.set_body(render(&graph));
+            res.set_content_type(mime);
+            Ok(res)
+        }
+        Err(e) => Ok(json!({
+            "code": 4001,
+            "message": format!("{} invalid filter", e)
+        })
+        .into()),
+    }
+}
+
+#[cfg(feature = "png")]
+async fn api_callgraph_png(req: Request<()>) -> tide::Result {
+    let query: ExportReq = req.query()?;
+    let hidden = match query.hidden() {
+        Ok(hidden) => hidden,
+        Err(e) => {
+            return Ok(json!({
+                "code": 4002,
+                "message": e,
+            })
+            .into())
+        }
+    };
+    let result = {
+        let context = CONTEXT.lock().unwrap();
+        query.subgraph(&context.code_index, context.config.depth, None, &hidden)
+    };
+    let mut graph = match result {
+        Ok(graph) => graph,
+        Err(e) => {
+            return Ok(json!({
+                "code": 4001,
+                "message": format!("{} invalid filter", e)
+            })
+            .into())
+        }
+    };
+    graph.truncate(query.max_nodes.unwrap_or(50));
+    match svg_to_png(&to_svg(&graph)) {
+        Ok(png) => {
+            let mut res = Response::new(StatusCode::Ok);
+            res.set_body(png);
+            res.set_content_type(tide::http::mime::PNG);
+            Ok(res)
+        }
+        Err(e) => Ok(json!({
+            "code": 5003,
+            "message": format!("failed to render png: {}", e)
+        })
+        .into()),
+    }
+}
+
+async fn api_callgraph_folded(req: Request<()>) -> tide::Result {
+    let FoldedReq {
+        function,
+        depth,
+        weight,
+        hide,
+    } = req.query()?;
+    let hidden = match CallKind::parse_list(hide.as_deref().unwrap_or_default()) {
+        Ok(hidden) => hidden,
+        Err(e) => {
+            return Ok(json!({
+                "code": 4002,
+                "message": e,
+            })
+            .into())
+        }
+    };
+    let weight = match weight.as_deref().unwrap_or("paths").parse::<FoldedWeight>() {
+        Ok(weight) => weight,
+        Err(e) => {
+            return Ok(json!({
+                "code": 4002,
+                "message": e,
+            })
+            .into())
+        }
+    };
+    let mut context = CONTEXT.lock().unwrap();
+    if matches!(weight, FoldedWeight::Metric(_)) {
+        context.metrics();
+    }
+    let stacks = context.code_index.folded_stacks(
+        &function,
+        depth.unwrap_or(6),
+        weight,
+        context.metrics.as_ref(),
+        &hidden,
+    );
+    match stacks {
+        Some(stacks) => {
+            let mut res = Response::new(StatusCode::Ok);
+            res.set_body(stacks.to_text());
+            res.set_content_type(tide::http::mime::PLAIN);
+            if stacks.truncated {
+                res.insert_header("x-graphgen-truncated", "true");
+            }
+            Ok(res)
+        }
+        None => Ok(json!({
+            "code": 300,
+            "message": format!("function {} not found", function),
+        })
+        .into()),
+    }
+}
+
 async fn api_callgraph_html(req: Request<()>) -> tide::Result {
     let CallGraphHtmlReq { depth } = req.query()?;
+    let depth = depth.unwrap_or_else(|| CONTEXT.lock().unwrap().config.depth);
     let host = req.local_addr().unwrap();
     let html_content = echart_tree_template()
         .replace("${host}$", host)
@@ -175,24 +1092,130 @@ fn echart_tree_template() -> String {
     
     <body>
         <h2>Choose a function</h2>
+        <select id="viewSelect" name="viewSelect" class="styled-select">
+            <option value="tree">function tree</option>
+            <option value="class">class graph</option>
+            <option value="file">file graph</option>
+            <option value="directory">directory graph</option>
+            <option value="package">package graph</option>
+        </select>
+        <select id="packageSelect" name="packageSelect" class="styled-select">
+            <option value="">all packages</option>
+        </select>
         <input type="text" id="keywordInput" placeholder="Type to filter...">
         <select id="dynamicSelect" name="dynamicSelect" class="styled-select">
             <option value="">Select an option...</option>
         </select>
+        <select id="metricSelect" name="metricSelect" class="styled-select">
+            <option value="">Size by: none</option>
+            <option value="fan_in">fan in</option>
+            <option value="fan_out">fan out</option>
+            <option value="calls">calls</option>
+            <option value="reach">reach</option>
+            <option value="betweenness">betweenness</option>
+            <option value="pagerank">pagerank</option>
+        </select>
+        <label><input type="checkbox" id="hideCheckbox"> hide external/builtin calls</label>
         <div id="f20333b98be84c3497bdb4b930129314" class="chart-container" style="width: 80vw; height: 1000px; "></div>
         <script>
             var chart = echarts.init(
                 document.getElementById('f20333b98be84c3497bdb4b930129314'), 'white', { renderer: 'canvas' });
     
             document.getElementById('dynamicSelect').addEventListener('change', function() {
+                document.getElementById('viewSelect').value = 'tree';
                 draw_function_graph(this.value, ${depth}$); 
             });
+            document.getElementById('metricSelect').addEventListener('change', function() {
+                const func = document.getElementById('dynamicSelect').value;
+                if (func) {
+                    draw_function_graph(func, ${depth}$);
+                }
+            });
+            document.getElementById('hideCheckbox').addEventListener('change', function() {
+                const func = document.getElementById('dynamicSelect').value;
+                if (func) {
+                    draw_function_graph(func, ${depth}$);
+                }
+            });
+
+            document.getElementById('packageSelect').addEventListener('change', load_function_list);
+
+            document.getElementById('viewSelect').addEventListener('change', function() {
+                if (this.value === 'tree') {
+                    chart.clear();
+                    const func = document.getElementById('dynamicSelect').value;
+                    if (func) {
+                        draw_function_graph(func, ${depth}$);
+                    }
+                } else {
+                    draw_aggregate_graph(this.value);
+                }
+            });
+
+            function draw_aggregate_graph(by) {
+                const url = 'http://${host}$/callgraph/aggregate?by=' + by;
+                fetch(url)
+                .then(response => response.json())
+                .then(resp => {
+                    const maxFunctions = Math.max(1, ...resp.data.nodes.map(n => n.functions));
+                    const maxWeight = Math.max(1, ...resp.data.edges.map(e => e.weight));
+                    chart.clear();
+                    chart.setOption({
+                        tooltip: {},
+                        series: [
+                            {
+                                type: 'graph',
+                                layout: 'force',
+                                roam: true,
+                                draggable: true,
+                                force: { repulsion: 400, edgeLength: [80, 240] },
+                                edgeSymbol: ['none', 'arrow'],
+                                label: { show: true, position: 'right' },
+                                data: resp.data.nodes.map(n => ({
+                                    name: n.name,
+                                    value: n.functions,
+                                    symbolSize: 8 + 32 * n.functions / maxFunctions,
+                                    tooltip: { formatter: n.name + '<br/>functions: ' + n.functions + '<br/>internal calls: ' + n.internal_calls }
+                                })),
+                                links: resp.data.edges.map(e => ({
+                                    source: e.source,
+                                    target: e.target,
+                                    value: e.weight,
+                                    lineStyle: { width: 1 + 5 * e.weight / maxWeight, curveness: 0.1 },
+                                    label: { show: true, formatter: '' + e.weight }
+                                }))
+                            }
+                        ]
+                    });
+                })
+                .catch((error) => {
+                    console.error('Error:', error);
+                });
+            }
+
+            function max_metric(node) {
+                let max = node.metric || 0;
+                (node.children || []).forEach(child => { max = Math.max(max, max_metric(child)); });
+                return max;
+            }
+
+            // size and colour nodes relative to the largest metric in the tree.
+            function apply_metric(node, max) {
+                if (node.metric !== undefined && max > 0) {
+                    const ratio = node.metric / max;
+                    node.symbolSize = 6 + 24 * ratio;
+                    node.itemStyle = { color: 'hsl(' + Math.round(210 - 210 * ratio) + ', 80%, 50%)' };
+                }
+                (node.children || []).forEach(child => apply_metric(child, max));
+            }
     
             function draw_function_graph(func, depth) {
                 const url = 'http://${host}$/callgraph/json';
                 const postData = {
                     "function": func,
-                    "depth": depth
+                    "depth": depth,
+                    "metric": document.getElementById('metricSelect').value,
+                    "hide": document.getElementById('hideCheckbox').checked ? "external,builtin" : ""
                 };
                 fetch(url, {
                     method: 'POST',
@@ -203,6 +1226,9 @@ fn echart_tree_template() -> String {
                 })
                 .then(response => response.json())
                 .then(resp => { 
+                    if (resp.data) {
+                        apply_metric(resp.data, max_metric(resp.data));
+                    }
     
                     var option = {
                         tooltip: {
@@ -223,6 +1249,9 @@ fn echart_tree_template() -> String {
                                     verticalAlign: 'middle',
                                     align: 'center',
                                     formatter: function(params) {
+                                        if (params.data.metric !== undefined) {
+                                            return params.data.name + ' (' + +params.data.metric.toFixed(3) + ')';
+                                        }
                                         return  params.data.name + ' (' + params.data.value + ')' ; 
                                         // return '{b|' + params.data.name + ' (' + params.data.value + ')' + '}';
                                     },
@@ -262,8 +1291,34 @@ fn echart_tree_template() -> String {
                 });
             }
      
-            document.addEventListener('DOMContentLoaded', function() { 
-                const url = 'http://${host}$/codeindex/functions';
+            function load_package_list() {
+                fetch('http://${host}$/codeindex/packages')
+                .then(response => response.json())
+                .then(resp => {
+                    const selectElement = document.getElementById('packageSelect');
+                    const selected = selectElement.value;
+                    selectElement.length = 1;
+                    resp.data.packages.forEach(item => {
+                        const option = document.createElement('option');
+                        option.value = item.name;
+                        option.text = item.name + ' (' + item.functions + ')';
+                        selectElement.appendChild(option);
+                    });
+                    if (resp.data.packages.some(p => p.name === selected)) {
+                        selectElement.value = selected;
+                    }
+                })
+                .catch((error) => {
+                    console.error('Error:', error);
+                });
+            }
+
+            function load_function_list() {
+                const packageName = document.getElementById('packageSelect').value;
+                let url = 'http://${host}$/codeindex/functions';
+                if (packageName) {
+                    url += '?package=' + encodeURIComponent(packageName);
+                }
                 fetch(url, {
                     method: 'GET',
                     headers: {
@@ -273,6 +1328,7 @@ fn echart_tree_template() -> String {
                 .then(response => response.json())
                 .then(resp => { 
                     const selectElement = document.getElementById('dynamicSelect');
+                    const selected = selectElement.value;
                     selectElement.innerHTML = '';
                     resp.data.forEach(item => {
                         const option = document.createElement('option');
@@ -280,10 +1336,33 @@ fn echart_tree_template() -> String {
                         option.text = item;
                         selectElement.appendChild(option);
                     });
+                    if (resp.data.includes(selected)) {
+                        selectElement.value = selected;
+                    }
                 })
                 .catch((error) => {
                     console.error('Error:', error);
                 });
+            }
+
+            // redraw whatever is shown when `--watch` picked up a change.
+            function refresh() {
+                load_package_list();
+                load_function_list();
+                const view = document.getElementById('viewSelect').value;
+                const func = document.getElementById('dynamicSelect').value;
+                if (view !== 'tree') {
+                    draw_aggregate_graph(view);
+                } else if (func) {
+                    draw_function_graph(func, ${depth}$);
+                }
+            }
+
+            document.addEventListener('DOMContentLoaded', function() { 
+                load_package_list();
+                load_function_list();
+                new EventSource('http://${host}$/codeindex/events')
+                    .addEventListener('reindex', refresh);
                 const keywordInput = document.getElementById('keywordInput');
                 const optionsSelect = document.getElementById('dynamicSelect');
                 keywordInput.addEventListener('input', function() {
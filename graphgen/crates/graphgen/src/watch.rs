@@ -0,0 +1,177 @@
+//! `--watch`: keeps the server's index in step with the project directory
+//! and tells connected pages when it changed.
+
+use async_std::channel::{unbounded, Receiver, Sender};
+use code_indexing::incremental::ReindexStats;
+use code_indexing::CodeIndex;
+use lazy_static::lazy_static;
+use log::{error, info};
+use notify_debouncer_mini::notify::RecursiveMode;
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult, DebouncedEvent};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tide::Request;
+
+use crate::CONTEXT;
+
+/// How long the project must be quiet before a batch of changes is indexed.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+lazy_static! {
+    // one per open `/codeindex/events` stream.
+    static ref SUBSCRIBERS: Mutex<Vec<Sender<String>>> = Mutex::new(vec![]);
+    static ref WATCHER: Mutex<Option<Watcher>> = Mutex::new(None);
+}
+
+enum Message {
+    Events(DebounceEventResult),
+    // `/codeindex/reindex`, answered with the stats once swapped in.
+    Reindex(Sender<ReindexStats>),
+    Stop,
+}
+
+struct Watcher {
+    tx: std::sync::mpsc::Sender<Message>,
+    // set before anything else replaces the served index, and checked
+    // under the lock before a swap, so a late swap cannot undo it.
+    stopped: Arc<AtomicBool>,
+}
+
+/// Watches the project directory and other roots of `code_index` on a
+/// background thread. Changed files are re-indexed on a copy, which then
+/// replaces the served index, so requests are only held up for the swap.
+pub fn spawn(mut code_index: CodeIndex) -> Result<(), String> {
+    let (tx, rx) = std::sync::mpsc::channel::<Message>();
+    let events = tx.clone();
+    let mut debouncer = new_debouncer(DEBOUNCE, move |result| {
+        let _ = events.send(Message::Events(result));
+    })
+    .map_err(|e| e.to_string())?;
+    for root in code_index.roots() {
+        let dir = std::fs::canonicalize(root).map_err(|e| format!("{}: {}", root, e))?;
+        debouncer
+            .watcher()
+            .watch(&dir, RecursiveMode::Recursive)
+            .map_err(|e| format!("{}: {}", root, e))?;
+    }
+    let stopped = Arc::new(AtomicBool::new(false));
+    if let Some(old) = WATCHER.lock().unwrap().replace(Watcher {
+        tx,
+        stopped: stopped.clone(),
+    }) {
+        old.stop();
+    }
+
+    std::thread::spawn(move || {
+        // dropping the debouncer stops the watch.
+        let _debouncer = debouncer;
+        for message in rx {
+            let (stats, reply) = match message {
+                Message::Events(Ok(events)) => match changed(&mut code_index, &events) {
+                    Some(stats) => (stats, None),
+                    None => continue,
+                },
+                Message::Events(Err(e)) => {
+                    error!("watch error {:?}", e);
+                    continue;
+                }
+                Message::Reindex(reply) => (code_index.reindex(), Some(reply)),
+                Message::Stop => break,
+            };
+            info!("Re-indexed {:?}", stats);
+            let fresh = code_index.clone();
+            let old = {
+                let mut context = CONTEXT.lock().unwrap();
+                if stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+                let old = std::mem::replace(&mut context.code_index, fresh);
+                context.mapped = None;
+                context.metrics = None;
+                old
+            };
+            // freeing a large index takes a while, so not under the lock.
+            drop(old);
+            notify(&stats);
+            if let Some(reply) = reply {
+                let _ = reply.try_send(stats);
+            }
+        }
+        info!("Stopped watching");
+    });
+    Ok(())
+}
+
+// Re-indexes what `events` touched, or returns None when nothing changed.
+fn changed(code_index: &mut CodeIndex, events: &[DebouncedEvent]) -> Option<ReindexStats> {
+    // a created, moved or deleted directory may hold files we got no
+    // event for, an ignore file changes what is indexed and a tsconfig or
+    // package.json where imports go, so look at the whole project again.
+    let rescan = events.iter().any(|e| {
+        let name = e.path.file_name().and_then(|n| n.to_str());
+        matches!(
+            name,
+            Some(".gitignore" | ".ignore" | "package.json" | "pnpm-workspace.yaml")
+        ) || name.is_some_and(|n| n.starts_with("tsconfig") && n.ends_with(".json"))
+            || (e.path.extension().is_none() && (e.path.is_dir() || !e.path.exists()))
+    });
+    let stats = if rescan {
+        code_index.reindex()
+    } else {
+        let files: Vec<String> = events
+            .iter()
+            .filter_map(|e| code_index.project_file_key(&e.path))
+            .collect();
+        if files.is_empty() {
+            return None;
+        }
+        code_index.update_files(files)
+    };
+    // a file failing to read changes the diagnostics.
+    (stats.added + stats.changed + stats.removed + stats.failed > 0).then_some(stats)
+}
+
+impl Watcher {
+    fn stop(self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        let _ = self.tx.send(Message::Stop);
+    }
+}
+
+/// Stops watching, for when the served index is replaced by one that does
+/// not come from the watched project. Call it before installing that index.
+pub fn stop() {
+    if let Some(watcher) = WATCHER.lock().unwrap().take() {
+        watcher.stop();
+    }
+}
+
+/// Has the watcher re-index the whole project and swap it in, so its copy
+/// stays the served index. None when nothing is watched.
+pub fn reindex() -> Option<Receiver<ReindexStats>> {
+    let watcher = WATCHER.lock().unwrap();
+    let (tx, rx) = unbounded();
+    watcher.as_ref()?.tx.send(Message::Reindex(tx)).ok()?;
+    Some(rx)
+}
+
+/// Sends a `reindex` event to every open events stream.
+pub fn notify(stats: &ReindexStats) {
+    let data = serde_json::to_string(stats).unwrap();
+    SUBSCRIBERS
+        .lock()
+        .unwrap()
+        .retain(|s| s.try_send(data.clone()).is_ok());
+}
+
+/// Server-sent events: `reindex`, with the `ReindexStats`, whenever the
+/// index was rebuilt from changed files.
+pub async fn api_events(_req: Request<()>, sender: tide::sse::Sender) -> tide::Result<()> {
+    let (tx, rx) = unbounded();
+    SUBSCRIBERS.lock().unwrap().push(tx);
+    while let Ok(data) = rx.recv().await {
+        sender.send("reindex", data, None).await?;
+    }
+    Ok(())
+}
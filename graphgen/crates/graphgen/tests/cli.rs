@@ -0,0 +1,37 @@
+use code_indexing::CodeIndex;
+use std::process::{Command, Output};
+
+/// Saves an index parsed from sources alone, so it knows no project
+/// directory, and runs graphgen with `--index` and `args`.
+fn run_on_index(name: &str, args: &[&str]) -> Output {
+    let file =
+        std::env::temp_dir().join(format!("graphgen-cli-{}-{}.bin", name, std::process::id()));
+    let file = file.display().to_string();
+    let mut index = CodeIndex::new();
+    index.parse_source("a.ts", "function a() { b(); }\nfunction b() {}");
+    index.into_file(&file).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_graphgen"))
+        .arg("--index")
+        .arg(&file)
+        .args(args)
+        .output()
+        .unwrap();
+    let _ = std::fs::remove_file(&file);
+    output
+}
+
+#[test]
+fn test_impact_base_without_project_dir() {
+    let output = run_on_index("impact", &["impact", "--base", "HEAD"]);
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--base needs --project-dir"), "{}", stderr);
+}
+
+#[test]
+fn test_check_without_project_dir() {
+    let output = run_on_index("check", &["check"]);
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--project-dir"), "{}", stderr);
+}